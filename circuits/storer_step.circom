@@ -0,0 +1,39 @@
+pragma circom 2.1.0;
+
+include "../node_modules/circomlib/circuits/poseidon.circom";
+
+include "./storer.circom";
+
+// One period of a contract as a step of an incremental (Nova) proof. The
+// storage proof itself is private, the step only carries the number of
+// periods proven and a running Poseidon hash of their roots and salts:
+//
+//   step_out[0] = step_in[0] + 1
+//   step_out[1] = Poseidon(step_in[1], root, salt)
+template StorageStep(BLOCK_SIZE, QUERY_LEN, LEVELS, DIGEST_CHUNK) {
+    signal input step_in[2];
+    signal output step_out[2];
+
+    signal input chunks[QUERY_LEN][BLOCK_SIZE];
+    signal input siblings[QUERY_LEN][LEVELS];
+    signal input path[QUERY_LEN];
+    signal input hashes[QUERY_LEN];
+    signal input root;
+    signal input salt;
+
+    component prover = StorageProver(BLOCK_SIZE, QUERY_LEN, LEVELS, DIGEST_CHUNK);
+    prover.chunks <== chunks;
+    prover.siblings <== siblings;
+    prover.path <== path;
+    prover.hashes <== hashes;
+    prover.root <== root;
+    prover.salt <== salt;
+
+    component acc = Poseidon(3);
+    acc.inputs[0] <== step_in[1];
+    acc.inputs[1] <== root;
+    acc.inputs[2] <== salt;
+
+    step_out[0] <== step_in[0] + 1;
+    step_out[1] <== acc.out;
+}
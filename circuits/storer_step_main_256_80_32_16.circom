@@ -0,0 +1,5 @@
+pragma circom 2.1.0;
+
+include "./storer_step.circom";
+
+component main { public [step_in] } = StorageStep(256, 80, 32, 16);
@@ -2,22 +2,287 @@ const EXT_ID_U256_BE* = 51
 
 const EXT_ID_U256_LE* = 50
 
+const ENVELOPE_CIRCUIT_MISMATCH* = 3
+
+const ENVELOPE_INVALID_PROOF* = 5
+
+const ENVELOPE_MALFORMED* = 1
+
+const ENVELOPE_OK* = 0
+
+const ENVELOPE_UNSUPPORTED_VERSION* = 2
+
+const ENVELOPE_VERSION* = 1
+
+const ENVELOPE_VK_MISMATCH* = 4
+
+const JOB_CANCELLED* = 6
+
+const JOB_DONE* = 4
+
+const JOB_FAILED* = 5
+
+const JOB_PROVING* = 2
+
+const JOB_QUEUED* = 0
+
+const JOB_SERIALIZING* = 3
+
+const JOB_WITNESS* = 1
+
+const VK_FORMAT_ARK_COMPRESSED* = 0
+
+const VK_FORMAT_ARK_UNCOMPRESSED* = 1
+
+const VK_FORMAT_EVM_WORDS* = 3
+
+const VK_FORMAT_SNARKJS* = 2
+
 
 type StorageProofs* {.incompleteStruct.} = object
 
+type ProofJob* {.incompleteStruct.} = object
+
+type ProverRegistry* {.incompleteStruct.} = object
+
+type Verifier* {.incompleteStruct.} = object
+
+type AggregationSrs* {.incompleteStruct.} = object
+
+type PlainProver* {.incompleteStruct.} = object
+
 type Buffer* = object
   data: ptr uint8
   len: uint
 
+## Sizes of a circuit, see `CircuitInfo`
+type CircuitStats* = object
+  num_constraints: uint
+  num_public_outputs: uint
+  num_public_inputs: uint
+  num_private_inputs: uint
+  num_wires: uint
+
 type ProofCtx* = object
   proof: Buffer
   public_inputs: Buffer
+  envelope: Buffer
+
+## Completion callback of a proof job, called from the job's thread
+type ProofJobCallback* = proc(status: int32, user_data: pointer) {.cdecl.}
 
 ## # Safety
 #
 # Use on a valid pointer to ProofCtx or panics
 proc free_proof_ctx*(ctx: ptr ProofCtx) {.importc: "free_proof_ctx".}
 
+## # Safety
+#
+# Use on a valid pointer to StorageProofs
+proc circuit_stats*(prover_ptr: ptr StorageProofs): CircuitStats {.importc: "circuit_stats".}
+
+## # Safety
+#
+# Use on a valid pointer to StorageProofs, returns the full `CircuitInfo`,
+# including the curve and the input signal names and dimensions, as an mpack
+# map. Free the result with `free_buffer`.
+proc circuit_info_mpack*(prover_ptr: ptr StorageProofs): (ptr Buffer) {.importc: "circuit_info_mpack".}
+
+## # Safety
+#
+# Use on a valid pointer to StorageProofs, returns its verifying key in one
+# of the `VK_FORMAT_*` formats or null for an unknown format. Free the
+# result with `free_buffer`.
+proc export_vk*(prover_ptr: ptr StorageProofs, format: uint8): (ptr Buffer) {.importc: "export_vk".}
+
+## # Safety
+#
+# `vk` must hold a verifying key in the `VK_FORMAT_*` `format`, returns a
+# Verifier or null if it can't be read. Free the result with `free_verifier`.
+proc import_verifier*(vk: ptr Buffer, format: uint8): (ptr Verifier) {.importc: "import_verifier".}
+
+## # Safety
+#
+# Use on a valid pointer to StorageProofs, returns a Verifier for its
+# circuit or null if it doesn't prove with Groth16. Free the result with
+# `free_verifier`.
+proc prover_verifier*(prover_ptr: ptr StorageProofs): (ptr Verifier) {.importc: "prover_verifier".}
+
+## # Safety
+#
+# Same as `export_vk`, for a valid pointer to a Verifier
+proc verifier_export_vk*(verifier: ptr Verifier, format: uint8): (ptr Buffer) {.importc: "verifier_export_vk".}
+
+## # Safety
+#
+# Same as `verify`, for a valid pointer to a Verifier
+proc verifier_verify*(verifier: ptr Verifier,
+                      proof: ptr Buffer,
+                      public_inputs: ptr Buffer): bool {.importc: "verifier_verify".}
+
+## # Safety
+#
+# Use on a valid pointer to StorageProofs, returns a fresh proof for the
+# same public inputs as `proof` or null if it can't be read. Free the result
+# with `free_buffer`.
+proc rerandomize*(prover_ptr: ptr StorageProofs,
+                  proof: ptr Buffer): (ptr Buffer) {.importc: "rerandomize".}
+
+## # Safety
+#
+# Same as `rerandomize`, for a valid pointer to a Verifier
+proc verifier_rerandomize*(verifier: ptr Verifier,
+                           proof: ptr Buffer): (ptr Buffer) {.importc: "verifier_rerandomize".}
+
+## # Safety
+#
+# Use on a valid pointer to a Verifier or panics
+proc free_verifier*(verifier: ptr Verifier) {.importc: "free_verifier".}
+
+## # Safety
+#
+# Generates an SRS to aggregate up to `max_proofs` proofs from local
+# randomness, only fit for tests and deployments that trust the caller.
+# Free the result with `free_aggregation_srs`.
+proc aggregation_srs_setup*(max_proofs: uint): (ptr AggregationSrs) {.importc: "aggregation_srs_setup".}
+
+## # Safety
+#
+# `srs` must hold an SRS exported with `aggregation_srs_export`, returns
+# null if it can't be read. Free the result with `free_aggregation_srs`.
+proc aggregation_srs_import*(srs: ptr Buffer): (ptr AggregationSrs) {.importc: "aggregation_srs_import".}
+
+## # Safety
+#
+# Use on a valid pointer to an AggregationSrs. Free the result with
+# `free_buffer`.
+proc aggregation_srs_export*(srs: ptr AggregationSrs): (ptr Buffer) {.importc: "aggregation_srs_export".}
+
+## # Safety
+#
+# `ctxs` must point to `len` valid ProofCtx pointers returned by prove for
+# this prover, returns their aggregate proof or null if they can't be
+# aggregated with `srs`. Free the result with `free_buffer`.
+proc aggregate_proofs*(prover_ptr: ptr StorageProofs,
+                       srs: ptr AggregationSrs,
+                       ctxs: ptr ptr ProofCtx,
+                       len: uint): (ptr Buffer) {.importc: "aggregate_proofs".}
+
+## # Safety
+#
+# `public_inputs` must point to `len` buffers, the public inputs of the
+# aggregated proofs in the order they were passed to `aggregate_proofs`
+proc verify_aggregate*(prover_ptr: ptr StorageProofs,
+                       srs: ptr AggregationSrs,
+                       aggregate: ptr Buffer,
+                       public_inputs: ptr Buffer,
+                       len: uint): bool {.importc: "verify_aggregate".}
+
+## # Safety
+#
+# Same as `verify_aggregate`, for a valid pointer to a Verifier
+proc verifier_verify_aggregate*(verifier: ptr Verifier,
+                                srs: ptr AggregationSrs,
+                                aggregate: ptr Buffer,
+                                public_inputs: ptr Buffer,
+                                len: uint): bool {.importc: "verifier_verify_aggregate".}
+
+## # Safety
+#
+# Use on a valid pointer to an AggregationSrs or panics
+proc free_aggregation_srs*(srs: ptr AggregationSrs) {.importc: "free_aggregation_srs".}
+
+## # Safety
+#
+# Construct a prover of plain, non zero-knowledge, proofs for the
+# `StorageProver` circuit with these parameters. Free it with
+# `free_plain_prover`.
+proc init_plain_prover*(block_size: uint,
+                        query_len: uint,
+                        levels: uint,
+                        digest_chunk: uint): (ptr PlainProver) {.importc: "init_plain_prover".}
+
+## # Safety
+#
+# Same arguments as `prove`, for a valid pointer to a PlainProver. Returns
+# null if the inputs don't satisfy the circuit.
+proc plain_prove*(prover: ptr PlainProver,
+                  chunks: ptr Buffer,
+                  siblings: ptr Buffer,
+                  hashes: ptr Buffer,
+                  path: ptr int32,
+                  path_len: uint,
+                  pubkey: ptr Buffer,
+                  root: ptr Buffer,
+                  salt: ptr Buffer): (ptr ProofCtx) {.importc: "plain_prove".}
+
+## # Safety
+#
+# Same as `verify_storage_proof`, for a valid pointer to a PlainProver
+proc plain_verify_storage_proof*(prover: ptr PlainProver,
+                                 proof: ptr Buffer,
+                                 root: ptr Buffer,
+                                 salt: ptr Buffer): bool {.importc: "plain_verify_storage_proof".}
+
+## # Safety
+#
+# Same as `verify_envelope`, for a valid pointer to a PlainProver
+proc plain_verify_envelope*(prover: ptr PlainProver,
+                            envelope: ptr Buffer): int32 {.importc: "plain_verify_envelope".}
+
+## # Safety
+#
+# Use on a valid pointer to a PlainProver or panics
+proc free_plain_prover*(prover: ptr PlainProver) {.importc: "free_plain_prover".}
+
+## # Safety
+#
+# Construct an empty registry of `StorageProver` circuits, every prover it
+# loads computes proofs on `num_threads` threads or one per core if it's 0
+proc init_prover_registry*(num_threads: uint): (ptr ProverRegistry) {.importc: "init_prover_registry".}
+
+## # Safety
+#
+# Use on a valid pointer to a ProverRegistry, before it's shared with other
+# threads. The files are only loaded when the circuit is first used.
+proc registry_register*(registry: ptr ProverRegistry,
+                        block_size: uint,
+                        query_len: uint,
+                        levels: uint,
+                        digest_chunk: uint,
+                        r1cs: Buffer,
+                        wasm: Buffer,
+                        zkey: ptr Buffer): bool {.importc: "registry_register".}
+
+## # Safety
+#
+# Use on a valid pointer to a ProverRegistry, returns the prover for the
+# circuit or null if it isn't registered. Free it with `free_prover`.
+proc registry_prover*(registry: ptr ProverRegistry,
+                      block_size: uint,
+                      query_len: uint,
+                      levels: uint,
+                      digest_chunk: uint): (ptr StorageProofs) {.importc: "registry_prover".}
+
+## # Safety
+#
+# Use on a valid pointer to a ProverRegistry, proves the mpack encoded
+# `args` with the circuit matching their shape. Returns null if there is no
+# such circuit or proving fails.
+proc registry_prove_mpack*(registry: ptr ProverRegistry,
+                           args: ptr Buffer): (ptr ProofCtx) {.importc: "registry_prove_mpack".}
+
+## # Safety
+#
+# Use on a valid pointer to a ProverRegistry, provers handed out by
+# `registry_prover` stay valid until they're freed themselves
+proc free_prover_registry*(registry: ptr ProverRegistry) {.importc: "free_prover_registry".}
+
+## # Safety
+#
+# Use on a buffer returned by this library, e.g. from `export_wtns_mpack`
+proc free_buffer*(buffer: ptr Buffer) {.importc: "free_buffer".}
+
 ## # Safety
 #
 # Use on a valid pointer to StorageProofs or panics
@@ -25,14 +290,69 @@ proc free_prover*(prover: ptr StorageProofs) {.importc: "free_prover".}
 
 ## # Safety
 #
-# Construct a StorageProofs object
+# Construct a StorageProofs object, proofs are computed on a pool of
+# `num_threads` threads or one per core if it's 0
 proc init_storage_proofs*(r1cs: Buffer,
                           wasm: Buffer,
-                          zkey: ptr Buffer): (ptr StorageProofs) {.importc: "init_storage_proofs".}
+                          zkey: ptr Buffer,
+                          num_threads: uint): (ptr StorageProofs) {.importc: "init_storage_proofs".}
+
+## # Safety
+#
+# Same as `init_storage_proofs`, witnesses are computed natively instead of
+# with the wasm, the `.sym` file has to be next to the r1cs. Returns null if
+# a path isn't utf8 or the prover can't be loaded.
+proc init_storage_proofs_native*(r1cs: Buffer,
+                                 zkey: ptr Buffer,
+                                 num_threads: uint): (ptr StorageProofs) {.importc: "init_storage_proofs_native".}
+
+## # Safety
+#
+# Same as `init_storage_proofs`, but returns null instead of panicking if
+# the zkey wasn't generated for the r1cs. `strict` also compares the
+# coefficients of every constraint, not only the sizes.
+proc init_storage_proofs_checked*(r1cs: Buffer,
+                                  wasm: Buffer,
+                                  zkey: ptr Buffer,
+                                  num_threads: uint,
+                                  strict: bool): (ptr StorageProofs) {.importc: "init_storage_proofs_checked".}
+
+## # Safety
+#
+# Same as `init_storage_proofs`, proving with Marlin instead of Groth16.
+# `srs` holds a universal SRS serialized with ark, if it's null one is
+# generated from local randomness for tests. Returns null if the SRS can't
+# be read or is too small for the circuit.
+proc init_storage_proofs_marlin*(r1cs: Buffer,
+                                 wasm: Buffer,
+                                 srs: ptr Buffer,
+                                 num_threads: uint): (ptr StorageProofs) {.importc: "init_storage_proofs_marlin".}
+
+## # Safety
+#
+# Use on a valid pointer to StorageProofs, resizes its proving pool
+proc set_num_threads*(prover_ptr: ptr StorageProofs,
+                      num_threads: uint): bool {.importc: "set_num_threads".}
+
+## # Safety
+#
+# Use on a valid pointer to StorageProofs, sets how many witnesses can be
+# computed concurrently
+proc set_witness_instances*(prover_ptr: ptr StorageProofs,
+                            instances: uint) {.importc: "set_witness_instances".}
+
+## # Safety
+#
+# Use on a valid pointer to StorageProofs, sets whether proving from inputs,
+# with `prove` or any of the mpack functions, checks their Merkle paths
+# before computing the witness
+proc set_check_paths*(prover_ptr: ptr StorageProofs,
+                      enabled: bool) {.importc: "set_check_paths".}
 
 ## # Safety
 #
-# Use after constructing a StorageProofs object with init
+# Use after constructing a StorageProofs object with init, returns null if
+# an argument can't be read or proving fails
 proc prove*(prover_ptr: ptr StorageProofs,
             chunks: ptr Buffer,
             siblings: ptr Buffer,
@@ -45,13 +365,120 @@ proc prove*(prover_ptr: ptr StorageProofs,
 
 ## # Safety
 #
-# Use after constructing a StorageProofs object with init
+# Use after constructing a StorageProofs object with init, returns null if
+# proving fails
 proc prove_mpack_ext*(prover_ptr: ptr StorageProofs,
                       args: ptr Buffer): (ptr ProofCtx) {.importc: "prove_mpack_ext".}
 
+## # Safety
+#
+# Use after constructing a StorageProofs object with init, `wtns` holds a
+# witness in the iden3 `.wtns` format. Returns null if proving fails.
+proc prove_wtns*(prover_ptr: ptr StorageProofs,
+                 wtns: ptr Buffer): (ptr ProofCtx) {.importc: "prove_wtns".}
+
+## # Safety
+#
+# Use after constructing a StorageProofs object with init, returns the
+# witness for the mpack encoded `args` as `.wtns` or null if it can't be
+# computed. Free the result with `free_buffer`.
+proc export_wtns_mpack*(prover_ptr: ptr StorageProofs,
+                        args: ptr Buffer): (ptr Buffer) {.importc: "export_wtns_mpack".}
+
+## # Safety
+#
+# `args` must point to `len` mpack encoded inputs and `results` to `len`
+# ProofCtx pointers, which are set to the proof for each input or null if
+# proving it failed. Every non-null result must be freed with `free_proof_ctx`.
+proc prove_mpack_many*(prover_ptr: ptr StorageProofs,
+                       args: ptr Buffer,
+                       len: uint,
+                       results: ptr ptr ProofCtx): bool {.importc: "prove_mpack_many".}
+
 ## # Safety
 #
 # Should be called on a valid proof and public inputs previously generated by prove
 proc verify*(prover_ptr: ptr StorageProofs,
              proof: ptr Buffer,
              public_inputs: ptr Buffer): bool {.importc: "verify".}
+
+## # Safety
+#
+# Should be called on a valid proof previously generated by prove, `root`,
+# `salt` and the optional `pubkey` are little-endian U256s the verifier expects
+proc verify_storage_proof*(prover_ptr: ptr StorageProofs,
+                           proof: ptr Buffer,
+                           root: ptr Buffer,
+                           salt: ptr Buffer,
+                           pubkey: ptr Buffer): bool {.importc: "verify_storage_proof".}
+
+## # Safety
+#
+# `proofs` and `public_inputs` must point to `len` buffers each, and `results`
+# to `len` bools, which are set to whether each proof verified. Returns true
+# if all proofs are valid.
+proc verify_batch*(prover_ptr: ptr StorageProofs,
+                   proofs: ptr Buffer,
+                   public_inputs: ptr Buffer,
+                   len: uint,
+                   results: ptr bool): bool {.importc: "verify_batch".}
+
+## # Safety
+#
+# Should be called on a valid envelope previously generated by prove,
+# returns `ENVELOPE_OK` or one of the `ENVELOPE_*` error codes
+proc verify_envelope*(prover_ptr: ptr StorageProofs,
+                      envelope: ptr Buffer): int32 {.importc: "verify_envelope".}
+
+## # Safety
+#
+# Use on a valid pointer to ProofCtx, writes the named public input as a
+# little-endian U256 into `dest`, which must hold 32 bytes
+proc proof_ctx_public_input*(ctx: ptr ProofCtx,
+                             name: ptr Buffer,
+                             dest: ptr uint8): bool {.importc: "proof_ctx_public_input".}
+
+## # Safety
+#
+# Use on a valid pointer to ProofCtx, writes `root` as a little-endian U256
+# into `dest`, which must hold 32 bytes
+proc proof_ctx_root*(ctx: ptr ProofCtx, dest: ptr uint8): bool {.importc: "proof_ctx_root".}
+
+## # Safety
+#
+# Use on a valid pointer to ProofCtx, writes `salt` as a little-endian U256
+# into `dest`, which must hold 32 bytes
+proc proof_ctx_salt*(ctx: ptr ProofCtx, dest: ptr uint8): bool {.importc: "proof_ctx_salt".}
+
+## # Safety
+#
+# Use after constructing a StorageProofs object with init. Starts proving
+# `args` in the background and returns a job handle, `callback` (if any) is
+# called with the final `JOB_*` status from the job's thread. The handle must
+# be released with `free_proof_job`.
+proc submit_proof_job*(prover_ptr: ptr StorageProofs,
+                       args: ptr Buffer,
+                       callback: ProofJobCallback,
+                       user_data: pointer): (ptr ProofJob) {.importc: "submit_proof_job".}
+
+## # Safety
+#
+# Use on a valid pointer to ProofJob, returns one of the `JOB_*` status codes
+proc proof_job_status*(job: ptr ProofJob): int32 {.importc: "proof_job_status".}
+
+## # Safety
+#
+# Use on a valid pointer to ProofJob, the job stops at the next stage
+proc cancel_proof_job*(job: ptr ProofJob) {.importc: "cancel_proof_job".}
+
+## # Safety
+#
+# Use on a valid pointer to ProofJob. Returns the proof of a job that's
+# `JOB_DONE`, or null otherwise. The proof can only be taken once and must
+# be freed with `free_proof_ctx`.
+proc proof_job_result*(job: ptr ProofJob): (ptr ProofCtx) {.importc: "proof_job_result".}
+
+## # Safety
+#
+# Use on a valid pointer to ProofJob, a job that's still running is cancelled
+proc free_proof_job*(job: ptr ProofJob) {.importc: "free_proof_job".}
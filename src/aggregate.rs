@@ -0,0 +1,721 @@
+//! SnarkPack aggregation of Groth16 proofs
+//!
+//! `n` proofs of the same circuit are aggregated into one proof of size and
+//! verification time logarithmic in `n`. With a random `r` derived from
+//! commitments to all proofs, the Groth16 equations combine into
+//!
+//!   prod e(A_i, B_i)^(r^i) = e(alpha, beta)^(sum r^i) * e(sum r^i IC_i, gamma)
+//!     * e(sum r^i C_i, delta)
+//!
+//! The prover shows that the left-hand side and `sum r^i C_i` are computed
+//! from the committed proofs with the TIPP and MIPP arguments, which fold the
+//! vectors in half every round, and opens the folded commitment keys with
+//! KZG.
+//!
+//! The structured reference string holds powers of two secrets `alpha` and
+//! `beta` in both groups. `AggregationSrs::setup` generates one locally, which
+//! is only sound if its secrets are discarded, so production deployments load
+//! one from a ceremony instead.
+
+use std::iter::successors;
+
+use ark_bn254::{
+    Bn254, Fq12, Fr, G1Affine, G1Prepared, G1Projective, G2Affine, G2Prepared, G2Projective,
+};
+use ark_ec::msm::VariableBaseMSM;
+use ark_ec::{AffineCurve, PairingEngine, ProjectiveCurve};
+use ark_ff::{Field, One, PrimeField, UniformRand, Zero};
+use ark_groth16::{Proof, VerifyingKey};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use ark_std::rand::Rng;
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+
+/// A pair of commitments, one for each secret of the SRS
+type GtPair = (Fq12, Fq12);
+
+const TRANSCRIPT_DOMAIN: &[u8] = b"codex-storage-proofs snarkpack v1";
+
+#[derive(Debug, Clone, PartialEq, Eq, CanonicalSerialize, CanonicalDeserialize)]
+pub struct AggregationSrs {
+    /// `alpha^i G1` and `beta^i G1` for `i < 2n`
+    g_alpha: Vec<G1Affine>,
+    g_beta: Vec<G1Affine>,
+    /// `alpha^i G2` and `beta^i G2` for `i < n`
+    h_alpha: Vec<G2Affine>,
+    h_beta: Vec<G2Affine>,
+}
+
+/// The part of the SRS the aggregate verifier needs, whatever the number of
+/// proofs
+#[derive(Debug, Clone, PartialEq, Eq, CanonicalSerialize, CanonicalDeserialize)]
+pub struct AggregationVerifierKey {
+    g: G1Affine,
+    h: G2Affine,
+    g_alpha: G1Affine,
+    g_beta: G1Affine,
+    h_alpha: G2Affine,
+    h_beta: G2Affine,
+}
+
+impl AggregationSrs {
+    /// SRS for up to `max_proofs` proofs from local randomness, for tests and
+    /// private deployments
+    pub fn setup<R: Rng>(max_proofs: usize, rng: &mut R) -> Self {
+        let n = max_proofs.max(2).next_power_of_two();
+        let (alpha, beta) = (Fr::rand(rng), Fr::rand(rng));
+
+        Self {
+            g_alpha: powers_g1(alpha, 2 * n),
+            g_beta: powers_g1(beta, 2 * n),
+            h_alpha: powers_g2(alpha, n),
+            h_beta: powers_g2(beta, n),
+        }
+    }
+
+    /// Largest number of proofs that can be aggregated, a power of two
+    pub fn max_proofs(&self) -> usize {
+        self.h_alpha.len()
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        let mut bytes = Vec::new();
+        self.serialize(&mut bytes).map_err(|e| e.to_string())?;
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let srs = Self::deserialize(bytes).map_err(|e| e.to_string())?;
+        let n = srs.h_alpha.len();
+        if n < 2
+            || !n.is_power_of_two()
+            || srs.h_beta.len() != n
+            || srs.g_alpha.len() != 2 * n
+            || srs.g_beta.len() != 2 * n
+        {
+            return Err("malformed aggregation SRS".to_string());
+        }
+        Ok(srs)
+    }
+
+    pub fn verifier_key(&self) -> AggregationVerifierKey {
+        AggregationVerifierKey {
+            g: self.g_alpha[0],
+            h: self.h_alpha[0],
+            g_alpha: self.g_alpha[1],
+            g_beta: self.g_beta[1],
+            h_alpha: self.h_alpha[1],
+            h_beta: self.h_beta[1],
+        }
+    }
+}
+
+/// Cross commitments of one halving round
+#[derive(Debug, Clone, PartialEq, Eq, CanonicalSerialize, CanonicalDeserialize)]
+struct GipaRound {
+    com_ab_l: GtPair,
+    com_ab_r: GtPair,
+    z_ab_l: Fq12,
+    z_ab_r: Fq12,
+    com_c_l: GtPair,
+    com_c_r: GtPair,
+    z_c_l: G1Affine,
+    z_c_r: G1Affine,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, CanonicalSerialize, CanonicalDeserialize)]
+pub struct AggregateProof {
+    /// Commitments to the `A` and `B`, and to the `C` of all proofs
+    com_ab: GtPair,
+    com_c: GtPair,
+    /// `prod e(A_i, B_i)^(r^i)`
+    ip_ab: Fq12,
+    /// `sum r^i C_i`
+    agg_c: G1Affine,
+    rounds: Vec<GipaRound>,
+    final_a: G1Affine,
+    final_b: G2Affine,
+    final_c: G1Affine,
+    final_vkey: (G2Affine, G2Affine),
+    final_wkey: (G1Affine, G1Affine),
+    /// KZG openings of the final commitment keys
+    vkey_opening: (G2Affine, G2Affine),
+    wkey_opening: (G1Affine, G1Affine),
+}
+
+impl AggregateProof {
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        let mut bytes = Vec::new();
+        self.serialize(&mut bytes).map_err(|e| e.to_string())?;
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        Self::deserialize(bytes).map_err(|e| e.to_string())
+    }
+}
+
+/// Commitment key, one vector for each secret of the SRS
+#[derive(Debug, Clone)]
+struct Key<G> {
+    a: Vec<G>,
+    b: Vec<G>,
+}
+
+impl<G: AffineCurve<ScalarField = Fr>> Key<G> {
+    fn all(&self) -> (&[G], &[G]) {
+        (&self.a, &self.b)
+    }
+
+    fn left(&self) -> (&[G], &[G]) {
+        let half = self.a.len() / 2;
+        (&self.a[..half], &self.b[..half])
+    }
+
+    fn right(&self) -> (&[G], &[G]) {
+        let half = self.a.len() / 2;
+        (&self.a[half..], &self.b[half..])
+    }
+
+    fn fold(&self, x: Fr) -> Self {
+        Self {
+            a: fold(&self.a, x),
+            b: fold(&self.b, x),
+        }
+    }
+
+    fn first(&self) -> (G, G) {
+        (self.a[0], self.b[0])
+    }
+}
+
+/// Fiat-Shamir transcript, every challenge depends on everything appended
+/// before it
+struct Transcript(Sha256);
+
+impl Transcript {
+    /// `count` is the number of proofs before padding, so an aggregate of
+    /// `n` proofs doesn't verify for their padded inputs
+    fn new(vk: &VerifyingKey<Bn254>, count: usize, inputs: &[Vec<Fr>]) -> Self {
+        let mut transcript = Self(Sha256::new());
+        transcript.0.update(TRANSCRIPT_DOMAIN);
+        transcript.append(vk);
+        transcript.append(&(count as u64));
+        inputs.iter().for_each(|inputs| transcript.append(inputs));
+        transcript
+    }
+
+    fn append(&mut self, val: &impl CanonicalSerialize) {
+        val.serialize_uncompressed(&mut self.0).unwrap();
+    }
+
+    fn challenge(&mut self) -> Fr {
+        loop {
+            let digest = self.0.clone().finalize();
+            self.0.update(digest);
+            let challenge = Fr::from_le_bytes_mod_order(&digest);
+            if !challenge.is_zero() {
+                return challenge;
+            }
+        }
+    }
+}
+
+/// Aggregate `proofs` of the circuit of `vk`, each for the public inputs at
+/// the same index of `inputs`
+pub fn aggregate_proofs(
+    srs: &AggregationSrs,
+    vk: &VerifyingKey<Bn254>,
+    proofs: &[Proof<Bn254>],
+    inputs: &[Vec<Fr>],
+) -> Result<AggregateProof, String> {
+    if proofs.is_empty() {
+        return Err("no proofs to aggregate".to_string());
+    }
+    if proofs.len() != inputs.len() {
+        return Err(format!(
+            "{} proofs but {} sets of public inputs",
+            proofs.len(),
+            inputs.len()
+        ));
+    }
+
+    let count = proofs.len();
+    let proofs = padded(proofs);
+    let inputs = padded(inputs);
+    let n = proofs.len();
+    if n > srs.max_proofs() {
+        return Err(format!(
+            "the SRS aggregates up to {} proofs, got {}",
+            srs.max_proofs(),
+            n
+        ));
+    }
+
+    let a: Vec<G1Affine> = proofs.iter().map(|p| p.a).collect();
+    let b: Vec<G2Affine> = proofs.iter().map(|p| p.b).collect();
+    let c: Vec<G1Affine> = proofs.iter().map(|p| p.c).collect();
+    let vkey = Key {
+        a: srs.h_alpha[..n].to_vec(),
+        b: srs.h_beta[..n].to_vec(),
+    };
+    let wkey = Key {
+        a: srs.g_alpha[n..2 * n].to_vec(),
+        b: srs.g_beta[n..2 * n].to_vec(),
+    };
+
+    let com_ab = commit_pair(vkey.all(), wkey.all(), &a, &b);
+    let com_c = commit_single(vkey.all(), &c);
+
+    let mut transcript = Transcript::new(vk, count, &inputs);
+    transcript.append(&com_ab);
+    transcript.append(&com_c);
+    let r = transcript.challenge();
+    let r_powers = powers(r, n);
+    let r_inv_powers = powers(r.inverse().unwrap(), n);
+
+    // e(A_i, r^i B_i) is e(A_i, B_i)^(r^i), and scaling the key of B by r^-i
+    // keeps the commitment the same
+    let b = scale(&b, &r_powers);
+    let wkey = Key {
+        a: scale(&wkey.a, &r_inv_powers),
+        b: scale(&wkey.b, &r_inv_powers),
+    };
+    let ip_ab = pairing_product(&a, &b);
+    let agg_c = msm(&c, &r_powers);
+    transcript.append(&ip_ab);
+    transcript.append(&agg_c);
+
+    let (mut a, mut b, mut c, mut s) = (a, b, c, r_powers);
+    let (mut vkey, mut wkey) = (vkey, wkey);
+    let mut rounds = Vec::new();
+    let mut challenges = Vec::new();
+    while a.len() > 1 {
+        let half = a.len() / 2;
+        let (a_l, a_r) = a.split_at(half);
+        let (b_l, b_r) = b.split_at(half);
+        let (c_l, c_r) = c.split_at(half);
+        let (s_l, s_r) = s.split_at(half);
+
+        let round = GipaRound {
+            com_ab_l: commit_pair(vkey.left(), wkey.right(), a_r, b_l),
+            com_ab_r: commit_pair(vkey.right(), wkey.left(), a_l, b_r),
+            z_ab_l: pairing_product(a_r, b_l),
+            z_ab_r: pairing_product(a_l, b_r),
+            com_c_l: commit_single(vkey.left(), c_r),
+            com_c_r: commit_single(vkey.right(), c_l),
+            z_c_l: msm(c_r, s_l),
+            z_c_r: msm(c_l, s_r),
+        };
+        transcript.append(&round);
+        let x = transcript.challenge();
+        let x_inv = x.inverse().unwrap();
+
+        a = fold(&a, x);
+        b = fold(&b, x_inv);
+        c = fold(&c, x);
+        s = fold_scalars(&s, x_inv);
+        vkey = vkey.fold(x_inv);
+        wkey = wkey.fold(x);
+        rounds.push(round);
+        challenges.push(x);
+    }
+
+    let final_vkey = vkey.first();
+    let final_wkey = wkey.first();
+    transcript.append(&final_vkey);
+    transcript.append(&final_wkey);
+    let z = transcript.challenge();
+
+    let challenges_inv: Vec<Fr> = challenges.iter().map(|x| x.inverse().unwrap()).collect();
+    let v_poly = fold_polynomial(&challenges_inv);
+    let v_quotient = kzg_quotient(&v_poly, z);
+
+    let w_poly = {
+        let r_inv = r.inverse().unwrap();
+        let k = challenges.len();
+        let scaled: Vec<Fr> = challenges
+            .iter()
+            .enumerate()
+            .map(|(j, x)| *x * r_inv.pow([1u64 << (k - 1 - j)]))
+            .collect();
+        let mut poly = vec![Fr::zero(); n];
+        poly.extend(fold_polynomial(&scaled));
+        poly
+    };
+    let w_quotient = kzg_quotient(&w_poly, z);
+
+    Ok(AggregateProof {
+        com_ab,
+        com_c,
+        ip_ab,
+        agg_c,
+        rounds,
+        final_a: a[0],
+        final_b: b[0],
+        final_c: c[0],
+        final_vkey,
+        final_wkey,
+        vkey_opening: (
+            msm(&srs.h_alpha[..v_quotient.len()], &v_quotient),
+            msm(&srs.h_beta[..v_quotient.len()], &v_quotient),
+        ),
+        wkey_opening: (
+            msm(&srs.g_alpha[..w_quotient.len()], &w_quotient),
+            msm(&srs.g_beta[..w_quotient.len()], &w_quotient),
+        ),
+    })
+}
+
+/// Check that `proof` aggregates valid proofs of the circuit of `vk` for
+/// each of `inputs`, in order
+pub fn verify_aggregate(
+    key: &AggregationVerifierKey,
+    vk: &VerifyingKey<Bn254>,
+    inputs: &[Vec<Fr>],
+    proof: &AggregateProof,
+) -> Result<(), String> {
+    if inputs.is_empty() {
+        return Err("no public inputs".to_string());
+    }
+    let num_inputs = vk.gamma_abc_g1.len() - 1;
+    if let Some(i) = inputs.iter().position(|inputs| inputs.len() != num_inputs) {
+        return Err(format!(
+            "expected {} public inputs, proof {} has {}",
+            num_inputs,
+            i,
+            inputs[i].len()
+        ));
+    }
+
+    let count = inputs.len();
+    let inputs = padded(inputs);
+    let n = inputs.len();
+    if proof.rounds.len() != n.trailing_zeros() as usize {
+        return Err(format!("aggregate proof isn't for {} proofs", n));
+    }
+
+    let mut transcript = Transcript::new(vk, count, &inputs);
+    transcript.append(&proof.com_ab);
+    transcript.append(&proof.com_c);
+    let r = transcript.challenge();
+    transcript.append(&proof.ip_ab);
+    transcript.append(&proof.agg_c);
+
+    let (mut com_ab, mut z_ab) = (proof.com_ab, proof.ip_ab);
+    let (mut com_c, mut z_c) = (proof.com_c, proof.agg_c.into_projective());
+    let mut challenges = Vec::new();
+    for round in &proof.rounds {
+        transcript.append(round);
+        let x = transcript.challenge();
+        let (x_repr, x_inv_repr) = (x.into_repr(), x.inverse().unwrap().into_repr());
+
+        let fold_gt = |com: GtPair, l: GtPair, r: GtPair| {
+            (
+                com.0 * l.0.pow(x_repr) * r.0.pow(x_inv_repr),
+                com.1 * l.1.pow(x_repr) * r.1.pow(x_inv_repr),
+            )
+        };
+        com_ab = fold_gt(com_ab, round.com_ab_l, round.com_ab_r);
+        z_ab *= round.z_ab_l.pow(x_repr) * round.z_ab_r.pow(x_inv_repr);
+        com_c = fold_gt(com_c, round.com_c_l, round.com_c_r);
+        z_c += round.z_c_l.mul(x_repr) + round.z_c_r.mul(x_inv_repr);
+        challenges.push(x);
+    }
+
+    transcript.append(&proof.final_vkey);
+    transcript.append(&proof.final_wkey);
+    let z = transcript.challenge();
+
+    // the folded vectors of a single element must reproduce the folded
+    // commitments and inner products
+    let (va, vb) = proof.final_vkey;
+    let (wa, wb) = proof.final_wkey;
+    let (a, b, c) = (proof.final_a, proof.final_b, proof.final_c);
+    let challenges_inv: Vec<Fr> = challenges.iter().map(|x| x.inverse().unwrap()).collect();
+    let s = eval_fold_polynomial(&challenges_inv, r);
+    if commit_pair((&[va], &[vb]), (&[wa], &[wb]), &[a], &[b]) != com_ab
+        || Bn254::pairing(a, b) != z_ab
+        || commit_single((&[va], &[vb]), &[c]) != com_c
+        || c.mul(s.into_repr()) != z_c
+    {
+        return Err("aggregate inner product arguments are invalid".to_string());
+    }
+
+    // the folded keys must be the SRS keys folded with the same challenges
+    let v_eval = eval_fold_polynomial(&challenges_inv, z);
+    let w_eval = eval_fold_polynomial(&challenges, z * r.inverse().unwrap()) * z.pow([n as u64]);
+    let g_z = key.g.mul(z.into_repr());
+    let h_z = key.h.mul(z.into_repr());
+    let open_g2 = |point: G2Affine, opening: G2Affine, g_secret: G1Affine| {
+        let point = point.into_projective() - key.h.mul(v_eval.into_repr());
+        Bn254::pairing(key.g, point) == Bn254::pairing(g_secret.into_projective() - g_z, opening)
+    };
+    let open_g1 = |point: G1Affine, opening: G1Affine, h_secret: G2Affine| {
+        let point = point.into_projective() - key.g.mul(w_eval.into_repr());
+        Bn254::pairing(point, key.h) == Bn254::pairing(opening, h_secret.into_projective() - h_z)
+    };
+    if !open_g2(va, proof.vkey_opening.0, key.g_alpha)
+        || !open_g2(vb, proof.vkey_opening.1, key.g_beta)
+        || !open_g1(wa, proof.wkey_opening.0, key.h_alpha)
+        || !open_g1(wb, proof.wkey_opening.1, key.h_beta)
+    {
+        return Err("aggregate commitment keys are invalid".to_string());
+    }
+
+    // the Groth16 equations of all proofs, combined with the powers of r
+    let r_powers = powers(r, n);
+    let sum_r: Fr = r_powers.iter().sum();
+    let mut scalars = vec![sum_r];
+    scalars.extend((0..num_inputs).map(|j| {
+        r_powers
+            .iter()
+            .zip(&inputs)
+            .map(|(r, inputs)| *r * inputs[j])
+            .sum::<Fr>()
+    }));
+    let acc_inputs = msm(&vk.gamma_abc_g1, &scalars);
+
+    let expected = Bn254::pairing(vk.alpha_g1, vk.beta_g2).pow(sum_r.into_repr())
+        * Bn254::pairing(acc_inputs, vk.gamma_g2)
+        * Bn254::pairing(proof.agg_c, vk.delta_g2);
+    if expected != proof.ip_ab {
+        return Err("aggregated proofs are invalid".to_string());
+    }
+
+    Ok(())
+}
+
+/// `aggregate_proofs` for ark serialized proofs and public inputs, as
+/// returned by `prove`
+pub fn aggregate_bytes(
+    srs: &AggregationSrs,
+    vk: &VerifyingKey<Bn254>,
+    proofs: &[(&[u8], &[u8])],
+) -> Result<Vec<u8>, String> {
+    let mut deserialized = Vec::with_capacity(proofs.len());
+    let mut inputs = Vec::with_capacity(proofs.len());
+    for (proof_bytes, public_inputs) in proofs {
+        deserialized.push(Proof::<Bn254>::deserialize(*proof_bytes).map_err(|e| e.to_string())?);
+        inputs.push(read_inputs(public_inputs)?);
+    }
+
+    aggregate_proofs(srs, vk, &deserialized, &inputs)?.to_bytes()
+}
+
+/// `verify_aggregate` for ark serialized public inputs
+pub fn verify_aggregate_bytes(
+    key: &AggregationVerifierKey,
+    vk: &VerifyingKey<Bn254>,
+    aggregate: &[u8],
+    public_inputs: &[&[u8]],
+) -> Result<(), String> {
+    let proof = AggregateProof::from_bytes(aggregate)?;
+    let inputs = public_inputs
+        .iter()
+        .map(|bytes| read_inputs(bytes))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    verify_aggregate(key, vk, &inputs, &proof)
+}
+
+fn read_inputs(bytes: &[u8]) -> Result<Vec<Fr>, String> {
+    CanonicalDeserialize::deserialize(bytes).map_err(|e| e.to_string())
+}
+
+/// Repeat the last item up to a power of two of at least 2. The repeated
+/// proof is valid whenever the last one is, so this doesn't change what the
+/// aggregate proves.
+fn padded<T: Clone>(items: &[T]) -> Vec<T> {
+    let n = items.len().max(2).next_power_of_two();
+    let last = items[items.len() - 1].clone();
+    let mut items = items.to_vec();
+    items.resize(n, last);
+    items
+}
+
+fn powers(x: Fr, count: usize) -> Vec<Fr> {
+    successors(Some(Fr::one()), |p| Some(*p * x))
+        .take(count)
+        .collect()
+}
+
+fn powers_g1(x: Fr, count: usize) -> Vec<G1Affine> {
+    let g = G1Affine::prime_subgroup_generator();
+    let points: Vec<G1Projective> = powers(x, count).par_iter().map(|p| g.mul(*p)).collect();
+    G1Projective::batch_normalization_into_affine(&points)
+}
+
+fn powers_g2(x: Fr, count: usize) -> Vec<G2Affine> {
+    let h = G2Affine::prime_subgroup_generator();
+    let points: Vec<G2Projective> = powers(x, count).par_iter().map(|p| h.mul(*p)).collect();
+    G2Projective::batch_normalization_into_affine(&points)
+}
+
+/// `points[i] * scalars[i]`
+fn scale<G: AffineCurve<ScalarField = Fr>>(points: &[G], scalars: &[Fr]) -> Vec<G> {
+    let points: Vec<G::Projective> = points
+        .par_iter()
+        .zip(scalars)
+        .map(|(p, s)| p.mul(*s))
+        .collect();
+    G::Projective::batch_normalization_into_affine(&points)
+}
+
+/// The left half plus `x` times the right half
+fn fold<G: AffineCurve<ScalarField = Fr>>(points: &[G], x: Fr) -> Vec<G> {
+    let (left, right) = points.split_at(points.len() / 2);
+    let x = x.into_repr();
+    let points: Vec<G::Projective> = left
+        .par_iter()
+        .zip(right)
+        .map(|(l, r)| r.mul(x).add_mixed(l))
+        .collect();
+    G::Projective::batch_normalization_into_affine(&points)
+}
+
+fn fold_scalars(scalars: &[Fr], x: Fr) -> Vec<Fr> {
+    let (left, right) = scalars.split_at(scalars.len() / 2);
+    left.iter().zip(right).map(|(l, r)| *l + *r * x).collect()
+}
+
+fn msm<G: AffineCurve<ScalarField = Fr>>(bases: &[G], scalars: &[Fr]) -> G {
+    let scalars: Vec<_> = scalars.iter().map(|s| s.into_repr()).collect();
+    VariableBaseMSM::multi_scalar_mul(bases, &scalars).into_affine()
+}
+
+fn pairing_product(a: &[G1Affine], b: &[G2Affine]) -> Fq12 {
+    let pairs: Vec<(G1Prepared, G2Prepared)> = a
+        .par_iter()
+        .zip(b)
+        .map(|(a, b)| (G1Prepared::from(*a), G2Prepared::from(*b)))
+        .collect();
+    Bn254::product_of_pairings(&pairs)
+}
+
+/// Commitment to `a` with `v` and to `b` with `w`, for both secrets
+fn commit_pair(
+    v: (&[G2Affine], &[G2Affine]),
+    w: (&[G1Affine], &[G1Affine]),
+    a: &[G1Affine],
+    b: &[G2Affine],
+) -> GtPair {
+    let commit = |v: &[G2Affine], w: &[G1Affine]| {
+        let g1s: Vec<G1Affine> = a.iter().chain(w).copied().collect();
+        let g2s: Vec<G2Affine> = v.iter().chain(b).copied().collect();
+        pairing_product(&g1s, &g2s)
+    };
+
+    (commit(v.0, w.0), commit(v.1, w.1))
+}
+
+/// Commitment to `c` with `v`, for both secrets
+fn commit_single(v: (&[G2Affine], &[G2Affine]), c: &[G1Affine]) -> GtPair {
+    (pairing_product(c, v.0), pairing_product(c, v.1))
+}
+
+/// Coefficients of `prod_j (1 + c_j X^(2^(k - j)))` for the `k` challenges
+/// `c_1, ..., c_k` of the rounds, the polynomial whose evaluation at a
+/// secret is the folded key
+fn fold_polynomial(challenges: &[Fr]) -> Vec<Fr> {
+    let mut coeffs = vec![Fr::one()];
+    for c in challenges.iter().rev() {
+        let high: Vec<Fr> = coeffs.iter().map(|coeff| *coeff * c).collect();
+        coeffs.extend(high);
+    }
+    coeffs
+}
+
+fn eval_fold_polynomial(challenges: &[Fr], z: Fr) -> Fr {
+    let mut power = z;
+    let mut eval = Fr::one();
+    for c in challenges.iter().rev() {
+        eval *= Fr::one() + *c * power;
+        power.square_in_place();
+    }
+    eval
+}
+
+/// Coefficients of `(f(X) - f(z)) / (X - z)`
+fn kzg_quotient(coeffs: &[Fr], z: Fr) -> Vec<Fr> {
+    let mut quotient = vec![Fr::zero(); coeffs.len() - 1];
+    let mut acc = Fr::zero();
+    for i in (1..coeffs.len()).rev() {
+        acc = acc * z + coeffs[i];
+        quotient[i - 1] = acc;
+    }
+    quotient
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_bn254::{Bn254, Fr};
+    use ark_circom::circom::R1CS;
+    use ark_groth16::{create_random_proof, generate_random_parameters};
+    use ark_std::rand::{rngs::StdRng, SeedableRng};
+
+    use super::{aggregate_proofs, verify_aggregate, AggregateProof, AggregationSrs};
+    use crate::circuit::StorageCircuit;
+
+    #[test]
+    fn test_aggregate() {
+        let mut rng = StdRng::seed_from_u64(42);
+
+        // x * y = z with public z
+        let one = Fr::from(1u32);
+        let r1cs = R1CS::<Bn254> {
+            num_inputs: 2,
+            num_aux: 2,
+            num_variables: 4,
+            constraints: vec![(vec![(2, one)], vec![(3, one)], vec![(1, one)])],
+            wire_mapping: None,
+        };
+        let params =
+            generate_random_parameters::<Bn254, _, _>(StorageCircuit::setup(&r1cs), &mut rng)
+                .unwrap();
+
+        let (proofs, inputs): (Vec<_>, Vec<_>) = (1..=5u32)
+            .map(|x| {
+                let witness: Vec<Fr> = [1, x * 7, x, 7].into_iter().map(Fr::from).collect();
+                let circuit = StorageCircuit::new(&r1cs, witness.clone());
+                let proof = create_random_proof(circuit, &params, &mut rng).unwrap();
+                (proof, vec![witness[1]])
+            })
+            .unzip();
+
+        let srs = AggregationSrs::setup(8, &mut rng);
+        let key = srs.verifier_key();
+        let small = AggregationSrs::setup(4, &mut rng);
+        assert!(aggregate_proofs(&small, &params.vk, &proofs, &inputs).is_err());
+
+        for count in [1, 2, 5] {
+            let aggregate =
+                aggregate_proofs(&srs, &params.vk, &proofs[..count], &inputs[..count]).unwrap();
+            let bytes = aggregate.to_bytes().unwrap();
+            let aggregate = AggregateProof::from_bytes(&bytes).unwrap();
+            verify_aggregate(&key, &params.vk, &inputs[..count], &aggregate).unwrap();
+        }
+
+        let aggregate = aggregate_proofs(&srs, &params.vk, &proofs, &inputs).unwrap();
+
+        // other inputs, order or number of proofs
+        let mut wrong = inputs.clone();
+        wrong[2][0] += one;
+        assert!(verify_aggregate(&key, &params.vk, &wrong, &aggregate).is_err());
+        let mut swapped = inputs.clone();
+        swapped.swap(0, 1);
+        assert!(verify_aggregate(&key, &params.vk, &swapped, &aggregate).is_err());
+        assert!(verify_aggregate(&key, &params.vk, &inputs[..4], &aggregate).is_err());
+
+        // an invalid proof can't be aggregated into a valid aggregate
+        let mut invalid = proofs.clone();
+        invalid[3].c = proofs[2].c;
+        let aggregate = aggregate_proofs(&srs, &params.vk, &invalid, &inputs).unwrap();
+        assert!(verify_aggregate(&key, &params.vk, &inputs, &aggregate).is_err());
+
+        // nor with an SRS of other secrets
+        let other = AggregationSrs::setup(8, &mut rng).verifier_key();
+        let aggregate = aggregate_proofs(&srs, &params.vk, &proofs, &inputs).unwrap();
+        assert!(verify_aggregate(&other, &params.vk, &inputs, &aggregate).is_err());
+    }
+}
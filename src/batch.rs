@@ -0,0 +1,87 @@
+//! Batch verification of Groth16 proofs
+//!
+//! Each proof satisfies `e(A, B) = e(alpha, beta) * e(IC, gamma) * e(C, delta)`.
+//! Raising every equation to an independent random power `r_i` and multiplying
+//! them together gives a single check
+//!
+//!   prod e(r_i * A_i, B_i) * e(sum r_i * IC_i, -gamma) * e(sum r_i * C_i, -delta)
+//!     = e(alpha, beta)^(sum r_i)
+//!
+//! which costs one multi-pairing for the whole batch instead of one pairing
+//! check per proof. A forged proof only passes with negligible probability.
+
+use ark_ec::{AffineCurve, PairingEngine, ProjectiveCurve};
+use ark_ff::{Field, PrimeField, UniformRand, Zero};
+use ark_groth16::{prepare_inputs, verify_proof, PreparedVerifyingKey, Proof};
+use ark_std::rand::Rng;
+
+/// Check all `proofs` against `pvk` with one randomized multi-pairing
+pub fn batch_verify<E: PairingEngine, R: Rng>(
+    pvk: &PreparedVerifyingKey<E>,
+    proofs: &[(Proof<E>, Vec<E::Fr>)],
+    rng: &mut R,
+) -> bool {
+    if proofs.is_empty() {
+        return true;
+    }
+
+    let mut pairs: Vec<(E::G1Prepared, E::G2Prepared)> = Vec::with_capacity(proofs.len() + 2);
+    let mut inputs_acc = E::G1Projective::zero();
+    let mut c_acc = E::G1Projective::zero();
+    let mut r_sum = E::Fr::zero();
+
+    for (proof, inputs) in proofs {
+        let prepared = match prepare_inputs(pvk, inputs) {
+            Ok(prepared) => prepared,
+            Err(_) => return false,
+        };
+
+        let r = E::Fr::rand(rng);
+        inputs_acc += &prepared.mul(r.into_repr());
+        c_acc += &proof.c.mul(r.into_repr());
+        r_sum += &r;
+
+        pairs.push((proof.a.mul(r.into_repr()).into_affine().into(), proof.b.into()));
+    }
+
+    pairs.push((inputs_acc.into_affine().into(), pvk.gamma_g2_neg_pc.clone()));
+    pairs.push((c_acc.into_affine().into(), pvk.delta_g2_neg_pc.clone()));
+
+    match E::final_exponentiation(&E::miller_loop(pairs.iter())) {
+        Some(res) => res == pvk.alpha_g1_beta_g2.pow(r_sum.into_repr()),
+        None => false,
+    }
+}
+
+/// Indices of the proofs that don't verify on their own
+pub fn find_invalid<E: PairingEngine>(
+    pvk: &PreparedVerifyingKey<E>,
+    proofs: &[(Proof<E>, Vec<E::Fr>)],
+) -> Vec<usize> {
+    proofs
+        .iter()
+        .enumerate()
+        .filter(|(_, (proof, inputs))| !matches!(verify_proof(pvk, proof, inputs), Ok(true)))
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Batch verify `proofs`, falling back to per-proof checks to find the
+/// culprits when the batch fails
+pub fn verify_batch<E: PairingEngine, R: Rng>(
+    pvk: &PreparedVerifyingKey<E>,
+    proofs: &[(Proof<E>, Vec<E::Fr>)],
+    rng: &mut R,
+) -> Result<(), Vec<usize>> {
+    if batch_verify(pvk, proofs, rng) {
+        return Ok(());
+    }
+
+    let invalid = find_invalid(pvk, proofs);
+    if invalid.is_empty() {
+        // every proof checks out on its own
+        return Ok(());
+    }
+
+    Err(invalid)
+}
@@ -0,0 +1,751 @@
+//! Command line tool to set up, prove, verify and inspect storage proofs
+//! without a Nim or Rust harness
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::process::exit;
+
+use ark_bn254::Bn254;
+use ark_circom::circom::{R1CSFile, R1CS};
+use ark_circom::read_zkey;
+use ark_groth16::{Proof, ProvingKey};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use ark_std::rand::rngs::ThreadRng;
+use rmpv::encode::write_value;
+use rmpv::Value;
+use rs_poseidon::poseidon::hash;
+use ruint::aliases::U256;
+use serde_json::json;
+
+use codex_storage_proofs::ceremony::{Beacon, Phase2};
+use codex_storage_proofs::circuit_info::CircuitInfo;
+use codex_storage_proofs::envelope::{vk_hash, ProofEnvelope};
+use codex_storage_proofs::merkle::{digest, MerkleTree};
+use codex_storage_proofs::ptau::PowersOfTau;
+use codex_storage_proofs::registry::CircuitParams;
+use codex_storage_proofs::storage_proofs::{parse_mpack_args, StorageProofs, EXT_ID_U256_LE};
+use codex_storage_proofs::verifier::{Verifier, VkFormat};
+use codex_storage_proofs::witness::{witness_inputs_from_json, WitnessBackend, WitnessInputs};
+use codex_storage_proofs::wtns::read_wtns;
+use codex_storage_proofs::zkey::ZkeyCheck;
+
+const USAGE: &str = "\
+usage: codex-proofs <command> [options]
+
+commands:
+  setup     --r1cs FILE --out FILE [--vk FILE] [--zkey FILE]
+            generate dev parameters, not for production use
+  prove     --r1cs FILE --wasm FILE (--zkey FILE | --params FILE)
+            --input FILE --proof FILE --public FILE [--envelope FILE]
+            prove an .mpack or .json input file
+  verify    (--zkey FILE | --vk FILE [--vk-format FORMAT]) --proof FILE
+            --public FILE
+            FORMAT is ark (default), ark-uncompressed, snarkjs or evm
+  phase2-new        --r1cs FILE --ptau FILE --out FILE
+                    start a phase 2 ceremony from a .ptau
+  phase2-contribute --in FILE --out FILE [--name NAME]
+  phase2-beacon     --in FILE --out FILE --hash HEX --iterations-exp N
+  phase2-verify     --r1cs FILE --ptau FILE --in FILE
+  phase2-export     --r1cs FILE --in FILE --zkey FILE
+  inspect   FILE
+            describe an r1cs, zkey, ptau, wtns, envelope, input or proof file
+  gen-input --data FILE --block-size N --query-len N --levels N
+            --digest-chunk N --salt N --out FILE
+            build .mpack or .json inputs for the StorageProver circuit
+";
+
+/// Bytes per symbol, the most that always fits a bn254 field element
+const SYMBOL_SIZE: usize = 31;
+
+struct Args {
+    positional: Vec<String>,
+    options: HashMap<String, String>,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut positional = Vec::new();
+        let mut options = HashMap::new();
+
+        while let Some(arg) = args.next() {
+            match arg.strip_prefix("--") {
+                Some(name) => {
+                    let val = args
+                        .next()
+                        .ok_or_else(|| format!("missing value for --{}", name))?;
+                    options.insert(name.to_string(), val);
+                }
+                None => positional.push(arg),
+            }
+        }
+
+        Ok(Self {
+            positional,
+            options,
+        })
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.options.get(name).map(|s| s.as_str())
+    }
+
+    fn required(&self, name: &str) -> Result<&str, String> {
+        self.get(name).ok_or_else(|| format!("missing --{}", name))
+    }
+
+    fn number(&self, name: &str) -> Result<usize, String> {
+        self.required(name)?
+            .parse()
+            .map_err(|e| format!("bad --{}: {}", name, e))
+    }
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let command = args.next().unwrap_or_default();
+
+    let result = Args::parse(args).and_then(|args| match command.as_str() {
+        "setup" => setup(&args),
+        "prove" => prove(&args),
+        "verify" => verify(&args),
+        "inspect" => inspect(&args),
+        "gen-input" => gen_input(&args),
+        "phase2-new" => phase2_new(&args),
+        "phase2-contribute" => phase2_contribute(&args),
+        "phase2-beacon" => phase2_beacon(&args),
+        "phase2-verify" => phase2_verify(&args),
+        "phase2-export" => phase2_export(&args),
+        "help" | "--help" | "-h" => {
+            print!("{}", USAGE);
+            Ok(())
+        }
+        _ => Err(format!("unknown command {:?}\n\n{}", command, USAGE)),
+    });
+
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        exit(1);
+    }
+}
+
+fn read(path: &str) -> Result<Vec<u8>, String> {
+    std::fs::read(path).map_err(|e| format!("{}: {}", path, e))
+}
+
+fn write(path: &str, bytes: &[u8]) -> Result<(), String> {
+    std::fs::write(path, bytes).map_err(|e| format!("{}: {}", path, e))
+}
+
+fn serialize(val: &impl CanonicalSerialize) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    val.serialize(&mut bytes).map_err(|e| e.to_string())?;
+    Ok(bytes)
+}
+
+fn load_r1cs(path: &str) -> Result<R1CS<Bn254>, String> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+    let r1cs = R1CSFile::<Bn254>::new(file).map_err(|e| e.to_string())?;
+    Ok(r1cs.into())
+}
+
+fn load_zkey(path: &str) -> Result<ProvingKey<Bn254>, String> {
+    let mut file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+    Ok(read_zkey(&mut file).map_err(|e| e.to_string())?.0)
+}
+
+fn load_ptau(path: &str, r1cs: &R1CS<Bn254>) -> Result<PowersOfTau, String> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+    let ptau = PowersOfTau::read_for_circuit(BufReader::new(file), r1cs)?;
+    ptau.verify()?;
+    Ok(ptau)
+}
+
+fn load_phase2(path: &str) -> Result<Phase2, String> {
+    Phase2::read(read(path)?.as_slice())
+}
+
+fn write_phase2(path: &str, phase2: &Phase2) -> Result<(), String> {
+    let mut bytes = Vec::new();
+    phase2.write(&mut bytes)?;
+    write(path, &bytes)
+}
+
+fn setup(args: &Args) -> Result<(), String> {
+    let r1cs = load_r1cs(args.required("r1cs")?)?;
+    let out = args.required("out")?;
+
+    let phase2 = Phase2::dev(&r1cs, &mut ThreadRng::default())?;
+
+    write(out, &serialize(phase2.params())?)?;
+    if let Some(vk) = args.get("vk") {
+        write(vk, &serialize(&phase2.params().vk)?)?;
+    }
+    if let Some(path) = args.get("zkey") {
+        let mut zkey = Vec::new();
+        phase2.write_zkey(&mut zkey, &r1cs)?;
+        write(path, &zkey)?;
+    }
+
+    println!("wrote parameters to {}", out);
+    Ok(())
+}
+
+fn prove(args: &Args) -> Result<(), String> {
+    let r1cs = args.required("r1cs")?.to_string();
+    let wasm = args.required("wasm")?.to_string();
+    let input_path = args.required("input")?;
+    let input = read(input_path)?;
+
+    let backend = WitnessBackend::Wasm(wasm);
+    let prover = match (args.get("zkey"), args.get("params")) {
+        (Some(zkey), None) => {
+            StorageProofs::load(backend, r1cs, Some(zkey.into()), 0, ZkeyCheck::Shape)?
+        }
+        (None, Some(params)) => {
+            let params = ProvingKey::<Bn254>::deserialize(read(params)?.as_slice())
+                .map_err(|e| e.to_string())?;
+            StorageProofs::with_proving_key(backend, r1cs, params, 0)?
+        }
+        _ => return Err("expected one of --zkey or --params".to_string()),
+    };
+
+    let mut proof_bytes = Vec::new();
+    let mut public_inputs_bytes = Vec::new();
+    if input_path.ends_with(".json") {
+        let json = String::from_utf8(input).map_err(|e| e.to_string())?;
+        let inputs = witness_inputs_from_json(&json)?;
+        prover.prove_witness_inputs(inputs, &mut proof_bytes, &mut public_inputs_bytes)?;
+    } else {
+        prover.prove_mpack(&input, &mut proof_bytes, &mut public_inputs_bytes)?;
+    }
+
+    write(args.required("proof")?, &proof_bytes)?;
+    write(args.required("public")?, &public_inputs_bytes)?;
+    if let Some(envelope) = args.get("envelope") {
+        let bytes = prover
+            .envelope(&proof_bytes, &public_inputs_bytes)?
+            .to_bytes()?;
+        write(envelope, &bytes)?;
+    }
+
+    Ok(())
+}
+
+fn verify(args: &Args) -> Result<(), String> {
+    let verifier = match (args.get("zkey"), args.get("vk")) {
+        (Some(zkey), None) => Verifier::new(load_zkey(zkey)?.vk),
+        (None, Some(vk)) => {
+            let format = vk_format(args.get("vk-format").unwrap_or("ark"))?;
+            Verifier::import(&read(vk)?, format)?
+        }
+        _ => return Err("expected one of --zkey or --vk".to_string()),
+    };
+
+    let proof = read(args.required("proof")?)?;
+    let public_inputs = read(args.required("public")?)?;
+    verifier.verify(proof.as_slice(), public_inputs.as_slice())?;
+
+    println!("proof is valid");
+    Ok(())
+}
+
+fn vk_format(name: &str) -> Result<VkFormat, String> {
+    match name {
+        "ark" => Ok(VkFormat::ArkCompressed),
+        "ark-uncompressed" => Ok(VkFormat::ArkUncompressed),
+        "snarkjs" => Ok(VkFormat::Snarkjs),
+        "evm" => Ok(VkFormat::EvmWords),
+        _ => Err(format!("bad --vk-format {:?}", name)),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FileKind {
+    R1cs,
+    Zkey,
+    Ptau,
+    Wtns,
+    Envelope,
+    MpackInputs,
+    JsonInputs,
+    Proof,
+}
+
+/// Binary circuit files are told apart by their magic, the others by trying
+/// to parse them
+fn file_kind(bytes: &[u8]) -> Option<FileKind> {
+    match bytes.get(..4) {
+        Some(b"r1cs") => return Some(FileKind::R1cs),
+        Some(b"zkey") => return Some(FileKind::Zkey),
+        Some(b"ptau") => return Some(FileKind::Ptau),
+        Some(b"wtns") => return Some(FileKind::Wtns),
+        _ => {}
+    }
+
+    if ProofEnvelope::from_bytes(bytes).is_ok() {
+        Some(FileKind::Envelope)
+    } else if parse_mpack_args(&mut WitnessInputs::new(), bytes).is_ok() {
+        Some(FileKind::MpackInputs)
+    } else if json_inputs(bytes).is_ok() {
+        Some(FileKind::JsonInputs)
+    } else if Proof::<Bn254>::deserialize(bytes).is_ok() {
+        Some(FileKind::Proof)
+    } else {
+        None
+    }
+}
+
+fn json_inputs(bytes: &[u8]) -> Result<WitnessInputs, String> {
+    std::str::from_utf8(bytes)
+        .map_err(|e| e.to_string())
+        .and_then(witness_inputs_from_json)
+}
+
+fn inspect(args: &Args) -> Result<(), String> {
+    let path = args.positional.first().ok_or("missing file to inspect")?;
+    let bytes = read(path)?;
+
+    match file_kind(&bytes).ok_or("unknown file format")? {
+        FileKind::R1cs => inspect_r1cs(path),
+        FileKind::Zkey => inspect_zkey(path),
+        FileKind::Ptau => {
+            let power = PowersOfTau::read_power(std::io::Cursor::new(&bytes))?;
+            println!("powers of tau: power {}", power);
+            println!("domain size: {}", 1u64 << power);
+            Ok(())
+        }
+        FileKind::Wtns => {
+            let witness = read_wtns(&bytes)?;
+            println!("witness: {} wires", witness.len());
+            Ok(())
+        }
+        FileKind::Envelope => inspect_envelope(&bytes),
+        FileKind::MpackInputs => {
+            let mut inputs = WitnessInputs::new();
+            parse_mpack_args(&mut inputs, &bytes)?;
+            println!("mpack inputs:");
+            print_inputs(&inputs);
+            Ok(())
+        }
+        FileKind::JsonInputs => {
+            println!("json inputs:");
+            print_inputs(&json_inputs(&bytes)?);
+            Ok(())
+        }
+        FileKind::Proof => {
+            let proof = Proof::<Bn254>::deserialize(bytes.as_slice()).map_err(|e| e.to_string())?;
+            println!("proof: groth16 over bn254");
+            println!("a: {}", proof.a);
+            println!("b: {}", proof.b);
+            println!("c: {}", proof.c);
+            Ok(())
+        }
+    }
+}
+
+fn inspect_r1cs(path: &str) -> Result<(), String> {
+    let info = CircuitInfo::load(path)?;
+
+    println!("r1cs: curve {}", info.curve);
+    println!("constraints: {}", info.num_constraints);
+    println!("wires: {}", info.num_wires);
+    println!("labels: {}", info.num_labels);
+    println!("public outputs: {}", info.num_public_outputs);
+    println!("public inputs: {}", info.num_public_inputs);
+    println!("private inputs: {}", info.num_private_inputs);
+    for input in &info.inputs {
+        println!(
+            "  {} {}{:?}",
+            if input.public { "public " } else { "private" },
+            input.name,
+            input.dims
+        );
+    }
+
+    Ok(())
+}
+
+fn inspect_zkey(path: &str) -> Result<(), String> {
+    let params = load_zkey(path)?;
+
+    println!("zkey: groth16 over bn254");
+    println!("public inputs: {}", params.vk.gamma_abc_g1.len() - 1);
+    println!("wires: {}", params.a_query.len());
+    println!("vk hash: {}", hex(&vk_hash(&params.vk)?));
+    Ok(())
+}
+
+fn inspect_envelope(bytes: &[u8]) -> Result<(), String> {
+    let envelope = ProofEnvelope::from_bytes(bytes).map_err(|e| e.to_string())?;
+    let circuit = &envelope.circuit_id;
+    println!("proof envelope: version {}", envelope.version);
+    println!(
+        "circuit: {} constraints, {} public inputs, {} wires",
+        circuit.num_constraints, circuit.num_public_inputs, circuit.num_wires
+    );
+    println!("r1cs hash: {}", hex(&circuit.r1cs_hash));
+    println!("vk hash: {}", hex(&envelope.vk_hash));
+    for (name, val) in envelope.public_inputs.pairs() {
+        println!("  {} = {}", name, val);
+    }
+    Ok(())
+}
+
+fn print_inputs(inputs: &WitnessInputs) {
+    let mut names: Vec<&String> = inputs.keys().collect();
+    names.sort();
+
+    for name in names {
+        match inputs[name].as_slice() {
+            [val] => println!("  {} = {}", name, val),
+            vals => println!("  {}: {} values", name, vals.len()),
+        }
+    }
+}
+
+fn phase2_new(args: &Args) -> Result<(), String> {
+    let r1cs = load_r1cs(args.required("r1cs")?)?;
+    let ptau = load_ptau(args.required("ptau")?, &r1cs)?;
+
+    let phase2 = Phase2::new(&r1cs, &ptau)?;
+    write_phase2(args.required("out")?, &phase2)?;
+
+    println!("circuit hash: {}", hex(phase2.cs_hash()));
+    Ok(())
+}
+
+fn phase2_contribute(args: &Args) -> Result<(), String> {
+    let mut phase2 = load_phase2(args.required("in")?)?;
+    let hash = phase2.contribute(args.get("name"), &mut ThreadRng::default());
+    write_phase2(args.required("out")?, &phase2)?;
+
+    println!(
+        "contribution {}: {}",
+        phase2.contributions().len(),
+        hex(&hash)
+    );
+    Ok(())
+}
+
+fn phase2_beacon(args: &Args) -> Result<(), String> {
+    let mut phase2 = load_phase2(args.required("in")?)?;
+    let beacon = Beacon {
+        hash: from_hex(args.required("hash")?)?,
+        iterations_exp: args.number("iterations-exp")? as u32,
+    };
+    let hash = phase2.apply_beacon(beacon)?;
+    write_phase2(args.required("out")?, &phase2)?;
+
+    println!("beacon {}: {}", phase2.contributions().len(), hex(&hash));
+    Ok(())
+}
+
+fn phase2_verify(args: &Args) -> Result<(), String> {
+    let r1cs = load_r1cs(args.required("r1cs")?)?;
+    let ptau = load_ptau(args.required("ptau")?, &r1cs)?;
+    let phase2 = load_phase2(args.required("in")?)?;
+
+    let hashes = phase2.verify(&r1cs, &ptau)?;
+    for (i, (hash, c)) in hashes.iter().zip(phase2.contributions()).enumerate() {
+        let kind = if c.beacon.is_some() {
+            "beacon"
+        } else {
+            "contribution"
+        };
+        println!("{} {}: {}", kind, i + 1, hex(hash));
+    }
+
+    println!("ceremony is valid");
+    Ok(())
+}
+
+fn phase2_export(args: &Args) -> Result<(), String> {
+    let r1cs = load_r1cs(args.required("r1cs")?)?;
+    let phase2 = load_phase2(args.required("in")?)?;
+    let out = args.required("zkey")?;
+
+    let mut zkey = Vec::new();
+    phase2.write_zkey(&mut zkey, &r1cs)?;
+    write(out, &zkey)?;
+
+    println!("wrote zkey to {}", out);
+    Ok(())
+}
+
+fn from_hex(s: &str) -> Result<Vec<u8>, String> {
+    let s = s.strip_prefix("0x").unwrap_or(s);
+    if s.len() % 2 != 0 {
+        return Err(format!("bad hex {:?}", s));
+    }
+
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).map_err(|e| format!("bad hex: {}", e)))
+        .collect()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn gen_input(args: &Args) -> Result<(), String> {
+    let data = read(args.required("data")?)?;
+    let params = CircuitParams::new(
+        args.number("block-size")?,
+        args.number("query-len")?,
+        args.number("levels")?,
+        args.number("digest-chunk")?,
+    );
+    let salt: U256 = args
+        .required("salt")?
+        .parse()
+        .map_err(|e| format!("bad --salt: {}", e))?;
+    let out = args.required("out")?;
+
+    let blocks = split_blocks(&data, params.block_size);
+    if blocks.is_empty() {
+        return Err("the data file is empty".to_string());
+    }
+
+    let leaves: Vec<U256> = blocks
+        .iter()
+        .map(|block| digest(block, params.digest_chunk))
+        .collect();
+    let tree = MerkleTree::new(&leaves, params.levels)?;
+
+    let indices = query_indices(salt, params.query_len, blocks.len());
+
+    let bytes = if out.ends_with(".json") {
+        let json = json!({
+            "chunks": indices
+                .iter()
+                .map(|i| blocks[*i].iter().map(|s| s.to_string()).collect())
+                .collect::<Vec<Vec<String>>>(),
+            "siblings": indices
+                .iter()
+                .map(|i| tree.siblings(*i).iter().map(|s| s.to_string()).collect())
+                .collect::<Vec<Vec<String>>>(),
+            "hashes": indices.iter().map(|i| leaves[*i].to_string()).collect::<Vec<_>>(),
+            "path": indices,
+            "root": tree.root().to_string(),
+            "salt": salt.to_string(),
+        });
+        serde_json::to_vec_pretty(&json).map_err(|e| e.to_string())?
+    } else {
+        let ext = |n: &U256| Value::Ext(EXT_ID_U256_LE, n.to_le_bytes_vec());
+        let inputs = Value::Map(vec![
+            (
+                "chunks".into(),
+                Value::Array(
+                    indices
+                        .iter()
+                        .map(|i| Value::Array(blocks[*i].iter().map(ext).collect()))
+                        .collect(),
+                ),
+            ),
+            (
+                "siblings".into(),
+                Value::Array(
+                    indices
+                        .iter()
+                        .map(|i| Value::Array(tree.siblings(*i).iter().map(ext).collect()))
+                        .collect(),
+                ),
+            ),
+            (
+                "hashes".into(),
+                Value::Array(indices.iter().map(|i| ext(&leaves[*i])).collect()),
+            ),
+            (
+                "path".into(),
+                Value::Array(indices.iter().map(|i| (*i as u64).into()).collect()),
+            ),
+            ("root".into(), ext(&tree.root())),
+            ("salt".into(), ext(&salt)),
+        ]);
+
+        let mut bytes = Vec::new();
+        write_value(&mut bytes, &inputs).map_err(|e| e.to_string())?;
+        bytes
+    };
+
+    write(out, &bytes)?;
+    println!(
+        "wrote {} queries over {} blocks to {}",
+        params.query_len,
+        blocks.len(),
+        out
+    );
+    Ok(())
+}
+
+/// Split `data` into symbols and the symbols into blocks, the last block is
+/// padded with zeros
+fn split_blocks(data: &[u8], block_size: usize) -> Vec<Vec<U256>> {
+    let symbols: Vec<U256> = data
+        .chunks(SYMBOL_SIZE)
+        .map(|s| U256::try_from_le_slice(s).unwrap())
+        .collect();
+
+    symbols
+        .chunks(block_size)
+        .map(|block| {
+            let mut block = block.to_vec();
+            block.resize(block_size, U256::ZERO);
+            block
+        })
+        .collect()
+}
+
+/// Block indices are derived from the salt, the same way for every run
+fn query_indices(salt: U256, query_len: usize, num_blocks: usize) -> Vec<usize> {
+    let num_blocks = U256::from(num_blocks);
+    (0..query_len)
+        .map(|i| (hash(&[salt, U256::from(i)]) % num_blocks).as_limbs()[0] as usize)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const R1CS: &str = "./src/circuit_tests/artifacts/storer-test.r1cs";
+    const WASM: &str = "./src/circuit_tests/artifacts/storer-test_js/storer-test.wasm";
+
+    fn args(args: &str) -> Args {
+        Args::parse(args.split_whitespace().map(String::from)).unwrap()
+    }
+
+    fn temp(name: &str) -> String {
+        std::env::temp_dir()
+            .join(name)
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn test_args() {
+        let parsed = args("file.r1cs --out pk.bin other --levels 32");
+        assert_eq!(parsed.positional, vec!["file.r1cs", "other"]);
+        assert_eq!(parsed.get("out"), Some("pk.bin"));
+        assert_eq!(parsed.required("out"), Ok("pk.bin"));
+        assert_eq!(parsed.number("levels"), Ok(32));
+        assert!(parsed.required("vk").is_err());
+        assert!(parsed.number("out").is_err());
+
+        assert!(Args::parse(["--out".to_string()].into_iter()).is_err());
+    }
+
+    #[test]
+    fn test_from_hex() {
+        assert_eq!(from_hex("0x00ff10"), Ok(vec![0x00, 0xff, 0x10]));
+        assert_eq!(from_hex("00FF10"), Ok(vec![0x00, 0xff, 0x10]));
+        assert_eq!(from_hex(""), Ok(vec![]));
+        assert!(from_hex("0x123").is_err());
+        assert!(from_hex("zz").is_err());
+        assert_eq!(hex(&from_hex("0a0b").unwrap()), "0a0b");
+    }
+
+    #[test]
+    fn test_gen_input_queries() {
+        let blocks = split_blocks(&[1u8; SYMBOL_SIZE * 4 + 1], 2);
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(blocks[2][0], U256::from(1));
+        assert_eq!(blocks[2][1], U256::ZERO);
+
+        let salt = U256::from(42);
+        let indices = query_indices(salt, 80, 5);
+        assert_eq!(indices.len(), 80);
+        assert!(indices.iter().all(|i| *i < 5));
+        assert_eq!(indices, query_indices(salt, 80, 5));
+        assert_ne!(indices, query_indices(U256::from(43), 80, 5));
+        assert_eq!(query_indices(salt, 3, 1), vec![0, 0, 0]);
+    }
+
+    #[test]
+    fn test_vk_format() {
+        assert_eq!(vk_format("ark"), Ok(VkFormat::ArkCompressed));
+        assert_eq!(vk_format("snarkjs"), Ok(VkFormat::Snarkjs));
+        assert!(vk_format("json").is_err());
+    }
+
+    #[test]
+    fn test_file_kind() {
+        assert_eq!(file_kind(&read(R1CS).unwrap()), Some(FileKind::R1cs));
+        assert_eq!(file_kind(b"wtns\x02\x00\x00\x00"), Some(FileKind::Wtns));
+        assert_eq!(
+            file_kind(&read("./tests/proof_test.mpack").unwrap()),
+            Some(FileKind::MpackInputs)
+        );
+        assert_eq!(
+            file_kind(br#"{"root": "1", "path": [0, 1]}"#),
+            Some(FileKind::JsonInputs)
+        );
+        assert_eq!(file_kind(b"not a proof"), None);
+    }
+
+    #[test]
+    fn test_gen_input_prove_verify() {
+        let data = temp("codex-proofs-cli.data");
+        let input = temp("codex-proofs-cli.mpack");
+        let params = temp("codex-proofs-cli-pk.bin");
+        let vk = temp("codex-proofs-cli-vk.bin");
+        let zkey = temp("codex-proofs-cli.zkey");
+        let proof = temp("codex-proofs-cli-proof.bin");
+        let public = temp("codex-proofs-cli-public.bin");
+        let envelope = temp("codex-proofs-cli-envelope.bin");
+
+        // 4 blocks of the StorageProver(256, 4, 2, 16) test circuit
+        let bytes: Vec<u8> = (0..4 * 256 * SYMBOL_SIZE).map(|i| i as u8).collect();
+        write(&data, &bytes).unwrap();
+
+        gen_input(&args(&format!(
+            "--data {} --block-size 256 --query-len 4 --levels 2 --digest-chunk 16 \
+             --salt 42 --out {}",
+            data, input
+        )))
+        .unwrap();
+        let kind = file_kind(&read(&input).unwrap());
+        assert_eq!(kind, Some(FileKind::MpackInputs));
+
+        setup(&args(&format!(
+            "--r1cs {} --out {} --vk {} --zkey {}",
+            R1CS, params, vk, zkey
+        )))
+        .unwrap();
+        assert_eq!(file_kind(&read(&zkey).unwrap()), Some(FileKind::Zkey));
+
+        prove(&args(&format!(
+            "--r1cs {} --wasm {} --zkey {} --input {} --proof {} --public {} --envelope {}",
+            R1CS, WASM, zkey, input, proof, public, envelope
+        )))
+        .unwrap();
+        let kind = file_kind(&read(&envelope).unwrap());
+        assert_eq!(kind, Some(FileKind::Envelope));
+
+        let verify_with = |key: &str| {
+            verify(&args(&format!(
+                "{} --proof {} --public {}",
+                key, proof, public
+            )))
+        };
+        assert!(verify_with(&format!("--vk {} --vk-format ark", vk)).is_ok());
+        assert!(verify_with(&format!("--zkey {}", zkey)).is_ok());
+        assert!(verify_with(&format!("--vk {} --vk-format snarkjs", vk)).is_err());
+
+        prove(&args(&format!(
+            "--r1cs {} --wasm {} --params {} --input {} --proof {} --public {}",
+            R1CS, WASM, params, input, proof, public
+        )))
+        .unwrap();
+        assert!(verify_with(&format!("--vk {}", vk)).is_ok());
+
+        // the public inputs are a length followed by the root and the salt
+        let mut other = read(&public).unwrap();
+        other[8] ^= 1;
+        write(&public, &other).unwrap();
+        assert!(verify_with(&format!("--vk {}", vk)).is_err());
+    }
+}
@@ -0,0 +1,245 @@
+//! The iden3 binary container shared by `.ptau`, `.zkey` and `.wtns` files
+//!
+//! A file starts with a 4-byte magic, a `u32` version and the number of
+//! sections. Each section is a `u32` type and `u64` size followed by its data.
+//! Curve points are stored uncompressed, every coordinate as 32 little endian
+//! bytes in Montgomery form, and the point at infinity as all zeros.
+
+use std::collections::HashMap;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use ark_bn254::{Fq, Fq2, G1Affine, G2Affine};
+use ark_ff::{BigInteger256, FpParameters, PrimeField, Zero};
+
+pub(crate) const FIELD_SIZE: usize = 32;
+pub(crate) const G1_SIZE: usize = 2 * FIELD_SIZE;
+pub(crate) const G2_SIZE: usize = 4 * FIELD_SIZE;
+
+#[derive(Debug)]
+pub(crate) struct BinReader<R> {
+    reader: R,
+    pub version: u32,
+    // position and size of every section
+    sections: HashMap<u32, (u64, u64)>,
+}
+
+impl<R: Read + Seek> BinReader<R> {
+    pub fn new(mut reader: R, magic: &[u8; 4]) -> Result<Self, String> {
+        let mut file_magic = [0u8; 4];
+        reader
+            .read_exact(&mut file_magic)
+            .map_err(|e| e.to_string())?;
+        if &file_magic != magic {
+            return Err(format!("not a {} file", String::from_utf8_lossy(magic)));
+        }
+
+        let version = read_u32(&mut reader)?;
+        let num_sections = read_u32(&mut reader)?;
+
+        let mut sections = HashMap::new();
+        for _ in 0..num_sections {
+            let id = read_u32(&mut reader)?;
+            let size = read_u64(&mut reader)?;
+            let pos = reader.stream_position().map_err(|e| e.to_string())?;
+            sections.insert(id, (pos, size));
+            reader
+                .seek(SeekFrom::Current(size as i64))
+                .map_err(|e| e.to_string())?;
+        }
+
+        Ok(Self {
+            reader,
+            version,
+            sections,
+        })
+    }
+
+    pub fn has_section(&self, id: u32) -> bool {
+        self.sections.contains_key(&id)
+    }
+
+    pub fn section_size(&self, id: u32) -> Result<u64, String> {
+        self.sections
+            .get(&id)
+            .map(|(_, size)| *size)
+            .ok_or_else(|| format!("missing section {}", id))
+    }
+
+    /// Position the reader `offset` bytes into section `id`
+    pub fn seek(&mut self, id: u32, offset: u64) -> Result<&mut R, String> {
+        let (pos, size) = *self
+            .sections
+            .get(&id)
+            .ok_or_else(|| format!("missing section {}", id))?;
+        if offset > size {
+            return Err(format!("section {} is too short", id));
+        }
+
+        self.reader
+            .seek(SeekFrom::Start(pos + offset))
+            .map_err(|e| e.to_string())?;
+        Ok(&mut self.reader)
+    }
+
+    pub fn read_section(&mut self, id: u32) -> Result<Vec<u8>, String> {
+        let size = self.section_size(id)? as usize;
+        let mut data = vec![0u8; size];
+        self.seek(id, 0)?
+            .read_exact(&mut data)
+            .map_err(|e| e.to_string())?;
+        Ok(data)
+    }
+
+    /// `count` G1 points starting at point `start` of section `id`
+    pub fn read_g1s(
+        &mut self,
+        id: u32,
+        start: usize,
+        count: usize,
+    ) -> Result<Vec<G1Affine>, String> {
+        self.check_len(id, (start + count) * G1_SIZE)?;
+        let mut data = vec![0u8; count * G1_SIZE];
+        self.seek(id, (start * G1_SIZE) as u64)?
+            .read_exact(&mut data)
+            .map_err(|e| e.to_string())?;
+
+        data.chunks(G1_SIZE).map(read_g1).collect()
+    }
+
+    /// `count` G2 points starting at point `start` of section `id`
+    pub fn read_g2s(
+        &mut self,
+        id: u32,
+        start: usize,
+        count: usize,
+    ) -> Result<Vec<G2Affine>, String> {
+        self.check_len(id, (start + count) * G2_SIZE)?;
+        let mut data = vec![0u8; count * G2_SIZE];
+        self.seek(id, (start * G2_SIZE) as u64)?
+            .read_exact(&mut data)
+            .map_err(|e| e.to_string())?;
+
+        data.chunks(G2_SIZE).map(read_g2).collect()
+    }
+
+    fn check_len(&self, id: u32, len: usize) -> Result<(), String> {
+        if (self.section_size(id)? as usize) < len {
+            return Err(format!("section {} is too short", id));
+        }
+
+        Ok(())
+    }
+}
+
+/// Write a file of `sections` in the order given
+pub(crate) fn write_bin<W: Write>(
+    mut writer: W,
+    magic: &[u8; 4],
+    version: u32,
+    sections: &[(u32, Vec<u8>)],
+) -> Result<(), String> {
+    let mut write = |bytes: &[u8]| writer.write_all(bytes).map_err(|e| e.to_string());
+
+    write(magic)?;
+    write(&version.to_le_bytes())?;
+    write(&(sections.len() as u32).to_le_bytes())?;
+    for (id, data) in sections {
+        write(&id.to_le_bytes())?;
+        write(&(data.len() as u64).to_le_bytes())?;
+        write(data)?;
+    }
+
+    Ok(())
+}
+
+fn read_u32(reader: &mut impl Read) -> Result<u32, String> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes).map_err(|e| e.to_string())?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> Result<u64, String> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes).map_err(|e| e.to_string())?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+pub(crate) fn read_bigint(bytes: &[u8]) -> BigInteger256 {
+    let mut limbs = [0u64; 4];
+    for (limb, chunk) in limbs.iter_mut().zip(bytes.chunks(8)) {
+        *limb = u64::from_le_bytes(chunk.try_into().unwrap());
+    }
+
+    BigInteger256::new(limbs)
+}
+
+pub(crate) fn write_bigint(bytes: &mut Vec<u8>, n: &BigInteger256) {
+    n.0.iter()
+        .for_each(|limb| bytes.extend_from_slice(&limb.to_le_bytes()));
+}
+
+/// Coordinates are already in Montgomery form, so they're used as they are
+pub(crate) fn read_fq(bytes: &[u8]) -> Result<Fq, String> {
+    let repr = read_bigint(bytes);
+    if repr >= <Fq as PrimeField>::Params::MODULUS {
+        return Err("coordinate is not in the field".to_string());
+    }
+
+    Ok(Fq::new(repr))
+}
+
+pub(crate) fn write_fq(bytes: &mut Vec<u8>, f: &Fq) {
+    write_bigint(bytes, &f.0);
+}
+
+pub(crate) fn read_g1(bytes: &[u8]) -> Result<G1Affine, String> {
+    let x = read_fq(&bytes[..FIELD_SIZE])?;
+    let y = read_fq(&bytes[FIELD_SIZE..])?;
+    if x.is_zero() && y.is_zero() {
+        return Ok(G1Affine::zero());
+    }
+
+    let p = G1Affine::new(x, y, false);
+    if !p.is_on_curve() {
+        return Err("G1 point is not on the curve".to_string());
+    }
+
+    Ok(p)
+}
+
+pub(crate) fn read_g2(bytes: &[u8]) -> Result<G2Affine, String> {
+    let fq = |i: usize| read_fq(&bytes[i * FIELD_SIZE..(i + 1) * FIELD_SIZE]);
+    let x = Fq2::new(fq(0)?, fq(1)?);
+    let y = Fq2::new(fq(2)?, fq(3)?);
+    if x.is_zero() && y.is_zero() {
+        return Ok(G2Affine::zero());
+    }
+
+    let p = G2Affine::new(x, y, false);
+    if !p.is_on_curve() || !p.is_in_correct_subgroup_assuming_on_curve() {
+        return Err("G2 point is not in the subgroup".to_string());
+    }
+
+    Ok(p)
+}
+
+pub(crate) fn write_g1(bytes: &mut Vec<u8>, p: &G1Affine) {
+    if p.infinity {
+        bytes.extend_from_slice(&[0u8; G1_SIZE]);
+        return;
+    }
+
+    write_fq(bytes, &p.x);
+    write_fq(bytes, &p.y);
+}
+
+pub(crate) fn write_g2(bytes: &mut Vec<u8>, p: &G2Affine) {
+    if p.infinity {
+        bytes.extend_from_slice(&[0u8; G2_SIZE]);
+        return;
+    }
+
+    for f in [p.x.c0, p.x.c1, p.y.c0, p.y.c1] {
+        write_fq(bytes, &f);
+    }
+}
@@ -0,0 +1,983 @@
+//! Groth16 phase 2 trusted setup, the ceremony snarkjs runs
+//!
+//! Phase 2 starts from the r1cs and the powers of tau of phase 1, with `delta`
+//! set to one. Every participant multiplies `delta` by a secret and divides
+//! the `l_query` and `h_query` points by it, publishing a proof of knowledge of
+//! the secret that's bound to the transcript so far. A random beacon can end
+//! the ceremony, its secret is derived from a public value anyone can
+//! recompute. As long as one participant discarded their secret, nobody
+//! knows `delta` and the resulting key is safe to prove with.
+//!
+//! The initial parameters, the circuit hash, the transcript and the beacon
+//! all follow snarkjs, so its `zkey verify` accepts the exported keys and a
+//! ceremony can go on with either tool. The H points are the ones of
+//! snarkjs's reduction, keys are proven with `CircomReduction`.
+
+use std::io::{Read, Write};
+
+use ark_bn254::{Bn254, Fq, Fq2, Fr, G1Affine, G1Projective, G2Affine, G2Projective};
+use ark_circom::circom::R1CS;
+use ark_ec::msm::{FixedBaseMSM, VariableBaseMSM};
+use ark_ec::{AffineCurve, ProjectiveCurve};
+use ark_ff::{
+    BigInteger, BigInteger256, Field, Fp256, Fp256Parameters, FpParameters, One, PrimeField,
+    UniformRand, Zero,
+};
+use ark_groth16::{ProvingKey, VerifyingKey};
+use ark_poly::{EvaluationDomain, GeneralEvaluationDomain};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use ark_std::rand::rngs::ThreadRng;
+use ark_std::rand::{Rng, RngCore, SeedableRng};
+use blake2::{Blake2b, Digest};
+use rand_chacha::ChaCha20Rng;
+use rayon::prelude::*;
+
+use crate::binfile::{read_g1, read_g2, write_g1, write_g2, FIELD_SIZE, G1_SIZE, G2_SIZE};
+use crate::envelope::sha256;
+use crate::ptau::{required_domain_size, same_ratio, PowersOfTau};
+use crate::zkey::write_zkey;
+
+const MAGIC: &[u8; 4] = b"cph2";
+
+/// Size of the blake2b hashes of the transcript
+pub const HASH_SIZE: usize = 64;
+
+/// Largest `2^exp` iterations of the beacon hash snarkjs accepts
+const MAX_BEACON_EXP: u32 = 63;
+
+/// snarkjs keeps the first 64 characters of a contributor's name
+const MAX_NAME_CHARS: usize = 64;
+
+const CONTRIBUTION: u32 = 0;
+const BEACON: u32 = 1;
+
+const PARAM_NAME: u8 = 1;
+const PARAM_ITERATIONS_EXP: u8 = 2;
+const PARAM_BEACON_HASH: u8 = 3;
+
+/// The public value a random beacon contribution is derived from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Beacon {
+    pub hash: Vec<u8>,
+    /// The hash is iterated `2^iterations_exp` times
+    pub iterations_exp: u32,
+}
+
+impl Beacon {
+    /// The contributed secret and `s`, drawn from the iterated hash the way
+    /// snarkjs draws them
+    fn secrets(&self) -> Result<(Fr, G1Affine), String> {
+        if self.hash.is_empty() || self.hash.len() > u8::MAX as usize {
+            return Err(format!(
+                "beacon hash of {} bytes, expected 1 to 255",
+                self.hash.len()
+            ));
+        }
+        if self.iterations_exp > MAX_BEACON_EXP {
+            return Err(format!(
+                "beacon iterations exponent {} is too large",
+                self.iterations_exp
+            ));
+        }
+
+        let mut digest = sha256(&self.hash);
+        for _ in 1..1u64 << self.iterations_exp {
+            digest = sha256(&digest);
+        }
+
+        let mut rng = seeded_rng(&digest);
+        let x = field_from_rng(&mut rng);
+        Ok((x, g1_from_rng(&mut rng)))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Contribution {
+    /// `delta G1` after this contribution
+    pub delta_after: G1Affine,
+    /// `s` and `x s` for the contributed secret `x`
+    pub s: G1Affine,
+    pub s_x: G1Affine,
+    /// `x r`, where `r` is hashed from `transcript`
+    pub r_x: G2Affine,
+    /// Hash of the transcript before this contribution, `s` and `x s`
+    pub transcript: [u8; HASH_SIZE],
+    pub name: Option<String>,
+    pub beacon: Option<Beacon>,
+}
+
+impl Contribution {
+    /// Add the public key of the contribution to `hasher`
+    fn hash_into(&self, hasher: &mut Blake2b) {
+        hash_g1(hasher, &self.delta_after);
+        hash_g1(hasher, &self.s);
+        hash_g1(hasher, &self.s_x);
+        hash_g2(hasher, &self.r_x);
+        hasher.update(self.transcript);
+    }
+
+    /// The hash snarkjs prints for the contribution, participants check
+    /// theirs is in the transcript with it
+    pub fn hash(&self) -> [u8; HASH_SIZE] {
+        let mut hasher = Blake2b::new();
+        self.hash_into(&mut hasher);
+        digest(hasher)
+    }
+}
+
+/// The phase 2 transcript snarkjs keeps in section 10 of a zkey
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MpcParams {
+    /// Hash of the initial parameters
+    pub cs_hash: [u8; HASH_SIZE],
+    pub contributions: Vec<Contribution>,
+}
+
+impl MpcParams {
+    /// The transcript the next contribution continues
+    fn transcript(&self) -> Blake2b {
+        let mut hasher = Blake2b::new();
+        hasher.update(self.cs_hash);
+        self.contributions
+            .iter()
+            .for_each(|c| c.hash_into(&mut hasher));
+        hasher
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        let mut bytes = self.cs_hash.to_vec();
+        bytes.extend_from_slice(&(self.contributions.len() as u32).to_le_bytes());
+        for c in &self.contributions {
+            write_g1(&mut bytes, &c.delta_after);
+            write_g1(&mut bytes, &c.s);
+            write_g1(&mut bytes, &c.s_x);
+            write_g2(&mut bytes, &c.r_x);
+            bytes.extend_from_slice(&c.transcript);
+
+            let mut params = Vec::new();
+            if let Some(name) = &c.name {
+                params.push(PARAM_NAME);
+                push_param(&mut params, name.as_bytes())?;
+            }
+            let kind = match &c.beacon {
+                Some(beacon) => {
+                    params.extend_from_slice(&[PARAM_ITERATIONS_EXP, beacon.iterations_exp as u8]);
+                    params.push(PARAM_BEACON_HASH);
+                    push_param(&mut params, &beacon.hash)?;
+                    BEACON
+                }
+                None => CONTRIBUTION,
+            };
+
+            bytes.extend_from_slice(&kind.to_le_bytes());
+            bytes.extend_from_slice(&(params.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(&params);
+        }
+
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, String> {
+        let reader = &mut bytes;
+        let cs_hash = take(reader, HASH_SIZE)?.try_into().unwrap();
+
+        let num_contributions = take_u32(reader)?;
+        let mut contributions = Vec::new();
+        for i in 0..num_contributions {
+            let delta_after = read_g1(take(reader, G1_SIZE)?)?;
+            let s = read_g1(take(reader, G1_SIZE)?)?;
+            let s_x = read_g1(take(reader, G1_SIZE)?)?;
+            let r_x = read_g2(take(reader, G2_SIZE)?)?;
+            let transcript = take(reader, HASH_SIZE)?.try_into().unwrap();
+            let kind = take_u32(reader)?;
+
+            let params_len = take_u32(reader)? as usize;
+            let params = &mut take(reader, params_len)?;
+            let (mut name, mut iterations_exp, mut hash) = (None, None, None);
+            while !params.is_empty() {
+                match take(params, 1)?[0] {
+                    PARAM_NAME => {
+                        let len = take(params, 1)?[0] as usize;
+                        let bytes = take(params, len)?.to_vec();
+                        name = Some(String::from_utf8(bytes).map_err(|e| e.to_string())?);
+                    }
+                    PARAM_ITERATIONS_EXP => iterations_exp = Some(take(params, 1)?[0] as u32),
+                    PARAM_BEACON_HASH => {
+                        let len = take(params, 1)?[0] as usize;
+                        hash = Some(take(params, len)?.to_vec());
+                    }
+                    param => {
+                        return Err(format!(
+                            "contribution {} has unknown parameter {}",
+                            i, param
+                        ))
+                    }
+                }
+            }
+
+            let beacon = match (kind, iterations_exp, hash) {
+                (CONTRIBUTION, _, _) => None,
+                (BEACON, Some(iterations_exp), Some(hash)) => Some(Beacon {
+                    hash,
+                    iterations_exp,
+                }),
+                (BEACON, _, _) => return Err(format!("beacon {} has no beacon parameters", i)),
+                _ => return Err(format!("contribution {} has unknown type {}", i, kind)),
+            };
+
+            contributions.push(Contribution {
+                delta_after,
+                s,
+                s_x,
+                r_x,
+                transcript,
+                name,
+                beacon,
+            });
+        }
+
+        if !reader.is_empty() {
+            return Err("unexpected data after the contributions".to_string());
+        }
+
+        Ok(Self {
+            cs_hash,
+            contributions,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Phase2 {
+    params: ProvingKey<Bn254>,
+    mpc: MpcParams,
+}
+
+impl Phase2 {
+    /// Size of the domain the powers of tau must cover for `r1cs`. It's
+    /// twice the circuit's evaluation domain, the H points are taken from the
+    /// Lagrange basis of the larger one.
+    pub fn domain_size(r1cs: &R1CS<Bn254>) -> Result<usize, String> {
+        Ok(2 * required_domain_size(r1cs)?)
+    }
+
+    /// Initial parameters for `r1cs`, with `gamma` and `delta` equal to one
+    pub fn new(r1cs: &R1CS<Bn254>, ptau: &PowersOfTau) -> Result<Self, String> {
+        let (params, legacy_h) = initial_params(r1cs, ptau)?;
+        let cs_hash = cs_hash(&params, &legacy_h);
+
+        Ok(Self {
+            params,
+            mpc: MpcParams {
+                cs_hash,
+                contributions: Vec::new(),
+            },
+        })
+    }
+
+    /// Parameters from a random `tau`, `alpha` and `beta` instead of a
+    /// ceremony, with one random contribution. Whoever runs this could keep
+    /// the secrets, like with `generate_random_parameters` it's only for
+    /// tests and development.
+    pub fn dev<R: Rng>(r1cs: &R1CS<Bn254>, rng: &mut R) -> Result<Self, String> {
+        let n = required_domain_size(r1cs)?;
+        let domain = GeneralEvaluationDomain::<Fr>::new(n).unwrap();
+        let double = GeneralEvaluationDomain::<Fr>::new(2 * n).unwrap();
+        let (tau, alpha, beta) = (Fr::rand(rng), Fr::rand(rng), Fr::rand(rng));
+
+        let lagrange = domain.evaluate_all_lagrange_coefficients(tau);
+        let [a, b, c] = wire_terms(r1cs).map(|wires| {
+            wires
+                .par_iter()
+                .map(|terms| {
+                    terms
+                        .iter()
+                        .map(|(i, coeff)| lagrange[*i] * coeff)
+                        .sum::<Fr>()
+                })
+                .collect::<Vec<Fr>>()
+        });
+        let abc: Vec<Fr> = a
+            .par_iter()
+            .zip(&b)
+            .zip(&c)
+            .map(|((a, b), c)| beta * a + alpha * b + c)
+            .collect();
+
+        let h: Vec<Fr> = double
+            .evaluate_all_lagrange_coefficients(tau)
+            .into_iter()
+            .skip(1)
+            .step_by(2)
+            .collect();
+        let z = tau.pow([n as u64]) - Fr::one();
+        let legacy_h: Vec<Fr> = std::iter::successors(Some(z), |p| Some(*p * tau))
+            .take(n - 1)
+            .collect();
+
+        let g1 = G1Affine::prime_subgroup_generator();
+        let g2 = G2Affine::prime_subgroup_generator();
+        let params = ProvingKey {
+            vk: VerifyingKey {
+                alpha_g1: g1.mul(alpha).into_affine(),
+                beta_g2: g2.mul(beta).into_affine(),
+                gamma_g2: g2,
+                delta_g2: g2,
+                gamma_abc_g1: times_generator::<G1Projective>(&abc[..r1cs.num_inputs]),
+            },
+            beta_g1: g1.mul(beta).into_affine(),
+            delta_g1: g1,
+            a_query: times_generator::<G1Projective>(&a),
+            b_g1_query: times_generator::<G1Projective>(&b),
+            b_g2_query: times_generator::<G2Projective>(&b),
+            h_query: times_generator::<G1Projective>(&h),
+            l_query: times_generator::<G1Projective>(&abc[r1cs.num_inputs..]),
+        };
+        let cs_hash = cs_hash(&params, &times_generator::<G1Projective>(&legacy_h));
+
+        let mut phase2 = Self {
+            params,
+            mpc: MpcParams {
+                cs_hash,
+                contributions: Vec::new(),
+            },
+        };
+        phase2.contribute(None, rng);
+        Ok(phase2)
+    }
+
+    pub fn params(&self) -> &ProvingKey<Bn254> {
+        &self.params
+    }
+
+    pub fn mpc_params(&self) -> &MpcParams {
+        &self.mpc
+    }
+
+    pub fn into_parts(self) -> (ProvingKey<Bn254>, MpcParams) {
+        (self.params, self.mpc)
+    }
+
+    /// Hash of the initial parameters, the same for every ceremony of the
+    /// circuit and powers of tau
+    pub fn cs_hash(&self) -> &[u8; HASH_SIZE] {
+        &self.mpc.cs_hash
+    }
+
+    pub fn contributions(&self) -> &[Contribution] {
+        &self.mpc.contributions
+    }
+
+    /// Contribute a random secret, returning the hash of the contribution
+    pub fn contribute<R: Rng>(&mut self, name: Option<&str>, rng: &mut R) -> [u8; HASH_SIZE] {
+        let mut x = Fr::rand(rng);
+        while x.is_zero() {
+            x = Fr::rand(rng);
+        }
+        let s = G1Affine::prime_subgroup_generator()
+            .mul(Fr::rand(rng))
+            .into_affine();
+
+        let name = name.map(|name| name.chars().take(MAX_NAME_CHARS).collect());
+        self.apply(x, s, name, None)
+    }
+
+    /// Contribute the secret derived from `beacon`, usually the last step
+    pub fn apply_beacon(&mut self, beacon: Beacon) -> Result<[u8; HASH_SIZE], String> {
+        let (x, s) = beacon.secrets()?;
+        if x.is_zero() {
+            return Err("beacon secret is zero".to_string());
+        }
+
+        Ok(self.apply(x, s, None, Some(beacon)))
+    }
+
+    fn apply(
+        &mut self,
+        x: Fr,
+        s: G1Affine,
+        name: Option<String>,
+        beacon: Option<Beacon>,
+    ) -> [u8; HASH_SIZE] {
+        let s_x = s.mul(x).into_affine();
+        let mut transcript = self.mpc.transcript();
+        hash_g1(&mut transcript, &s);
+        hash_g1(&mut transcript, &s_x);
+        let transcript = digest(transcript);
+        let r = hash_to_g2(&transcript);
+        let x_inv = x.inverse().expect("contributions are not zero");
+
+        let params = &mut self.params;
+        params.delta_g1 = params.delta_g1.mul(x).into_affine();
+        params.vk.delta_g2 = params.vk.delta_g2.mul(x).into_affine();
+        params.l_query = scale(&params.l_query, x_inv);
+        params.h_query = scale(&params.h_query, x_inv);
+
+        let contribution = Contribution {
+            delta_after: params.delta_g1,
+            s,
+            s_x,
+            r_x: r.mul(x).into_affine(),
+            transcript,
+            name,
+            beacon,
+        };
+        let hash = contribution.hash();
+        self.mpc.contributions.push(contribution);
+
+        hash
+    }
+
+    /// Check the parameters against the ones computed from `r1cs` and
+    /// `ptau`, and every contribution since. Returns the hash of each
+    /// contribution, for participants to find theirs.
+    pub fn verify(
+        &self,
+        r1cs: &R1CS<Bn254>,
+        ptau: &PowersOfTau,
+    ) -> Result<Vec<[u8; HASH_SIZE]>, String> {
+        let initial = Self::new(r1cs, ptau)?;
+        if initial.mpc.cs_hash != self.mpc.cs_hash {
+            return Err("parameters were not initialized from this circuit".to_string());
+        }
+
+        let (params, init) = (&self.params, &initial.params);
+        if params.vk.alpha_g1 != init.vk.alpha_g1
+            || params.vk.beta_g2 != init.vk.beta_g2
+            || params.vk.gamma_g2 != init.vk.gamma_g2
+            || params.vk.gamma_abc_g1 != init.vk.gamma_abc_g1
+            || params.beta_g1 != init.beta_g1
+            || params.a_query != init.a_query
+            || params.b_g1_query != init.b_g1_query
+            || params.b_g2_query != init.b_g2_query
+            || params.l_query.len() != init.l_query.len()
+            || params.h_query.len() != init.h_query.len()
+        {
+            return Err("parameters other than delta were modified".to_string());
+        }
+
+        let mut transcript = initial.mpc.transcript();
+        let mut delta = init.delta_g1;
+        let mut hashes = Vec::with_capacity(self.mpc.contributions.len());
+        for (i, c) in self.mpc.contributions.iter().enumerate() {
+            let mut hasher = transcript.clone();
+            hash_g1(&mut hasher, &c.s);
+            hash_g1(&mut hasher, &c.s_x);
+            if digest(hasher) != c.transcript {
+                return Err(format!("contribution {} doesn't follow the transcript", i));
+            }
+
+            let r = hash_to_g2(&c.transcript);
+            if !same_ratio((c.s, c.s_x), (r, c.r_x))
+                || !same_ratio((delta, c.delta_after), (r, c.r_x))
+            {
+                return Err(format!("contribution {} is invalid", i));
+            }
+
+            if let Some(beacon) = &c.beacon {
+                let (x, s) = beacon.secrets()?;
+                if c.s != s || c.s_x != s.mul(x).into_affine() {
+                    return Err(format!(
+                        "beacon contribution {} doesn't match its beacon",
+                        i
+                    ));
+                }
+            }
+
+            c.hash_into(&mut transcript);
+            hashes.push(c.hash());
+            delta = c.delta_after;
+        }
+
+        let g1 = G1Affine::prime_subgroup_generator();
+        let g2 = G2Affine::prime_subgroup_generator();
+        if params.delta_g1 != delta || !same_ratio((g1, params.delta_g1), (g2, params.vk.delta_g2))
+        {
+            return Err("delta doesn't match the last contribution".to_string());
+        }
+
+        // every point of l and h must be divided by the same delta, which is
+        // checked for a random linear combination of them at once
+        let mut rng = ThreadRng::default();
+        for (name, after, before) in [
+            ("l", &params.l_query, &init.l_query),
+            ("h", &params.h_query, &init.h_query),
+        ] {
+            let scalars: Vec<_> = (0..after.len())
+                .map(|_| Fr::rand(&mut rng).into_repr())
+                .collect();
+            let after = VariableBaseMSM::multi_scalar_mul(after, &scalars).into_affine();
+            let before = VariableBaseMSM::multi_scalar_mul(before, &scalars).into_affine();
+            if !same_ratio((after, before), (g2, params.vk.delta_g2)) {
+                return Err(format!("{} query doesn't match delta", name));
+            }
+        }
+
+        Ok(hashes)
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<(), String> {
+        writer.write_all(MAGIC).map_err(|e| e.to_string())?;
+        self.params
+            .serialize_uncompressed(&mut writer)
+            .map_err(|e| e.to_string())?;
+        writer
+            .write_all(&self.mpc.to_bytes()?)
+            .map_err(|e| e.to_string())
+    }
+
+    pub fn read<R: Read>(mut reader: R) -> Result<Self, String> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic).map_err(|e| e.to_string())?;
+        if &magic != MAGIC {
+            return Err("not a phase 2 parameters file".to_string());
+        }
+
+        let params = ProvingKey::<Bn254>::deserialize_uncompressed(&mut reader)
+            .map_err(|e| e.to_string())?;
+        let mut mpc = Vec::new();
+        reader.read_to_end(&mut mpc).map_err(|e| e.to_string())?;
+
+        Ok(Self {
+            params,
+            mpc: MpcParams::from_bytes(&mpc)?,
+        })
+    }
+
+    /// Write the parameters and the transcript as a `.zkey`, for
+    /// `StorageProofs::new` or snarkjs
+    pub fn write_zkey<W: Write>(&self, writer: W, r1cs: &R1CS<Bn254>) -> Result<(), String> {
+        write_zkey(writer, &self.params, r1cs, &self.mpc)
+    }
+}
+
+/// The parameters snarkjs's `zkey new` computes from the powers of tau, with
+/// `gamma` and `delta` equal to one and the standard generators. Also returns
+/// the H points of ark's reduction, `tau^i (tau^n - 1) G1`, which snarkjs
+/// still hashes.
+fn initial_params(
+    r1cs: &R1CS<Bn254>,
+    ptau: &PowersOfTau,
+) -> Result<(ProvingKey<Bn254>, Vec<G1Affine>), String> {
+    let n = required_domain_size(r1cs)?;
+    if ptau.domain_size() < 2 * n {
+        return Err(format!(
+            "powers of tau for a domain of {} are too small for a domain of {}",
+            ptau.domain_size(),
+            2 * n
+        ));
+    }
+    let lagrange = ptau.lagrange(n)?;
+
+    let [a, b, c] = wire_terms(r1cs);
+    let a_query = combine(&lagrange.tau_g1, &a);
+    let b_g1_query = combine(&lagrange.tau_g1, &b);
+    let b_g2_query = combine(&lagrange.tau_g2, &b);
+
+    // beta a + alpha b + c of every wire
+    let abc: Vec<G1Projective> = combine(&lagrange.beta_tau_g1, &a)
+        .into_par_iter()
+        .zip(combine(&lagrange.alpha_tau_g1, &b))
+        .zip(combine(&lagrange.tau_g1, &c))
+        .map(|((a, b), c)| a + b + c)
+        .collect();
+
+    // the Lagrange basis of twice the domain at its odd elements, where the
+    // prover evaluates `a b - c`
+    let h_query: Vec<G1Affine> = ptau
+        .lagrange(2 * n)?
+        .tau_g1
+        .into_iter()
+        .skip(1)
+        .step_by(2)
+        .collect();
+
+    let legacy_h: Vec<G1Projective> = (0..n - 1)
+        .into_par_iter()
+        .map(|i| ptau.tau_g1[n + i].into_projective() - ptau.tau_g1[i].into_projective())
+        .collect();
+
+    let g1 = ptau.tau_g1[0];
+    let g2 = ptau.tau_g2[0];
+    let params = ProvingKey {
+        vk: VerifyingKey {
+            alpha_g1: ptau.alpha_tau_g1[0],
+            beta_g2: ptau.beta_g2,
+            gamma_g2: g2,
+            delta_g2: g2,
+            gamma_abc_g1: G1Projective::batch_normalization_into_affine(&abc[..r1cs.num_inputs]),
+        },
+        beta_g1: ptau.beta_tau_g1[0],
+        delta_g1: g1,
+        a_query: G1Projective::batch_normalization_into_affine(&a_query),
+        b_g1_query: G1Projective::batch_normalization_into_affine(&b_g1_query),
+        b_g2_query: G2Projective::batch_normalization_into_affine(&b_g2_query),
+        h_query,
+        l_query: G1Projective::batch_normalization_into_affine(&abc[r1cs.num_inputs..]),
+    };
+
+    Ok((
+        params,
+        G1Projective::batch_normalization_into_affine(&legacy_h),
+    ))
+}
+
+/// The A, B and C terms of every wire, as `(constraint, coeff)`. The public
+/// inputs and the `one` wire get one extra A term each, the way ark and
+/// snarkjs bind them.
+fn wire_terms(r1cs: &R1CS<Bn254>) -> [Vec<Vec<(usize, Fr)>>; 3] {
+    let num_constraints = r1cs.constraints.len();
+    let mut a = vec![Vec::new(); r1cs.num_variables];
+    let mut b = vec![Vec::new(); r1cs.num_variables];
+    let mut c = vec![Vec::new(); r1cs.num_variables];
+    for (i, constraint) in r1cs.constraints.iter().enumerate() {
+        for (terms, lc) in [
+            (&mut a, &constraint.0),
+            (&mut b, &constraint.1),
+            (&mut c, &constraint.2),
+        ] {
+            for (wire, coeff) in lc {
+                terms[*wire].push((i, *coeff));
+            }
+        }
+    }
+    for (i, terms) in a.iter_mut().enumerate().take(r1cs.num_inputs) {
+        terms.push((num_constraints + i, Fr::one()));
+    }
+
+    [a, b, c]
+}
+
+/// The blake2b hash snarkjs identifies the initial parameters by. It covers
+/// `legacy_h` instead of the H points of the key.
+fn cs_hash(params: &ProvingKey<Bn254>, legacy_h: &[G1Affine]) -> [u8; HASH_SIZE] {
+    let mut hasher = Blake2b::new();
+    hash_g1(&mut hasher, &params.vk.alpha_g1);
+    hash_g1(&mut hasher, &params.beta_g1);
+    hash_g2(&mut hasher, &params.vk.beta_g2);
+    hash_g2(&mut hasher, &params.vk.gamma_g2);
+    hash_g1(&mut hasher, &params.delta_g1);
+    hash_g2(&mut hasher, &params.vk.delta_g2);
+
+    for points in [
+        &params.vk.gamma_abc_g1[..],
+        legacy_h,
+        &params.l_query,
+        &params.a_query,
+        &params.b_g1_query,
+    ] {
+        hasher.update((points.len() as u32).to_be_bytes());
+        points.iter().for_each(|p| hash_g1(&mut hasher, p));
+    }
+    hasher.update((params.b_g2_query.len() as u32).to_be_bytes());
+    params
+        .b_g2_query
+        .iter()
+        .for_each(|p| hash_g2(&mut hasher, p));
+
+    digest(hasher)
+}
+
+/// `sum coeff basis[i]` for the `(i, coeff)` terms of every wire
+fn combine<G: AffineCurve<ScalarField = Fr>>(
+    basis: &[G],
+    terms: &[Vec<(usize, Fr)>],
+) -> Vec<G::Projective> {
+    terms
+        .par_iter()
+        .map(|terms| {
+            let mut sum = G::Projective::zero();
+            for (i, coeff) in terms {
+                if coeff.is_one() {
+                    sum.add_assign_mixed(&basis[*i]);
+                } else {
+                    sum += basis[*i].mul(coeff.into_repr());
+                }
+            }
+            sum
+        })
+        .collect()
+}
+
+/// `x G` for every scalar `x` and the generator `G`
+fn times_generator<G: ProjectiveCurve<ScalarField = Fr>>(scalars: &[Fr]) -> Vec<G::Affine> {
+    let bits = Fr::size_in_bits();
+    let window = FixedBaseMSM::get_mul_window_size(scalars.len());
+    let table = FixedBaseMSM::get_window_table(bits, window, G::prime_subgroup_generator());
+    let points = FixedBaseMSM::multi_scalar_mul::<G>(bits, window, &table, scalars);
+    G::batch_normalization_into_affine(&points)
+}
+
+fn scale(points: &[G1Affine], x: Fr) -> Vec<G1Affine> {
+    let x = x.into_repr();
+    let points: Vec<G1Projective> = points.par_iter().map(|p| p.mul(x)).collect();
+    G1Projective::batch_normalization_into_affine(&points)
+}
+
+fn digest(hasher: Blake2b) -> [u8; HASH_SIZE] {
+    let mut hash = [0u8; HASH_SIZE];
+    hash.copy_from_slice(&hasher.finalize());
+    hash
+}
+
+/// Hash a point the way snarkjs does: big endian coordinates, `c1` before
+/// `c0`, and the point at infinity flagged in the first byte
+fn hash_point(hasher: &mut Blake2b, coords: &[Fq], infinity: bool) {
+    let mut bytes = vec![0u8; coords.len() * FIELD_SIZE];
+    if infinity {
+        bytes[0] = 0x40;
+    } else {
+        for (chunk, coord) in bytes.chunks_mut(FIELD_SIZE).zip(coords) {
+            chunk.copy_from_slice(&coord.into_repr().to_bytes_be());
+        }
+    }
+
+    hasher.update(&bytes);
+}
+
+fn hash_g1(hasher: &mut Blake2b, p: &G1Affine) {
+    hash_point(hasher, &[p.x, p.y], p.infinity)
+}
+
+fn hash_g2(hasher: &mut Blake2b, p: &G2Affine) {
+    hash_point(hasher, &[p.x.c1, p.x.c0, p.y.c1, p.y.c0], p.infinity)
+}
+
+/// The ChaCha20 generator snarkjs seeds with the first 32 bytes of `hash`
+fn seeded_rng(hash: &[u8]) -> ChaCha20Rng {
+    // snarkjs reads the key as big endian words
+    let mut seed = [0u8; 32];
+    for (word, bytes) in seed.chunks_mut(4).zip(hash.chunks(4)) {
+        let value = u32::from_be_bytes(bytes.try_into().unwrap());
+        word.copy_from_slice(&value.to_le_bytes());
+    }
+
+    ChaCha20Rng::from_seed(seed)
+}
+
+/// A field element drawn the way ffjavascript does: 64 bit limbs of two
+/// words each, the first one high, masked to the size of the modulus and
+/// taken as the Montgomery form
+fn field_from_rng<P: Fp256Parameters>(rng: &mut impl RngCore) -> Fp256<P> {
+    loop {
+        let mut limbs = [0u64; 4];
+        for limb in limbs.iter_mut() {
+            *limb = (u64::from(rng.next_u32()) << 32) | u64::from(rng.next_u32());
+        }
+        limbs[3] &= u64::MAX >> (256 - P::MODULUS_BITS);
+
+        let repr = BigInteger256::new(limbs);
+        if repr < P::MODULUS {
+            return Fp256::new(repr);
+        }
+    }
+}
+
+fn g1_from_rng(rng: &mut impl RngCore) -> G1Affine {
+    loop {
+        let x: Fq = field_from_rng(rng);
+        let greatest = rng.next_u32() & 1 == 1;
+        if let Some(p) = G1Affine::get_point_from_x(x, greatest) {
+            return p;
+        }
+    }
+}
+
+/// A G2 point nobody knows the discrete log of, drawn from a generator
+/// seeded with `transcript`
+fn hash_to_g2(transcript: &[u8; HASH_SIZE]) -> G2Affine {
+    let mut rng = seeded_rng(transcript);
+    loop {
+        let x = Fq2::new(field_from_rng(&mut rng), field_from_rng(&mut rng));
+        let greatest = rng.next_u32() & 1 == 1;
+        if let Some(p) = G2Affine::get_point_from_x(x, greatest) {
+            return p.scale_by_cofactor().into_affine();
+        }
+    }
+}
+
+/// A parameter of a contribution, prefixed with its length
+fn push_param(params: &mut Vec<u8>, value: &[u8]) -> Result<(), String> {
+    if value.len() > u8::MAX as usize {
+        return Err(format!("contribution parameter of {} bytes", value.len()));
+    }
+
+    params.push(value.len() as u8);
+    params.extend_from_slice(value);
+    Ok(())
+}
+
+fn take<'a>(reader: &mut &'a [u8], len: usize) -> Result<&'a [u8], String> {
+    if reader.len() < len {
+        return Err("phase 2 transcript is truncated".to_string());
+    }
+
+    let (bytes, rest) = reader.split_at(len);
+    *reader = rest;
+    Ok(bytes)
+}
+
+fn take_u32(reader: &mut &[u8]) -> Result<u32, String> {
+    Ok(u32::from_le_bytes(take(reader, 4)?.try_into().unwrap()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use ark_bn254::{Bn254, Fr, G1Affine, G1Projective};
+    use ark_circom::circom::{R1CSFile, R1CS};
+    use ark_circom::{read_zkey, CircomReduction};
+    use ark_ec::{AffineCurve, ProjectiveCurve};
+    use ark_ff::{Field, PrimeField, Zero};
+    use ark_groth16::{create_random_proof_with_reduction, prepare_verifying_key, verify_proof};
+    use ark_poly::{EvaluationDomain, GeneralEvaluationDomain};
+    use ark_std::rand::{rngs::StdRng, SeedableRng};
+
+    use super::{cs_hash, Beacon, MpcParams, Phase2};
+    use crate::circuit::StorageCircuit;
+    use crate::ptau::tests::generate;
+    use crate::zkey::read_mpc_params;
+
+    #[test]
+    fn test_phase2_ceremony() {
+        let mut rng = StdRng::seed_from_u64(42);
+
+        // x * y = z and z * 2 = w with public z and w
+        let two = Fr::from(2u32);
+        let r1cs = R1CS::<Bn254> {
+            num_inputs: 3,
+            num_aux: 2,
+            num_variables: 5,
+            constraints: vec![
+                (
+                    vec![(3, Fr::from(1u32))],
+                    vec![(4, Fr::from(1u32))],
+                    vec![(1, Fr::from(1u32))],
+                ),
+                (
+                    vec![(1, Fr::from(1u32))],
+                    vec![(0, two)],
+                    vec![(2, Fr::from(1u32))],
+                ),
+            ],
+            wire_mapping: None,
+        };
+        assert_eq!(Phase2::domain_size(&r1cs).unwrap(), 16);
+        let ptau = generate(4, &mut rng);
+        assert!(Phase2::new(&r1cs, &generate(3, &mut rng)).is_err());
+
+        let mut phase2 = Phase2::new(&r1cs, &ptau).unwrap();
+        let first = phase2.contribute(Some("first"), &mut rng);
+        phase2.contribute(None, &mut rng);
+        let beacon = Beacon {
+            hash: vec![7; 32],
+            iterations_exp: 4,
+        };
+        let last = phase2.apply_beacon(beacon).unwrap();
+
+        let hashes = phase2.verify(&r1cs, &ptau).unwrap();
+        assert_eq!(hashes.len(), 3);
+        assert_eq!(hashes[0], first);
+        assert_eq!(hashes[2], last);
+        assert_eq!(phase2.contributions()[0].name.as_deref(), Some("first"));
+
+        let mut bytes = Vec::new();
+        phase2.write(&mut bytes).unwrap();
+        assert_eq!(Phase2::read(bytes.as_slice()).unwrap(), phase2);
+        let mpc = phase2.mpc_params();
+        assert_eq!(
+            &MpcParams::from_bytes(&mpc.to_bytes().unwrap()).unwrap(),
+            mpc
+        );
+
+        let mut tampered = phase2.clone();
+        tampered.params.h_query[0] = tampered.params.h_query[1];
+        assert!(tampered.verify(&r1cs, &ptau).is_err());
+
+        let mut tampered = phase2.clone();
+        tampered.mpc.contributions[1].transcript[0] ^= 1;
+        assert!(tampered.verify(&r1cs, &ptau).is_err());
+
+        let mut tampered = phase2.clone();
+        tampered.mpc.contributions[2]
+            .beacon
+            .as_mut()
+            .unwrap()
+            .iterations_exp = 5;
+        assert!(tampered.verify(&r1cs, &ptau).is_err());
+
+        let mut zkey = Vec::new();
+        phase2.write_zkey(&mut zkey, &r1cs).unwrap();
+        let params = read_zkey(&mut Cursor::new(&zkey)).unwrap().0;
+        assert_eq!(params.vk, phase2.params().vk);
+        assert_eq!(&read_mpc_params(Cursor::new(&zkey)).unwrap(), mpc);
+
+        let witness: Vec<Fr> = [1u32, 12, 24, 3, 4].into_iter().map(Fr::from).collect();
+        let circuit = StorageCircuit::new(&r1cs, witness.clone());
+        let proof = create_random_proof_with_reduction::<_, _, _, CircomReduction>(
+            circuit, &params, &mut rng,
+        )
+        .unwrap();
+        let pvk = prepare_verifying_key(&params.vk);
+        assert!(verify_proof(&pvk, &proof, &witness[1..3]).unwrap());
+    }
+
+    #[test]
+    fn test_snarkjs_cs_hash() {
+        let r1cs: R1CS<Bn254> =
+            R1CSFile::<Bn254>::new(&include_bytes!("../tests/snarkjs/mycircuit.r1cs")[..])
+                .unwrap()
+                .into();
+        let zkey = include_bytes!("../tests/snarkjs/mycircuit.zkey");
+        let params = read_zkey(&mut Cursor::new(zkey)).unwrap().0;
+        let mpc = read_mpc_params(Cursor::new(zkey)).unwrap();
+        assert!(mpc.contributions.is_empty());
+
+        // the hash covers H points the zkey doesn't have, they're recovered
+        // from the powers of tau hidden in its points. With the wires one, c,
+        // a and b, the domain of 4 has a for constraint 0 in B and the input
+        // constraints of one and c in A, which gives its Lagrange basis.
+        let n = 4;
+        let g1 = G1Affine::prime_subgroup_generator().into_projective();
+        let mut small = vec![
+            params.b_g1_query[3].into_projective(),
+            params.a_query[0].into_projective(),
+            params.a_query[1].into_projective(),
+        ];
+        small.push(g1 - small.iter().copied().sum::<G1Projective>());
+
+        // the even elements of twice the domain are the domain itself, the
+        // odd ones are the H points
+        let domain = GeneralEvaluationDomain::<Fr>::new(n).unwrap();
+        let double = GeneralEvaluationDomain::<Fr>::new(2 * n).unwrap();
+        let mut lagrange = vec![G1Projective::zero(); 2 * n];
+        for (j, h) in params.h_query.iter().enumerate() {
+            lagrange[2 * j + 1] = h.into_projective();
+            let at = domain.evaluate_all_lagrange_coefficients(double.element(2 * j + 1));
+            for (k, l) in small.iter_mut().enumerate() {
+                *l -= h.mul(at[k].into_repr());
+            }
+        }
+        for (k, l) in small.into_iter().enumerate() {
+            lagrange[2 * k] = l;
+        }
+
+        // tau^j = sum_k w^(j k) L_k(tau)
+        let tau: Vec<G1Projective> = (0..2 * n)
+            .map(|j| {
+                lagrange
+                    .iter()
+                    .enumerate()
+                    .map(|(k, l)| l.mul(double.element(1).pow([(j * k) as u64]).into_repr()))
+                    .sum()
+            })
+            .collect();
+        assert_eq!(tau[0], g1);
+
+        let legacy_h: Vec<G1Affine> = (0..n - 1)
+            .map(|i| (tau[n + i] - tau[i]).into_affine())
+            .collect();
+        assert_eq!(cs_hash(&params, &legacy_h), mpc.cs_hash);
+    }
+}
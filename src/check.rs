@@ -0,0 +1,99 @@
+//! Constraint check without proving
+//!
+//! Evaluates every r1cs constraint against a witness and reports the ones that
+//! don't hold, with the names of the signals they involve, so it's clear which
+//! part of the circuit (digest, Merkle path, root) the inputs violate.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use ark_bn254::{Bn254, Fr};
+use ark_circom::circom::R1CS;
+
+use crate::native_witness::eval;
+use crate::symbols::SymbolTable;
+
+/// A constraint that isn't satisfied by the witness
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConstraintFailure {
+    pub constraint: usize,
+    /// Names of the signals in the constraint, in wire order
+    pub signals: Vec<String>,
+}
+
+impl fmt::Display for ConstraintFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "constraint {} is not satisfied: {}",
+            self.constraint,
+            self.signals.join(", ")
+        )
+    }
+}
+
+/// The first `max_failures` constraints that `witness` doesn't satisfy
+pub fn failing_constraints(
+    r1cs: &R1CS<Bn254>,
+    witness: &[Fr],
+    symbols: Option<&SymbolTable>,
+    max_failures: usize,
+) -> Vec<ConstraintFailure> {
+    let failing = unsatisfied(r1cs, witness).take(max_failures).collect();
+    describe_constraints(r1cs, failing, symbols)
+}
+
+/// Indices of the constraints `witness` doesn't satisfy, in order
+pub(crate) fn unsatisfied<'a>(
+    r1cs: &'a R1CS<Bn254>,
+    witness: &'a [Fr],
+) -> impl Iterator<Item = usize> + 'a {
+    r1cs.constraints
+        .iter()
+        .enumerate()
+        .filter(|(_, (a, b, c))| eval(a, witness) * eval(b, witness) != eval(c, witness))
+        .map(|(i, _)| i)
+}
+
+/// Name the signals of each of the `failing` constraints
+pub(crate) fn describe_constraints(
+    r1cs: &R1CS<Bn254>,
+    failing: Vec<usize>,
+    symbols: Option<&SymbolTable>,
+) -> Vec<ConstraintFailure> {
+    if failing.is_empty() {
+        return Vec::new();
+    }
+
+    let names: HashMap<u64, &str> = symbols
+        .map(|symbols| {
+            symbols
+                .symbols()
+                .iter()
+                .filter_map(|s| s.wire.map(|w| (w, s.name.as_str())))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    failing
+        .into_iter()
+        .map(|i| {
+            let (a, b, c) = &r1cs.constraints[i];
+            let mut wires: Vec<usize> = a.iter().chain(b).chain(c).map(|(w, _)| *w).collect();
+            wires.sort_unstable();
+            wires.dedup();
+
+            ConstraintFailure {
+                constraint: i,
+                signals: wires
+                    .into_iter()
+                    .filter(|w| *w != 0)
+                    .map(|w| match names.get(&(w as u64)) {
+                        Some(name) => name.to_string(),
+                        None => format!("wire {}", w),
+                    })
+                    .collect(),
+            }
+        })
+        .collect()
+}
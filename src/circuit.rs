@@ -0,0 +1,81 @@
+//! R1CS circuit borrowed from the prover
+//!
+//! `CircomCircuit` owns its `R1CS`, so every proof used to copy all of the
+//! constraints. `StorageCircuit` borrows them from the prover instead and only
+//! owns the witness.
+
+use ark_bn254::{Bn254, Fr};
+use ark_circom::circom::R1CS;
+use ark_relations::r1cs::{
+    ConstraintSynthesizer, ConstraintSystemRef, LinearCombination, SynthesisError, Variable,
+};
+
+#[derive(Debug, Clone)]
+pub struct StorageCircuit<'a> {
+    r1cs: &'a R1CS<Bn254>,
+    witness: Option<Vec<Fr>>,
+}
+
+impl<'a> StorageCircuit<'a> {
+    /// Circuit without a witness, used to generate the proving key
+    pub fn setup(r1cs: &'a R1CS<Bn254>) -> Self {
+        Self { r1cs, witness: None }
+    }
+
+    /// `witness` is indexed by wire, as returned by the witness calculator
+    pub fn new(r1cs: &'a R1CS<Bn254>, witness: Vec<Fr>) -> Self {
+        Self {
+            r1cs,
+            witness: Some(witness),
+        }
+    }
+
+    pub fn get_public_inputs(&self) -> Option<Vec<Fr>> {
+        self.witness
+            .as_ref()
+            .map(|w| w[1..self.r1cs.num_inputs].to_vec())
+    }
+}
+
+impl<'a> ConstraintSynthesizer<Fr> for StorageCircuit<'a> {
+    fn generate_constraints(self, cs: ConstraintSystemRef<Fr>) -> Result<(), SynthesisError> {
+        let num_inputs = self.r1cs.num_inputs;
+        let value = |i: usize| match &self.witness {
+            None => Fr::from(1u32),
+            Some(w) => w[i],
+        };
+
+        // start from 1, arkworks allocates the `one` input implicitly
+        for i in 1..num_inputs {
+            cs.new_input_variable(|| Ok(value(i)))?;
+        }
+
+        for i in 0..self.r1cs.num_aux {
+            cs.new_witness_variable(|| Ok(value(i + num_inputs)))?;
+        }
+
+        let make_index = |index| {
+            if index < num_inputs {
+                Variable::Instance(index)
+            } else {
+                Variable::Witness(index - num_inputs)
+            }
+        };
+        let make_lc = |lc_data: &[(usize, Fr)]| {
+            lc_data.iter().fold(
+                LinearCombination::<Fr>::zero(),
+                |lc: LinearCombination<Fr>, (index, coeff)| lc + (*coeff, make_index(*index)),
+            )
+        };
+
+        for constraint in &self.r1cs.constraints {
+            cs.enforce_constraint(
+                make_lc(&constraint.0),
+                make_lc(&constraint.1),
+                make_lc(&constraint.2),
+            )?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,181 @@
+//! Circuit introspection
+//!
+//! Sizes come from the r1cs header, input names and dimensions from the
+//! `.sym` file when there is one.
+
+use std::io::Cursor;
+use std::path::Path;
+
+use ark_bn254::{Bn254, Fr};
+use ark_circom::circom::R1CSFile;
+use ark_ff::Field;
+use rmpv::encode::write_value;
+use rmpv::Value;
+
+use crate::symbols::{signal_name, SymbolTable};
+
+/// Name circom and snarkjs use for the bn254 curve
+pub const CURVE_BN128: &str = "bn128";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputSignal {
+    /// Name without the `main.` prefix, e.g. `chunks`
+    pub name: String,
+    /// Array dimensions, empty for a single value
+    pub dims: Vec<usize>,
+    pub public: bool,
+}
+
+impl InputSignal {
+    /// Number of field elements in the signal
+    pub fn len(&self) -> usize {
+        self.dims.iter().product()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CircuitInfo {
+    pub curve: String,
+    pub num_constraints: usize,
+    pub num_public_outputs: usize,
+    pub num_public_inputs: usize,
+    pub num_private_inputs: usize,
+    pub num_wires: usize,
+    pub num_labels: usize,
+    /// Input signals in declaration order, empty without a `.sym` file
+    pub inputs: Vec<InputSignal>,
+}
+
+impl CircuitInfo {
+    pub fn new(file: &R1CSFile<Bn254>, symbols: Option<&SymbolTable>) -> Self {
+        let header = &file.header;
+        let public_start = 1 + header.n_pub_out as usize;
+        let public_end = public_start + header.n_pub_in as usize;
+        let private_end = public_end + header.n_prv_in as usize;
+
+        let mut inputs: Vec<InputSignal> = Vec::new();
+        for symbol in symbols.map(|s| s.symbols()).unwrap_or_default() {
+            let wire = match symbol.wire {
+                Some(wire) if (public_start..private_end).contains(&(wire as usize)) => wire,
+                _ => continue,
+            };
+
+            let (name, indices) = split_indices(signal_name(&symbol.name));
+            let pos = match inputs.iter().position(|i| i.name == name) {
+                Some(pos) => pos,
+                None => {
+                    inputs.push(InputSignal {
+                        name: name.to_string(),
+                        dims: vec![0; indices.len()],
+                        public: (wire as usize) < public_end,
+                    });
+                    inputs.len() - 1
+                }
+            };
+
+            for (dim, index) in inputs[pos].dims.iter_mut().zip(indices) {
+                *dim = (*dim).max(index + 1);
+            }
+        }
+
+        Self {
+            curve: curve_name(&header.prime_size).to_string(),
+            num_constraints: header.n_constraints as usize,
+            num_public_outputs: header.n_pub_out as usize,
+            num_public_inputs: header.n_pub_in as usize,
+            num_private_inputs: header.n_prv_in as usize,
+            num_wires: header.n_wires as usize,
+            num_labels: header.n_labels as usize,
+            inputs,
+        }
+    }
+
+    /// Read the info of an r1cs file and the `.sym` file next to it
+    pub fn load(r1cs: impl AsRef<Path>) -> Result<Self, String> {
+        let bytes = std::fs::read(&r1cs).map_err(|e| e.to_string())?;
+        let file = R1CSFile::<Bn254>::new(Cursor::new(bytes)).map_err(|e| e.to_string())?;
+        let symbols = SymbolTable::for_r1cs(&r1cs);
+
+        Ok(Self::new(&file, symbols.as_ref()))
+    }
+
+    pub fn input(&self, name: &str) -> Option<&InputSignal> {
+        self.inputs.iter().find(|i| i.name == name)
+    }
+
+    pub fn to_mpack(&self) -> Result<Vec<u8>, String> {
+        let inputs = self
+            .inputs
+            .iter()
+            .map(|input| {
+                Value::Map(vec![
+                    ("name".into(), input.name.as_str().into()),
+                    (
+                        "dims".into(),
+                        Value::Array(input.dims.iter().map(|d| (*d as u64).into()).collect()),
+                    ),
+                    ("public".into(), input.public.into()),
+                ])
+            })
+            .collect();
+
+        let info = Value::Map(vec![
+            ("curve".into(), self.curve.as_str().into()),
+            ("constraints".into(), (self.num_constraints as u64).into()),
+            ("public_outputs".into(), (self.num_public_outputs as u64).into()),
+            ("public_inputs".into(), (self.num_public_inputs as u64).into()),
+            ("private_inputs".into(), (self.num_private_inputs as u64).into()),
+            ("wires".into(), (self.num_wires as u64).into()),
+            ("labels".into(), (self.num_labels as u64).into()),
+            ("inputs".into(), Value::Array(inputs)),
+        ]);
+
+        let mut bytes = Vec::new();
+        write_value(&mut bytes, &info).map_err(|e| e.to_string())?;
+        Ok(bytes)
+    }
+}
+
+fn curve_name(prime: &[u8]) -> &'static str {
+    let bn254: Vec<u8> = Fr::characteristic()
+        .iter()
+        .flat_map(|limb| limb.to_le_bytes())
+        .collect();
+
+    if prime == bn254.as_slice() {
+        CURVE_BN128
+    } else {
+        "unknown"
+    }
+}
+
+/// `chunks[1][2]` is split into `chunks` and `[1, 2]`
+fn split_indices(name: &str) -> (&str, Vec<usize>) {
+    let (base, rest) = match name.find('[') {
+        Some(pos) => name.split_at(pos),
+        None => return (name, Vec::new()),
+    };
+
+    let indices = rest
+        .split(['[', ']'])
+        .filter(|s| !s.is_empty())
+        .filter_map(|s| s.parse().ok())
+        .collect();
+
+    (base, indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::split_indices;
+
+    #[test]
+    fn test_split_indices() {
+        assert_eq!(split_indices("root"), ("root", vec![]));
+        assert_eq!(split_indices("chunks[3][255]"), ("chunks", vec![3, 255]));
+    }
+}
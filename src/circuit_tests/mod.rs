@@ -2,19 +2,34 @@ pub mod utils;
 
 #[cfg(test)]
 mod test {
-    use ark_bn254::Bn254;
+    use ark_bn254::{Bn254, Fr};
     use ark_circom::{CircomBuilder, CircomConfig};
     use ark_groth16::{
         create_random_proof as prove, generate_random_parameters, prepare_inputs,
-        prepare_verifying_key, verify_proof_with_prepared_inputs, ProvingKey,
+        prepare_verifying_key, verify_proof_with_prepared_inputs, Proof, ProvingKey,
     };
     use ark_std::rand::{distributions::Alphanumeric, rngs::ThreadRng, Rng};
     use rs_poseidon::poseidon::hash;
     use ruint::aliases::U256;
 
+    use ark_circom::circom::{R1CSFile, R1CS};
+
+    use std::fs::File;
+    use std::sync::{mpsc, Arc};
+
     use crate::{
+        batch::verify_batch,
+        circuit_info::{CircuitInfo, CURVE_BN128},
         circuit_tests::utils::{digest, treehash},
-        storage_proofs::StorageProofs,
+        jobs::{ProofJob, JOB_DONE, JOB_QUEUED},
+        native_witness::{input_wires, NativeWitness},
+        proof_system::ProofSystem,
+        registry::{CircuitParams, ProverRegistry},
+        storage_proofs::{witness_inputs, StorageProofInput, StorageProofs},
+        symbols::SymbolTable,
+        witness::{push_input, WitnessBackend, WitnessInputs, WitnessPool},
+        wtns::{read_wtns, write_wtns},
+        zkey::ZkeyCheck,
     };
 
     pub struct CircuitsTests {
@@ -51,6 +66,22 @@ mod test {
             verify_proof_with_prepared_inputs(&vk, &proof, &public_inputs).is_ok()
         }
 
+        pub fn poseidon_hash_proof(
+            &mut self,
+            elements: &[U256],
+            hash: U256,
+        ) -> (Proof<Bn254>, Vec<Fr>) {
+            let mut builder = self.builder.clone();
+
+            elements.iter().for_each(|c| builder.push_input("in", *c));
+            builder.push_input("hash", hash);
+
+            let circuit = builder.build().unwrap();
+            let inputs = circuit.get_public_inputs().unwrap();
+            let proof = prove(circuit, &self.params, &mut self.rng).unwrap();
+            (proof, inputs)
+        }
+
         pub fn poseidon_digest(&mut self, elements: &[U256], hash: U256) -> bool {
             let mut builder = self.builder.clone();
 
@@ -79,6 +110,24 @@ mod test {
         assert!(hasher.poseidon_hash(&[U256::from(1)], hash(&[U256::from(1)])));
     }
 
+    #[test]
+    fn test_batch_verify() {
+        let r1cs = "./src/circuit_tests/artifacts/poseidon-hash-test.r1cs";
+        let wasm = "./src/circuit_tests/artifacts/poseidon-hash-test_js/poseidon-hash-test.wasm";
+
+        let mut hasher = CircuitsTests::new(wasm.to_string(), r1cs.to_string());
+        let mut proofs = (1..5)
+            .map(|i| hasher.poseidon_hash_proof(&[U256::from(i)], hash(&[U256::from(i)])))
+            .collect::<Vec<_>>();
+        let vk = prepare_verifying_key(&hasher.params.vk);
+
+        assert!(verify_batch(&vk, &proofs, &mut hasher.rng).is_ok());
+
+        // a valid proof presented with someone else's inputs
+        proofs[2].1 = proofs[1].1.clone();
+        assert_eq!(verify_batch(&vk, &proofs, &mut hasher.rng), Err(vec![2]));
+    }
+
     #[test]
     fn test_poseidon_digest() {
         let r1cs = "./src/circuit_tests/artifacts/poseidon-digest-test.r1cs";
@@ -90,12 +139,7 @@ mod test {
         assert!(hasher.poseidon_digest(&input, digest(&input, Some(16))));
     }
 
-    #[test]
-    fn test_storer() {
-        let r1cs = "./src/circuit_tests/artifacts/storer-test.r1cs";
-        let wasm = "./src/circuit_tests/artifacts/storer-test_js/storer-test.wasm";
-        let mut prover = StorageProofs::new(wasm.to_string(), r1cs.to_string(), None);
-
+    fn storer_input(salt: U256) -> StorageProofInput {
         // generate a tuple of (preimages, hash), where preimages is a vector of 256 U256s
         // and hash is the hash of each vector generated using the digest function
         let data = (0..4)
@@ -118,7 +162,7 @@ mod test {
         let parent_hash_l = hash(&[hashes[0], hashes[1]]);
         let parent_hash_r = hash(&[hashes[2], hashes[3]]);
 
-        let siblings = &[
+        let siblings = vec![
             hashes[1],
             parent_hash_r,
             hashes[0],
@@ -130,24 +174,499 @@ mod test {
         ];
 
         let root = treehash(hashes.as_slice());
-        // let proof_bytes = &mut Vec::new();
-        // let public_inputs_bytes = &mut Vec::new();
-
-        // prover
-        //     .prove(
-        //         chunks.as_slice(),
-        //         siblings,
-        //         hashes.as_slice(),
-        //         path.as_slice(),
-        //         root,
-        //         root, // random salt - block hash
-        //         proof_bytes,
-        //         public_inputs_bytes,
-        //     )
-        //     .unwrap();
-
-        // assert!(prover
-        //     .verify(proof_bytes.as_slice(), public_inputs_bytes.as_slice())
-        //     .is_ok());
+
+        StorageProofInput {
+            chunks,
+            siblings,
+            hashes,
+            path,
+            root,
+            salt, // random salt - block hash
+        }
+    }
+
+    #[test]
+    fn test_storer() {
+        let r1cs = "./src/circuit_tests/artifacts/storer-test.r1cs";
+        let wasm = "./src/circuit_tests/artifacts/storer-test_js/storer-test.wasm";
+        let prover = StorageProofs::new(wasm.to_string(), r1cs.to_string(), None, 0);
+
+        let input = storer_input(U256::from(42));
+        let (root, salt) = (input.root, input.salt);
+        let proof_bytes = &mut Vec::new();
+        let public_inputs_bytes = &mut Vec::new();
+
+        let inputs = prover
+            .prove(
+                input.chunks.as_slice(),
+                input.siblings.as_slice(),
+                input.hashes.as_slice(),
+                input.path.as_slice(),
+                root,
+                salt,
+                proof_bytes,
+                public_inputs_bytes,
+            )
+            .unwrap();
+
+        assert_eq!(inputs.root, root);
+        assert_eq!(inputs.salt, salt);
+        assert!(prover
+            .verify_storage_proof(proof_bytes.as_slice(), root, salt, None)
+            .is_ok());
+
+        // a valid proof for another challenge must not verify
+        assert!(prover
+            .verify_storage_proof(proof_bytes.as_slice(), root, U256::from(43), None)
+            .is_err());
+        assert!(prover
+            .verify_storage_proof(proof_bytes.as_slice(), input.hashes[0], salt, None)
+            .is_err());
+    }
+
+    #[test]
+    fn test_prove_many() {
+        let r1cs = "./src/circuit_tests/artifacts/storer-test.r1cs";
+        let wasm = "./src/circuit_tests/artifacts/storer-test_js/storer-test.wasm";
+        let prover = StorageProofs::new(wasm.to_string(), r1cs.to_string(), None, 1);
+        prover.set_num_threads(2).unwrap();
+        assert_eq!(prover.num_threads(), 2);
+        prover.set_witness_instances(2);
+        assert_eq!(prover.witness_instances(), 2);
+
+        let inputs = (0..3)
+            .map(|i| storer_input(U256::from(i)))
+            .collect::<Vec<StorageProofInput>>();
+        let proofs = prover.prove_many(&inputs);
+
+        assert_eq!(proofs.len(), inputs.len());
+        for (input, proof) in inputs.iter().zip(proofs) {
+            let (proof_bytes, _) = proof.unwrap();
+            assert!(prover
+                .verify_storage_proof(proof_bytes.as_slice(), input.root, input.salt, None)
+                .is_ok());
+        }
+    }
+
+    #[test]
+    fn test_proof_job() {
+        let r1cs = "./src/circuit_tests/artifacts/storer-test.r1cs";
+        let wasm = "./src/circuit_tests/artifacts/storer-test_js/storer-test.wasm";
+        let prover = Arc::new(StorageProofs::new(wasm.to_string(), r1cs.to_string(), None, 0));
+
+        let inputs = std::fs::read("./tests/proof_test.mpack").unwrap();
+        let (tx, rx) = mpsc::channel();
+        let job = ProofJob::submit(
+            prover.clone(),
+            inputs,
+            Some(Box::new(move |status| tx.send(status).unwrap())),
+        )
+        .unwrap();
+
+        assert_eq!(rx.recv().unwrap(), JOB_DONE);
+        assert_eq!(job.status(), JOB_DONE);
+
+        let (_, _, envelope) = job.take_result().unwrap().unwrap();
+        assert!(prover.verify_envelope(&envelope).is_ok());
+        assert!(job.take_result().is_none());
+
+        // jobs run on the prover's pool, they wait while its only thread is busy
+        let prover = Arc::new(StorageProofs::new(wasm.to_string(), r1cs.to_string(), None, 1));
+        let inputs = std::fs::read("./tests/proof_test.mpack").unwrap();
+        let (release, blocked) = mpsc::channel::<()>();
+        prover.pool().spawn(move || blocked.recv().unwrap());
+        let (tx, rx) = mpsc::channel();
+        let jobs: Vec<ProofJob> = (0..2)
+            .map(|_| {
+                let tx = tx.clone();
+                ProofJob::submit(
+                    prover.clone(),
+                    inputs.clone(),
+                    Some(Box::new(move |status| tx.send(status).unwrap())),
+                )
+                .unwrap()
+            })
+            .collect();
+
+        assert!(jobs.iter().all(|job| job.status() == JOB_QUEUED));
+        release.send(()).unwrap();
+        assert_eq!(rx.recv().unwrap(), JOB_DONE);
+        assert_eq!(rx.recv().unwrap(), JOB_DONE);
+    }
+
+    /// Witnesses computed by the wasm and by the native generator
+    fn witnesses(r1cs: &str, wasm: &str, inputs: WitnessInputs) -> (Vec<Fr>, Vec<Fr>) {
+        let file = R1CSFile::<Bn254>::new(File::open(r1cs).unwrap()).unwrap();
+        let wires = input_wires(&file);
+        let r1cs_data: R1CS<Bn254> = file.into();
+        let symbols = SymbolTable::for_r1cs(r1cs).unwrap();
+
+        let native = NativeWitness::new(&r1cs_data, wires, &symbols).unwrap();
+        let pool = WitnessPool::new(wasm).unwrap();
+
+        (
+            pool.calculate(inputs.clone()).unwrap(),
+            native.calculate(&r1cs_data, &inputs).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_native_witness() {
+        let r1cs = "./src/circuit_tests/artifacts/storer-test.r1cs";
+        let wasm = "./src/circuit_tests/artifacts/storer-test_js/storer-test.wasm";
+
+        let inputs = storer_input(U256::from(42)).witness_inputs();
+        let (wasm_witness, native_witness) = witnesses(r1cs, wasm, inputs);
+        assert_eq!(wasm_witness, native_witness);
+
+        let r1cs = "./src/circuit_tests/artifacts/poseidon-digest-test.r1cs";
+        let wasm =
+            "./src/circuit_tests/artifacts/poseidon-digest-test_js/poseidon-digest-test.wasm";
+
+        let block: Vec<U256> = (0..256).map(|c| U256::from(c)).collect();
+        let mut inputs = WitnessInputs::new();
+        block.iter().for_each(|c| push_input(&mut inputs, "block", *c));
+        push_input(&mut inputs, "hash", digest(&block, Some(16)));
+        let (wasm_witness, native_witness) = witnesses(r1cs, wasm, inputs);
+        assert_eq!(wasm_witness, native_witness);
+    }
+
+    #[test]
+    fn test_native_prover() {
+        let r1cs = "./src/circuit_tests/artifacts/storer-test.r1cs";
+        let prover =
+            StorageProofs::with_witness(WitnessBackend::Native, r1cs.to_string(), None, 0);
+
+        let input = storer_input(U256::from(42));
+        let proof_bytes = &mut Vec::new();
+        let public_inputs_bytes = &mut Vec::new();
+        prover
+            .prove_input(&input, proof_bytes, public_inputs_bytes)
+            .unwrap();
+
+        assert!(prover
+            .verify_storage_proof(proof_bytes.as_slice(), input.root, input.salt, None)
+            .is_ok());
+
+        // a wrong hash fails the digest constraint instead of producing a proof
+        let mut input = input;
+        input.hashes[0] = input.hashes[1];
+        assert!(prover
+            .prove_input(&input, &mut Vec::new(), &mut Vec::new())
+            .is_err());
+    }
+
+    #[test]
+    fn test_marlin_prover() {
+        let r1cs = "./src/circuit_tests/artifacts/storer-test.r1cs";
+        let wasm = "./src/circuit_tests/artifacts/storer-test_js/storer-test.wasm";
+        let prover = StorageProofs::with_proof_system(
+            WitnessBackend::Wasm(wasm.to_string()),
+            r1cs.to_string(),
+            ProofSystem::Marlin(None),
+            0,
+        )
+        .unwrap();
+        assert_eq!(prover.proof_system(), "Marlin");
+
+        let input = storer_input(U256::from(42));
+        let proof_bytes = &mut Vec::new();
+        let public_inputs_bytes = &mut Vec::new();
+        prover
+            .prove_input(&input, proof_bytes, public_inputs_bytes)
+            .unwrap();
+
+        assert!(prover
+            .verify(proof_bytes.as_slice(), public_inputs_bytes.as_slice())
+            .is_ok());
+        assert!(prover
+            .verify_storage_proof(proof_bytes.as_slice(), input.root, input.salt, None)
+            .is_ok());
+        assert!(prover
+            .verify_storage_proof(proof_bytes.as_slice(), input.root, U256::from(43), None)
+            .is_err());
+
+        let envelope = prover
+            .envelope(proof_bytes.as_slice(), public_inputs_bytes.as_slice())
+            .unwrap()
+            .to_bytes()
+            .unwrap();
+        assert!(prover.verify_envelope(&envelope).is_ok());
+        assert_eq!(
+            prover.verify_batch(&[
+                (proof_bytes.as_slice(), public_inputs_bytes.as_slice()),
+                (&proof_bytes[1..], public_inputs_bytes.as_slice()),
+            ]),
+            Err(vec![1])
+        );
+
+        // the Groth16 only features fail instead of using the wrong keys
+        assert!(prover.verifier().is_err());
+        assert!(prover.write_zkey(Vec::new()).is_err());
+        assert!(prover.rerandomize(proof_bytes.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_marlin_load_errors() {
+        let wasm = "./src/circuit_tests/artifacts/storer-test_js/storer-test.wasm";
+        let err = StorageProofs::with_proof_system(
+            WitnessBackend::Wasm(wasm.to_string()),
+            "missing.r1cs".to_string(),
+            ProofSystem::Marlin(None),
+            0,
+        )
+        .err()
+        .unwrap();
+        assert!(err.starts_with("missing.r1cs"));
+    }
+
+    #[cfg(feature = "nova")]
+    #[test]
+    fn test_incremental() {
+        use crate::incremental::{IncrementalProof, IncrementalProver};
+
+        let r1cs = "./src/circuit_tests/artifacts/storer-step-test.r1cs";
+        let wasm = "./src/circuit_tests/artifacts/storer-step-test_js/storer-step-test.wasm";
+        let prover = IncrementalProver::new(wasm, r1cs).unwrap();
+
+        let periods = (0..3)
+            .map(|i| storer_input(U256::from(i)))
+            .collect::<Vec<StorageProofInput>>();
+        let challenges = periods
+            .iter()
+            .map(|p| (p.root, p.salt))
+            .collect::<Vec<_>>();
+
+        let proof = prover.prove(&periods).unwrap();
+        let proof = IncrementalProof::from_bytes(&proof.to_bytes().unwrap()).unwrap();
+        assert_eq!(proof.num_periods(), 3);
+        assert!(prover.verify(&proof, &challenges).is_ok());
+
+        // the challenges have to match, in order
+        let mut swapped = challenges.clone();
+        swapped.swap(0, 1);
+        assert!(prover.verify(&proof, &swapped).is_err());
+        assert!(prover.verify(&proof, &challenges[..2]).is_err());
+    }
+
+    #[test]
+    fn test_prove_wtns() {
+        let r1cs = "./src/circuit_tests/artifacts/storer-test.r1cs";
+        let wasm = "./src/circuit_tests/artifacts/storer-test_js/storer-test.wasm";
+        let prover = StorageProofs::new(wasm.to_string(), r1cs.to_string(), None, 0);
+
+        let input = storer_input(U256::from(42));
+        let wtns = prover.export_wtns(&input).unwrap();
+
+        let proof_bytes = &mut Vec::new();
+        let public_inputs_bytes = &mut Vec::new();
+        let inputs = prover
+            .prove_wtns(&wtns, proof_bytes, public_inputs_bytes)
+            .unwrap();
+
+        assert_eq!(inputs.root, input.root);
+        assert!(prover
+            .verify_storage_proof(proof_bytes.as_slice(), input.root, input.salt, None)
+            .is_ok());
+
+        // a witness for another circuit
+        let wtns = write_wtns(&[Fr::from(1u64); 3]);
+        assert!(prover
+            .prove_wtns(&wtns, &mut Vec::new(), &mut Vec::new())
+            .is_err());
+
+        // a witness that doesn't satisfy the circuit is caught before proving
+        let witness = read_wtns(&prover.export_wtns(&input).unwrap()).unwrap();
+        let mut tampered = witness.clone();
+        tampered[0] = Fr::from(2u64);
+        assert_eq!(
+            prover.prove_wtns(&write_wtns(&tampered), &mut Vec::new(), &mut Vec::new()),
+            Err("wire 0 of the witness must be 1".to_string())
+        );
+
+        // wire 1 is the root, the Merkle paths no longer lead to it
+        let mut tampered = witness;
+        tampered[1] += Fr::from(1u64);
+        let err = prover
+            .prove_wtns(&write_wtns(&tampered), &mut Vec::new(), &mut Vec::new())
+            .unwrap_err();
+        assert!(err.starts_with("constraint "));
+    }
+
+    #[test]
+    fn test_check_inputs() {
+        let r1cs = "./src/circuit_tests/artifacts/storer-test.r1cs";
+        let wasm = "./src/circuit_tests/artifacts/storer-test_js/storer-test.wasm";
+        let prover = StorageProofs::new(wasm.to_string(), r1cs.to_string(), None, 0);
+
+        let mut input = storer_input(U256::from(42));
+        assert!(prover.check_inputs(&input, 10).unwrap().is_empty());
+
+        // a wrong root only breaks the Merkle proofs, not the digests
+        input.root = input.hashes[0];
+        let failures = prover.check_inputs(&input, 10).unwrap();
+        assert!(!failures.is_empty());
+        assert!(failures.len() <= 10);
+        assert!(failures.iter().all(|f| f
+            .signals
+            .iter()
+            .any(|s| s == "main.root" || s.starts_with("main.merkelizer["))));
+    }
+
+    #[test]
+    fn test_check_paths() {
+        let r1cs = "./src/circuit_tests/artifacts/storer-test.r1cs";
+        let wasm = "./src/circuit_tests/artifacts/storer-test_js/storer-test.wasm";
+        let prover = StorageProofs::new(wasm.to_string(), r1cs.to_string(), None, 0);
+        assert!(!prover.check_paths());
+        prover.set_check_paths(true);
+
+        let mut input = storer_input(U256::from(42));
+        let proof_bytes = &mut Vec::new();
+        let public_inputs_bytes = &mut Vec::new();
+        assert!(prover
+            .prove_input(&input, proof_bytes, public_inputs_bytes)
+            .is_ok());
+
+        // swapping two paths breaks both queries, the first one is reported
+        input.path.swap(1, 2);
+        assert_eq!(
+            prover.prove_input(&input, proof_bytes, public_inputs_bytes),
+            Err("query 1: path doesn't lead to the root".to_string())
+        );
+
+        // inputs that don't go through `prove`, like mpack ones, are checked too
+        let inputs = witness_inputs(
+            &input.chunks,
+            &input.siblings,
+            &input.hashes,
+            &input.path,
+            input.root,
+            input.salt,
+        );
+        assert_eq!(
+            prover.prove_witness_inputs(inputs, proof_bytes, public_inputs_bytes),
+            Err("query 1: path doesn't lead to the root".to_string())
+        );
+    }
+
+    #[test]
+    fn test_circuit_info() {
+        let r1cs = "./src/circuit_tests/artifacts/storer-test.r1cs";
+        let info = CircuitInfo::load(r1cs).unwrap();
+
+        // StorageProver(256, 4, 2, 16)
+        assert_eq!(info.curve, CURVE_BN128);
+        assert_eq!(info.num_public_outputs, 0);
+        assert_eq!(info.num_public_inputs, 2);
+        assert_eq!(info.num_private_inputs, 4 * 256 + 4 * 2 + 4 + 4);
+        assert!(info.num_constraints > 0);
+
+        let chunks = info.input("chunks").unwrap();
+        assert_eq!(chunks.dims, vec![4, 256]);
+        assert!(!chunks.public);
+        assert_eq!(info.input("siblings").unwrap().dims, vec![4, 2]);
+
+        let root = info.input("root").unwrap();
+        assert!(root.dims.is_empty());
+        assert!(root.public);
+    }
+
+    #[test]
+    fn test_registry() {
+        let r1cs = "./src/circuit_tests/artifacts/storer-test.r1cs";
+        let wasm = "./src/circuit_tests/artifacts/storer-test_js/storer-test.wasm";
+        let params = CircuitParams::new(256, 4, 2, 16);
+
+        let mut registry = ProverRegistry::new(0);
+        registry
+            .register(params, wasm.to_string(), r1cs.to_string(), None)
+            .unwrap();
+        assert!(registry
+            .register(params, wasm.to_string(), r1cs.to_string(), None)
+            .is_err());
+        assert!(!registry.is_loaded(&params));
+
+        let input = storer_input(U256::from(42));
+        let proof_bytes = &mut Vec::new();
+        let public_inputs_bytes = &mut Vec::new();
+        registry
+            .prove_input(&input, proof_bytes, public_inputs_bytes)
+            .unwrap();
+        assert!(registry.is_loaded(&params));
+
+        let prover = registry.prover_by_id("256_4_2_16").unwrap();
+        assert!(prover
+            .verify_storage_proof(proof_bytes.as_slice(), input.root, input.salt, None)
+            .is_ok());
+
+        // no circuit proves a single query
+        let mut input = input;
+        input.hashes.truncate(1);
+        assert!(registry.select(&input).is_err());
+
+        // the circuit doesn't match the parameters it's registered with
+        let params = CircuitParams::new(256, 4, 3, 16);
+        let mut registry = ProverRegistry::new(0);
+        registry
+            .register(params, wasm.to_string(), r1cs.to_string(), None)
+            .unwrap();
+        assert!(registry.prover(&params).is_err());
+        assert!(!registry.is_loaded(&params));
+    }
+
+    #[test]
+    fn test_zkey_check() {
+        let r1cs = "./src/circuit_tests/artifacts/storer-test.r1cs";
+        let wasm = "./src/circuit_tests/artifacts/storer-test_js/storer-test.wasm";
+        let other_r1cs = "./src/circuit_tests/artifacts/poseidon-hash-test.r1cs";
+        let other_wasm =
+            "./src/circuit_tests/artifacts/poseidon-hash-test_js/poseidon-hash-test.wasm";
+        let dir = std::env::temp_dir();
+        let zkey = dir.join("storer-test-check.zkey");
+        let other_zkey = dir.join("poseidon-hash-test-check.zkey");
+
+        let prover = StorageProofs::new(wasm.to_string(), r1cs.to_string(), None, 0);
+        prover.write_zkey(File::create(&zkey).unwrap()).unwrap();
+        let other = StorageProofs::new(other_wasm.to_string(), other_r1cs.to_string(), None, 0);
+        other.write_zkey(File::create(&other_zkey).unwrap()).unwrap();
+
+        let load = |zkey: &std::path::Path| {
+            StorageProofs::load(
+                WitnessBackend::Wasm(wasm.to_string()),
+                r1cs.to_string(),
+                Some(zkey.to_str().unwrap().to_string()),
+                0,
+                ZkeyCheck::Strict,
+            )
+        };
+
+        let loaded = load(&zkey).unwrap();
+        assert_eq!(loaded.vk_hash(), prover.vk_hash());
+
+        let err = load(&other_zkey).err().unwrap();
+        assert!(err.contains("poseidon-hash-test-check.zkey"), "{}", err);
+    }
+
+    #[test]
+    fn test_load_errors() {
+        let r1cs = "./src/circuit_tests/artifacts/storer-test.r1cs";
+        let wasm = "./src/circuit_tests/artifacts/storer-test_js/storer-test.wasm";
+        let load = |backend: WitnessBackend, r1cs: &str| {
+            StorageProofs::load(backend, r1cs.to_string(), None, 0, ZkeyCheck::Shape)
+        };
+
+        let err = load(WitnessBackend::Wasm(wasm.to_string()), "missing.r1cs")
+            .err()
+            .unwrap();
+        assert!(err.starts_with("missing.r1cs"), "{}", err);
+
+        let err = load(WitnessBackend::Wasm("missing.wasm".to_string()), r1cs)
+            .err()
+            .unwrap();
+        assert!(err.starts_with("missing.wasm"), "{}", err);
+
+        // the wasm isn't an r1cs
+        assert!(load(WitnessBackend::Wasm(wasm.to_string()), wasm).is_err());
     }
 }
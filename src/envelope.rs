@@ -0,0 +1,271 @@
+//! Versioned proof envelope
+//!
+//! A raw Groth16 proof says nothing about the circuit or key that produced it.
+//! The envelope bundles the proof with a circuit identifier, the verifying-key
+//! hash and the named public inputs, so a verifier can reject a proof for the
+//! wrong circuit before it ever runs the pairing check.
+
+use std::fmt;
+
+use ark_bn254::Bn254;
+use ark_circom::circom::R1CS;
+use ark_groth16::VerifyingKey;
+use ark_serialize::CanonicalSerialize;
+use rmpv::decode::read_value;
+use rmpv::encode::write_value;
+use rmpv::Value;
+use ruint::aliases::U256;
+use sha2::{Digest, Sha256};
+
+use crate::public_inputs::PublicInputs;
+use crate::storage_proofs::EXT_ID_U256_LE;
+
+pub const ENVELOPE_VERSION: u64 = 1;
+
+// Status codes returned by the `verify_envelope` FFI call
+pub const ENVELOPE_OK: i32 = 0;
+pub const ENVELOPE_MALFORMED: i32 = 1;
+pub const ENVELOPE_UNSUPPORTED_VERSION: i32 = 2;
+pub const ENVELOPE_CIRCUIT_MISMATCH: i32 = 3;
+pub const ENVELOPE_VK_MISMATCH: i32 = 4;
+pub const ENVELOPE_INVALID_PROOF: i32 = 5;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EnvelopeError {
+    Malformed(String),
+    UnsupportedVersion(u64),
+    CircuitMismatch,
+    VerifyingKeyMismatch,
+    InvalidProof,
+}
+
+impl EnvelopeError {
+    pub fn code(&self) -> i32 {
+        match self {
+            EnvelopeError::Malformed(_) => ENVELOPE_MALFORMED,
+            EnvelopeError::UnsupportedVersion(_) => ENVELOPE_UNSUPPORTED_VERSION,
+            EnvelopeError::CircuitMismatch => ENVELOPE_CIRCUIT_MISMATCH,
+            EnvelopeError::VerifyingKeyMismatch => ENVELOPE_VK_MISMATCH,
+            EnvelopeError::InvalidProof => ENVELOPE_INVALID_PROOF,
+        }
+    }
+}
+
+impl fmt::Display for EnvelopeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnvelopeError::Malformed(e) => write!(f, "malformed proof envelope: {}", e),
+            EnvelopeError::UnsupportedVersion(v) => {
+                write!(f, "unsupported proof envelope version {}", v)
+            }
+            EnvelopeError::CircuitMismatch => write!(f, "proof was made for a different circuit"),
+            EnvelopeError::VerifyingKeyMismatch => {
+                write!(f, "proof was made with a different verifying key")
+            }
+            EnvelopeError::InvalidProof => write!(f, "proof verification failed"),
+        }
+    }
+}
+
+impl std::error::Error for EnvelopeError {}
+
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(data).into()
+}
+
+pub fn vk_hash(vk: &VerifyingKey<Bn254>) -> Result<[u8; 32], String> {
+    let mut bytes = Vec::new();
+    vk.serialize(&mut bytes).map_err(|e| e.to_string())?;
+    Ok(sha256(&bytes))
+}
+
+/// Identifies a circuit by its r1cs shape and the hash of the r1cs file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CircuitId {
+    pub num_constraints: u64,
+    pub num_public_inputs: u64,
+    pub num_wires: u64,
+    pub r1cs_hash: [u8; 32],
+}
+
+impl CircuitId {
+    pub fn new(r1cs: &R1CS<Bn254>, r1cs_bytes: &[u8]) -> Self {
+        Self {
+            num_constraints: r1cs.constraints.len() as u64,
+            // the first input is the implicit `one` wire
+            num_public_inputs: (r1cs.num_inputs - 1) as u64,
+            num_wires: r1cs.num_variables as u64,
+            r1cs_hash: sha256(r1cs_bytes),
+        }
+    }
+
+    fn to_mpack(&self) -> Value {
+        Value::Map(vec![
+            ("constraints".into(), self.num_constraints.into()),
+            ("public_inputs".into(), self.num_public_inputs.into()),
+            ("wires".into(), self.num_wires.into()),
+            ("r1cs_hash".into(), Value::Binary(self.r1cs_hash.to_vec())),
+        ])
+    }
+
+    fn from_mpack(val: &Value) -> Result<Self, EnvelopeError> {
+        Ok(Self {
+            num_constraints: get_u64(val, "constraints")?,
+            num_public_inputs: get_u64(val, "public_inputs")?,
+            num_wires: get_u64(val, "wires")?,
+            r1cs_hash: get_hash(val, "r1cs_hash")?,
+        })
+    }
+}
+
+/// A proof together with the circuit, key and public inputs it was made for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProofEnvelope {
+    pub version: u64,
+    pub circuit_id: CircuitId,
+    pub vk_hash: [u8; 32],
+    pub public_inputs: PublicInputs,
+    pub proof: Vec<u8>,
+}
+
+impl ProofEnvelope {
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        let inputs = self
+            .public_inputs
+            .pairs()
+            .iter()
+            .map(|(name, val)| {
+                (
+                    Value::from(name.as_str()),
+                    Value::Ext(EXT_ID_U256_LE, val.to_le_bytes_vec()),
+                )
+            })
+            .collect();
+
+        let envelope = Value::Map(vec![
+            ("version".into(), self.version.into()),
+            ("circuit".into(), self.circuit_id.to_mpack()),
+            ("vk_hash".into(), Value::Binary(self.vk_hash.to_vec())),
+            ("inputs".into(), Value::Map(inputs)),
+            ("proof".into(), Value::Binary(self.proof.clone())),
+        ]);
+
+        let mut bytes = Vec::new();
+        write_value(&mut bytes, &envelope).map_err(|e| e.to_string())?;
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, EnvelopeError> {
+        let val = read_value(&mut bytes).map_err(|e| EnvelopeError::Malformed(e.to_string()))?;
+
+        // check the version first, later versions may change the layout
+        let version = get_u64(&val, "version")?;
+        if version != ENVELOPE_VERSION {
+            return Err(EnvelopeError::UnsupportedVersion(version));
+        }
+
+        let inputs = get(&val, "inputs")?
+            .as_map()
+            .ok_or_else(|| EnvelopeError::Malformed("inputs must be a map".to_string()))?;
+        let mut public_inputs = Vec::with_capacity(inputs.len());
+        for (name, val) in inputs {
+            let name = name
+                .as_str()
+                .ok_or_else(|| EnvelopeError::Malformed("expected input name".to_string()))?;
+            let val = match val {
+                Value::Ext(EXT_ID_U256_LE, bytes) => U256::try_from_le_slice(bytes),
+                _ => None,
+            }
+            .ok_or_else(|| EnvelopeError::Malformed(format!("bad value for input {}", name)))?;
+            public_inputs.push((name.to_string(), val));
+        }
+
+        Ok(Self {
+            version,
+            circuit_id: CircuitId::from_mpack(get(&val, "circuit")?)?,
+            vk_hash: get_hash(&val, "vk_hash")?,
+            public_inputs: PublicInputs::from_pairs(public_inputs),
+            proof: get_binary(&val, "proof")?.to_vec(),
+        })
+    }
+}
+
+fn get<'a>(val: &'a Value, key: &str) -> Result<&'a Value, EnvelopeError> {
+    val.as_map()
+        .and_then(|m| m.iter().find(|(k, _)| k.as_str() == Some(key)))
+        .map(|(_, v)| v)
+        .ok_or_else(|| EnvelopeError::Malformed(format!("missing field {}", key)))
+}
+
+fn get_u64(val: &Value, key: &str) -> Result<u64, EnvelopeError> {
+    get(val, key)?
+        .as_u64()
+        .ok_or_else(|| EnvelopeError::Malformed(format!("{} must be an integer", key)))
+}
+
+fn get_binary<'a>(val: &'a Value, key: &str) -> Result<&'a [u8], EnvelopeError> {
+    get(val, key)?
+        .as_slice()
+        .ok_or_else(|| EnvelopeError::Malformed(format!("{} must be binary", key)))
+}
+
+fn get_hash(val: &Value, key: &str) -> Result<[u8; 32], EnvelopeError> {
+    get_binary(val, key)?
+        .try_into()
+        .map_err(|_| EnvelopeError::Malformed(format!("{} must be 32 bytes", key)))
+}
+
+#[cfg(test)]
+mod tests {
+    use ruint::aliases::U256;
+
+    use super::{CircuitId, EnvelopeError, ProofEnvelope, ENVELOPE_VERSION};
+    use crate::public_inputs::PublicInputs;
+
+    fn envelope() -> ProofEnvelope {
+        ProofEnvelope {
+            version: ENVELOPE_VERSION,
+            circuit_id: CircuitId {
+                num_constraints: 1024,
+                num_public_inputs: 2,
+                num_wires: 2048,
+                r1cs_hash: [1u8; 32],
+            },
+            vk_hash: [2u8; 32],
+            public_inputs: PublicInputs::from_pairs(vec![
+                ("root".to_string(), U256::from(42)),
+                ("salt".to_string(), U256::from(7)),
+            ]),
+            proof: vec![3u8; 128],
+        }
+    }
+
+    #[test]
+    fn test_envelope_roundtrip() {
+        let envelope = envelope();
+        let bytes = envelope.to_bytes().unwrap();
+
+        assert_eq!(ProofEnvelope::from_bytes(&bytes).unwrap(), envelope);
+        assert_eq!(envelope.public_inputs.get("salt"), Some(U256::from(7)));
+    }
+
+    #[test]
+    fn test_envelope_unsupported_version() {
+        let mut envelope = envelope();
+        envelope.version = ENVELOPE_VERSION + 1;
+        let bytes = envelope.to_bytes().unwrap();
+
+        assert_eq!(
+            ProofEnvelope::from_bytes(&bytes),
+            Err(EnvelopeError::UnsupportedVersion(ENVELOPE_VERSION + 1))
+        );
+    }
+
+    #[test]
+    fn test_envelope_malformed() {
+        assert!(matches!(
+            ProofEnvelope::from_bytes(&[0xc1]),
+            Err(EnvelopeError::Malformed(_))
+        ));
+    }
+}
@@ -1,7 +1,19 @@
+use ark_std::rand::rngs::ThreadRng;
 use ruint::aliases::U256;
 
-use crate::storage_proofs::StorageProofs;
+use crate::aggregate::AggregationSrs;
+use crate::envelope::{ProofEnvelope, ENVELOPE_OK};
+use crate::jobs::{ProofJob, JOB_FAILED};
+use crate::plain::PlainProver;
+use crate::proof_system::{read_marlin_srs, ProofSystem};
+use crate::registry::{CircuitParams, ProverRegistry};
+use crate::storage_proofs::{StorageProofInput, StorageProofs};
+use crate::verifier::{Verifier, VkFormat};
+use crate::witness::WitnessBackend;
+use crate::zkey::ZkeyCheck;
+use std::ffi::c_void;
 use std::str;
+use std::sync::Arc;
 
 #[derive(Debug, Clone)]
 #[repr(C)]
@@ -10,143 +22,1152 @@ pub struct Buffer {
     pub len: usize,
 }
 
-#[derive(Debug, Clone)]
+impl Buffer {
+    /// Hand the vector over to the caller, it's reclaimed by `free_owned`
+    fn from_vec(data: Vec<u8>) -> Self {
+        let data = Box::leak(data.into_boxed_slice());
+        Self {
+            data: data.as_ptr(),
+            len: data.len(),
+        }
+    }
+
+    /// # Safety
+    ///
+    /// Only call on a buffer created with `from_vec`
+    unsafe fn free_owned(&self) {
+        if self.data.is_null() {
+            return;
+        }
+
+        let slice = std::slice::from_raw_parts_mut(self.data as *mut u8, self.len);
+        drop(Box::from_raw(slice as *mut [u8]))
+    }
+}
+
+/// Sizes of a circuit, see `CircuitInfo`
+#[derive(Debug, Clone, Default)]
+#[repr(C)]
+pub struct CircuitStats {
+    pub num_constraints: usize,
+    pub num_public_outputs: usize,
+    pub num_public_inputs: usize,
+    pub num_private_inputs: usize,
+    pub num_wires: usize,
+}
+
+#[derive(Debug)]
 #[repr(C)]
 pub struct ProofCtx {
     pub proof: Buffer,
     pub public_inputs: Buffer,
+    pub envelope: Buffer,
+}
+
+impl ProofCtx {
+    pub fn new(proof: Vec<u8>, public_inputs: Vec<u8>, envelope: Vec<u8>) -> Self {
+        Self {
+            proof: Buffer::from_vec(proof),
+            public_inputs: Buffer::from_vec(public_inputs),
+            envelope: Buffer::from_vec(envelope),
+        }
+    }
+}
+
+impl Drop for ProofCtx {
+    fn drop(&mut self) {
+        unsafe {
+            self.proof.free_owned();
+            self.public_inputs.free_owned();
+            self.envelope.free_owned();
+        }
+    }
+}
+
+/// # Safety
+///
+/// Construct a StorageProofs object, proofs are computed on a pool of
+/// `num_threads` threads or one per core if it's 0
+#[no_mangle]
+pub unsafe extern "C" fn init_storage_proofs(
+    r1cs: Buffer,
+    wasm: Buffer,
+    zkey: *const Buffer,
+    num_threads: usize,
+) -> *mut StorageProofs {
+    let r1cs = {
+
+        let slice = std::slice::from_raw_parts((r1cs).data, (r1cs).len);
+        str::from_utf8(slice).unwrap().to_string().to_owned()
+    };
+
+    let wasm = {
+        let slice = std::slice::from_raw_parts((wasm).data, (wasm).len);
+        str::from_utf8(slice).unwrap().to_string().to_owned()
+    };
+
+    let zkey = {
+        if !zkey.is_null() {
+            let slice = std::slice::from_raw_parts((*zkey).data, (*zkey).len);
+            Some(str::from_utf8(slice).unwrap().to_string().to_owned())
+        } else {
+            None
+        }
+    };
+
+    // reference counted, so running proof jobs keep the prover alive
+    Arc::into_raw(Arc::new(StorageProofs::new(wasm, r1cs, zkey, num_threads))) as *mut _
+}
+
+/// # Safety
+///
+/// Same as `init_storage_proofs`, witnesses are computed natively instead of
+/// with the wasm, the `.sym` file has to be next to the r1cs. Returns null if
+/// a path isn't utf8 or the prover can't be loaded.
+#[no_mangle]
+pub unsafe extern "C" fn init_storage_proofs_native(
+    r1cs: Buffer,
+    zkey: *const Buffer,
+    num_threads: usize,
+) -> *mut StorageProofs {
+    let r1cs = {
+        let slice = std::slice::from_raw_parts((r1cs).data, (r1cs).len);
+        match str::from_utf8(slice) {
+            Ok(r1cs) => r1cs.to_string(),
+            Err(_) => return std::ptr::null_mut(),
+        }
+    };
+
+    let zkey = {
+        if !zkey.is_null() {
+            let slice = std::slice::from_raw_parts((*zkey).data, (*zkey).len);
+            match str::from_utf8(slice) {
+                Ok(zkey) => Some(zkey.to_string()),
+                Err(_) => return std::ptr::null_mut(),
+            }
+        } else {
+            None
+        }
+    };
+
+    match StorageProofs::load(
+        WitnessBackend::Native,
+        r1cs,
+        zkey,
+        num_threads,
+        ZkeyCheck::Shape,
+    ) {
+        Ok(prover) => Arc::into_raw(Arc::new(prover)) as *mut _,
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// # Safety
+///
+/// Same as `init_storage_proofs`, but returns null instead of panicking if
+/// the zkey wasn't generated for the r1cs. `strict` also compares the
+/// coefficients of every constraint, not only the sizes.
+#[no_mangle]
+pub unsafe extern "C" fn init_storage_proofs_checked(
+    r1cs: Buffer,
+    wasm: Buffer,
+    zkey: *const Buffer,
+    num_threads: usize,
+    strict: bool,
+) -> *mut StorageProofs {
+    let r1cs = {
+        let slice = std::slice::from_raw_parts((r1cs).data, (r1cs).len);
+        str::from_utf8(slice).unwrap().to_string()
+    };
+
+    let wasm = {
+        let slice = std::slice::from_raw_parts((wasm).data, (wasm).len);
+        str::from_utf8(slice).unwrap().to_string()
+    };
+
+    let zkey = {
+        if !zkey.is_null() {
+            let slice = std::slice::from_raw_parts((*zkey).data, (*zkey).len);
+            Some(str::from_utf8(slice).unwrap().to_string())
+        } else {
+            None
+        }
+    };
+
+    let check = if strict {
+        ZkeyCheck::Strict
+    } else {
+        ZkeyCheck::Shape
+    };
+    match StorageProofs::load(WitnessBackend::Wasm(wasm), r1cs, zkey, num_threads, check) {
+        Ok(prover) => Arc::into_raw(Arc::new(prover)) as *mut _,
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// # Safety
+///
+/// Same as `init_storage_proofs`, proving with Marlin instead of Groth16.
+/// `srs` holds a universal SRS serialized with ark, if it's null one is
+/// generated from local randomness for tests. Returns null if the SRS can't
+/// be read or is too small for the circuit.
+#[no_mangle]
+pub unsafe extern "C" fn init_storage_proofs_marlin(
+    r1cs: Buffer,
+    wasm: Buffer,
+    srs: *const Buffer,
+    num_threads: usize,
+) -> *mut StorageProofs {
+    let r1cs = {
+        let slice = std::slice::from_raw_parts((r1cs).data, (r1cs).len);
+        str::from_utf8(slice).unwrap().to_string()
+    };
+
+    let wasm = {
+        let slice = std::slice::from_raw_parts((wasm).data, (wasm).len);
+        str::from_utf8(slice).unwrap().to_string()
+    };
+
+    let srs = if srs.is_null() {
+        None
+    } else {
+        let slice = std::slice::from_raw_parts((*srs).data, (*srs).len);
+        match read_marlin_srs(slice) {
+            Ok(srs) => Some(Arc::new(srs)),
+            Err(_) => return std::ptr::null_mut(),
+        }
+    };
+
+    let system = ProofSystem::Marlin(srs);
+    match StorageProofs::with_proof_system(WitnessBackend::Wasm(wasm), r1cs, system, num_threads) {
+        Ok(prover) => Arc::into_raw(Arc::new(prover)) as *mut _,
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// # Safety
+///
+/// Use after constructing a StorageProofs object with init, returns null if
+/// an argument can't be read or proving fails
+#[no_mangle]
+pub unsafe extern "C" fn prove(
+    prover_ptr: *const StorageProofs,
+    chunks: *const Buffer,
+    siblings: *const Buffer,
+    hashes: *const Buffer,
+    path: *const i32,
+    path_len: usize,
+    pubkey: *const Buffer,
+    root: *const Buffer,
+    salt: *const Buffer,
+) -> *mut ProofCtx {
+    // the public key isn't a circuit input, but it has to be a valid one
+    if pubkey.is_null() || read_u256(pubkey).is_none() {
+        return std::ptr::null_mut();
+    }
+
+    let (chunks, siblings, hashes, root, salt) = match (
+        read_u256s(chunks),
+        read_u256s(siblings),
+        read_u256s(hashes),
+        read_u256(root),
+        read_u256(salt),
+    ) {
+        (Some(chunks), Some(siblings), Some(hashes), Some(root), Some(salt)) => {
+            (chunks, siblings, hashes, root, salt)
+        }
+        _ => return std::ptr::null_mut(),
+    };
+
+    let path = {
+        let slice = std::slice::from_raw_parts(path, path_len);
+        slice.to_vec()
+    };
+
+    let proof_bytes = &mut Vec::new();
+    let public_inputs_bytes = &mut Vec::new();
+
+    let _prover = &*prover_ptr;
+    let envelope = _prover
+        .prove(
+            chunks.as_slice(),
+            siblings.as_slice(),
+            hashes.as_slice(),
+            path.as_slice(),
+            root,
+            salt,
+            proof_bytes,
+            public_inputs_bytes,
+        )
+        .and_then(|_| _prover.envelope(proof_bytes, public_inputs_bytes))
+        .and_then(|e| e.to_bytes());
+
+    match envelope {
+        Ok(envelope) => Box::into_raw(Box::new(ProofCtx::new(
+            std::mem::take(proof_bytes),
+            std::mem::take(public_inputs_bytes),
+            envelope,
+        ))),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// # Safety
+///
+/// Use after constructing a StorageProofs object with init, returns null if
+/// proving fails
+#[no_mangle]
+pub unsafe extern "C" fn prove_mpack_ext(
+    prover_ptr: *const StorageProofs,
+    args: *const Buffer,
+) -> *mut ProofCtx {
+    let inputs = std::slice::from_raw_parts((*args).data, (*args).len);
+
+    let proof_bytes = &mut Vec::new();
+    let public_inputs_bytes = &mut Vec::new();
+
+    let _prover = &*prover_ptr;
+    let envelope = _prover
+        .prove_mpack(inputs, proof_bytes, public_inputs_bytes)
+        .and_then(|_| _prover.envelope(proof_bytes, public_inputs_bytes))
+        .and_then(|e| e.to_bytes());
+
+    match envelope {
+        Ok(envelope) => Box::into_raw(Box::new(ProofCtx::new(
+            std::mem::take(proof_bytes),
+            std::mem::take(public_inputs_bytes),
+            envelope,
+        ))),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// # Safety
+///
+/// Use after constructing a StorageProofs object with init, `wtns` holds a
+/// witness in the iden3 `.wtns` format. Returns null if proving fails.
+#[no_mangle]
+pub unsafe extern "C" fn prove_wtns(
+    prover_ptr: *const StorageProofs,
+    wtns: *const Buffer,
+) -> *mut ProofCtx {
+    let wtns = std::slice::from_raw_parts((*wtns).data, (*wtns).len);
+
+    let proof_bytes = &mut Vec::new();
+    let public_inputs_bytes = &mut Vec::new();
+
+    let _prover = &*prover_ptr;
+    let envelope = _prover
+        .prove_wtns(wtns, proof_bytes, public_inputs_bytes)
+        .and_then(|_| _prover.envelope(proof_bytes, public_inputs_bytes))
+        .and_then(|e| e.to_bytes());
+
+    match envelope {
+        Ok(envelope) => Box::into_raw(Box::new(ProofCtx::new(
+            std::mem::take(proof_bytes),
+            std::mem::take(public_inputs_bytes),
+            envelope,
+        ))),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// # Safety
+///
+/// Use after constructing a StorageProofs object with init, returns the
+/// witness for the mpack encoded `args` as `.wtns` or null if it can't be
+/// computed. Free the result with `free_buffer`.
+#[no_mangle]
+pub unsafe extern "C" fn export_wtns_mpack(
+    prover_ptr: *const StorageProofs,
+    args: *const Buffer,
+) -> *mut Buffer {
+    let inputs = std::slice::from_raw_parts((*args).data, (*args).len);
+
+    let _prover = &*prover_ptr;
+    match _prover.export_wtns_mpack(inputs) {
+        Ok(wtns) => Box::into_raw(Box::new(Buffer::from_vec(wtns))),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// # Safety
+///
+/// `args` must point to `len` mpack encoded inputs and `results` to `len`
+/// ProofCtx pointers, which are set to the proof for each input or null if
+/// proving it failed. Every non-null result must be freed with `free_proof_ctx`.
+#[no_mangle]
+pub unsafe extern "C" fn prove_mpack_many(
+    prover_ptr: *const StorageProofs,
+    args: *const Buffer,
+    len: usize,
+    results: *mut *mut ProofCtx,
+) -> bool {
+    let inputs = std::slice::from_raw_parts(args, len)
+        .iter()
+        .map(|a| std::slice::from_raw_parts(a.data, a.len))
+        .collect::<Vec<&[u8]>>();
+    let results = std::slice::from_raw_parts_mut(results, len);
+
+    let _prover = &*prover_ptr;
+    let proofs = _prover.prove_mpack_many(&inputs);
+
+    let mut all_ok = true;
+    for (result, proof) in results.iter_mut().zip(proofs) {
+        let ctx = proof.and_then(|(proof_bytes, public_inputs_bytes)| {
+            let envelope = _prover
+                .envelope(&proof_bytes, &public_inputs_bytes)?
+                .to_bytes()?;
+            Ok(ProofCtx::new(proof_bytes, public_inputs_bytes, envelope))
+        });
+
+        *result = match ctx {
+            Ok(ctx) => Box::into_raw(Box::new(ctx)),
+            Err(_) => {
+                all_ok = false;
+                std::ptr::null_mut()
+            }
+        };
+    }
+
+    all_ok
+}
+
+#[no_mangle]
+/// # Safety
+///
+/// Should be called on a valid proof and public inputs previously generated by prove
+pub unsafe extern "C" fn verify(
+    prover_ptr: *const StorageProofs,
+    proof: *const Buffer,
+    public_inputs: *const Buffer,
+) -> bool {
+    let proof = std::slice::from_raw_parts((*proof).data, (*proof).len);
+    let public_inputs = std::slice::from_raw_parts((*public_inputs).data, (*public_inputs).len);
+    let _prover = &*prover_ptr;
+    _prover.verify(proof, public_inputs).is_ok()
+}
+
+#[no_mangle]
+/// # Safety
+///
+/// Should be called on a valid proof previously generated by prove, `root`,
+/// `salt` and the optional `pubkey` are little-endian U256s the verifier expects
+pub unsafe extern "C" fn verify_storage_proof(
+    prover_ptr: *const StorageProofs,
+    proof: *const Buffer,
+    root: *const Buffer,
+    salt: *const Buffer,
+    pubkey: *const Buffer,
+) -> bool {
+    let proof = std::slice::from_raw_parts((*proof).data, (*proof).len);
+
+    let root = U256::try_from_le_slice(std::slice::from_raw_parts((*root).data, (*root).len));
+    let salt = U256::try_from_le_slice(std::slice::from_raw_parts((*salt).data, (*salt).len));
+    let (root, salt) = match (root, salt) {
+        (Some(root), Some(salt)) => (root, salt),
+        _ => return false,
+    };
+
+    let pubkey = if !pubkey.is_null() {
+        match U256::try_from_le_slice(std::slice::from_raw_parts((*pubkey).data, (*pubkey).len)) {
+            Some(pubkey) => Some(pubkey),
+            None => return false,
+        }
+    } else {
+        None
+    };
+
+    let _prover = &*prover_ptr;
+    _prover
+        .verify_storage_proof(proof, root, salt, pubkey)
+        .is_ok()
+}
+
+#[no_mangle]
+/// # Safety
+///
+/// `proofs` and `public_inputs` must point to `len` buffers each, and `results`
+/// to `len` bools, which are set to whether each proof verified. Returns true
+/// if all proofs are valid.
+pub unsafe extern "C" fn verify_batch(
+    prover_ptr: *const StorageProofs,
+    proofs: *const Buffer,
+    public_inputs: *const Buffer,
+    len: usize,
+    results: *mut bool,
+) -> bool {
+    let proofs = std::slice::from_raw_parts(proofs, len);
+    let public_inputs = std::slice::from_raw_parts(public_inputs, len);
+    let batch = proofs
+        .iter()
+        .zip(public_inputs)
+        .map(|(p, i)| {
+            (
+                std::slice::from_raw_parts(p.data, p.len),
+                std::slice::from_raw_parts(i.data, i.len),
+            )
+        })
+        .collect::<Vec<(&[u8], &[u8])>>();
+
+    let results = std::slice::from_raw_parts_mut(results, len);
+    results.iter_mut().for_each(|r| *r = true);
+
+    let _prover = &*prover_ptr;
+    match _prover.verify_batch(&batch) {
+        Ok(()) => true,
+        Err(invalid) => {
+            invalid.into_iter().for_each(|i| results[i] = false);
+            false
+        }
+    }
+}
+
+#[no_mangle]
+/// # Safety
+///
+/// Should be called on a valid envelope previously generated by prove,
+/// returns `ENVELOPE_OK` or one of the `ENVELOPE_*` error codes
+pub unsafe extern "C" fn verify_envelope(
+    prover_ptr: *const StorageProofs,
+    envelope: *const Buffer,
+) -> i32 {
+    let envelope = std::slice::from_raw_parts((*envelope).data, (*envelope).len);
+    let _prover = &*prover_ptr;
+    match _prover.verify_envelope(envelope) {
+        Ok(()) => ENVELOPE_OK,
+        Err(e) => e.code(),
+    }
+}
+
+/// # Safety
+///
+/// Use on a valid pointer to StorageProofs
+#[no_mangle]
+pub unsafe extern "C" fn circuit_stats(prover_ptr: *const StorageProofs) -> CircuitStats {
+    let info = (*prover_ptr).circuit_info();
+
+    CircuitStats {
+        num_constraints: info.num_constraints,
+        num_public_outputs: info.num_public_outputs,
+        num_public_inputs: info.num_public_inputs,
+        num_private_inputs: info.num_private_inputs,
+        num_wires: info.num_wires,
+    }
+}
+
+/// # Safety
+///
+/// Use on a valid pointer to StorageProofs, returns the full `CircuitInfo`,
+/// including the curve and the input signal names and dimensions, as an mpack
+/// map. Free the result with `free_buffer`.
+#[no_mangle]
+pub unsafe extern "C" fn circuit_info_mpack(prover_ptr: *const StorageProofs) -> *mut Buffer {
+    let _prover = &*prover_ptr;
+    match _prover.circuit_info().to_mpack() {
+        Ok(info) => Box::into_raw(Box::new(Buffer::from_vec(info))),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// # Safety
+///
+/// Use on a valid pointer to StorageProofs, returns its verifying key in one
+/// of the `VK_FORMAT_*` formats or null for an unknown format. Free the
+/// result with `free_buffer`.
+#[no_mangle]
+pub unsafe extern "C" fn export_vk(prover_ptr: *const StorageProofs, format: u8) -> *mut Buffer {
+    let _prover = &*prover_ptr;
+    match VkFormat::try_from(format).and_then(|format| _prover.export_vk(format)) {
+        Ok(vk) => Box::into_raw(Box::new(Buffer::from_vec(vk))),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// # Safety
+///
+/// `vk` must hold a verifying key in the `VK_FORMAT_*` `format`, returns a
+/// Verifier or null if it can't be read. Free the result with `free_verifier`.
+#[no_mangle]
+pub unsafe extern "C" fn import_verifier(vk: *const Buffer, format: u8) -> *mut Verifier {
+    let vk = std::slice::from_raw_parts((*vk).data, (*vk).len);
+    match VkFormat::try_from(format).and_then(|format| Verifier::import(vk, format)) {
+        Ok(verifier) => Box::into_raw(Box::new(verifier)),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// # Safety
+///
+/// Use on a valid pointer to StorageProofs, returns a Verifier for its
+/// circuit or null if it doesn't prove with Groth16. Free the result with
+/// `free_verifier`.
+#[no_mangle]
+pub unsafe extern "C" fn prover_verifier(prover_ptr: *const StorageProofs) -> *mut Verifier {
+    let _prover = &*prover_ptr;
+    match _prover.verifier() {
+        Ok(verifier) => Box::into_raw(Box::new(verifier)),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// # Safety
+///
+/// Same as `export_vk`, for a valid pointer to a Verifier
+#[no_mangle]
+pub unsafe extern "C" fn verifier_export_vk(verifier: *const Verifier, format: u8) -> *mut Buffer {
+    let verifier = &*verifier;
+    match VkFormat::try_from(format).and_then(|format| verifier.export(format)) {
+        Ok(vk) => Box::into_raw(Box::new(Buffer::from_vec(vk))),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// # Safety
+///
+/// Same as `verify`, for a valid pointer to a Verifier
+#[no_mangle]
+pub unsafe extern "C" fn verifier_verify(
+    verifier: *const Verifier,
+    proof: *const Buffer,
+    public_inputs: *const Buffer,
+) -> bool {
+    let proof = std::slice::from_raw_parts((*proof).data, (*proof).len);
+    let public_inputs = std::slice::from_raw_parts((*public_inputs).data, (*public_inputs).len);
+    let verifier = &*verifier;
+    verifier.verify(proof, public_inputs).is_ok()
+}
+
+/// # Safety
+///
+/// Use on a valid pointer to StorageProofs, returns a fresh proof for the
+/// same public inputs as `proof` or null if it can't be read. Free the result
+/// with `free_buffer`.
+#[no_mangle]
+pub unsafe extern "C" fn rerandomize(
+    prover_ptr: *const StorageProofs,
+    proof: *const Buffer,
+) -> *mut Buffer {
+    let proof = std::slice::from_raw_parts((*proof).data, (*proof).len);
+    let _prover = &*prover_ptr;
+    match _prover.rerandomize(proof) {
+        Ok(proof) => Box::into_raw(Box::new(Buffer::from_vec(proof))),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// # Safety
+///
+/// Same as `rerandomize`, for a valid pointer to a Verifier
+#[no_mangle]
+pub unsafe extern "C" fn verifier_rerandomize(
+    verifier: *const Verifier,
+    proof: *const Buffer,
+) -> *mut Buffer {
+    let proof = std::slice::from_raw_parts((*proof).data, (*proof).len);
+    let verifier = &*verifier;
+    match verifier.rerandomize(proof) {
+        Ok(proof) => Box::into_raw(Box::new(Buffer::from_vec(proof))),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// # Safety
+///
+/// Generates an SRS to aggregate up to `max_proofs` proofs from local
+/// randomness, only fit for tests and deployments that trust the caller.
+/// Free the result with `free_aggregation_srs`.
+#[no_mangle]
+pub unsafe extern "C" fn aggregation_srs_setup(max_proofs: usize) -> *mut AggregationSrs {
+    Box::into_raw(Box::new(AggregationSrs::setup(
+        max_proofs,
+        &mut ThreadRng::default(),
+    )))
+}
+
+/// # Safety
+///
+/// `srs` must hold an SRS exported with `aggregation_srs_export`, returns
+/// null if it can't be read. Free the result with `free_aggregation_srs`.
+#[no_mangle]
+pub unsafe extern "C" fn aggregation_srs_import(srs: *const Buffer) -> *mut AggregationSrs {
+    let srs = std::slice::from_raw_parts((*srs).data, (*srs).len);
+    match AggregationSrs::from_bytes(srs) {
+        Ok(srs) => Box::into_raw(Box::new(srs)),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// # Safety
+///
+/// Use on a valid pointer to an AggregationSrs. Free the result with
+/// `free_buffer`.
+#[no_mangle]
+pub unsafe extern "C" fn aggregation_srs_export(srs: *const AggregationSrs) -> *mut Buffer {
+    match (*srs).to_bytes() {
+        Ok(srs) => Box::into_raw(Box::new(Buffer::from_vec(srs))),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// # Safety
+///
+/// `ctxs` must point to `len` valid ProofCtx pointers returned by prove for
+/// this prover, returns their aggregate proof or null if they can't be
+/// aggregated with `srs`. Free the result with `free_buffer`.
+#[no_mangle]
+pub unsafe extern "C" fn aggregate_proofs(
+    prover_ptr: *const StorageProofs,
+    srs: *const AggregationSrs,
+    ctxs: *const *const ProofCtx,
+    len: usize,
+) -> *mut Buffer {
+    let proofs = std::slice::from_raw_parts(ctxs, len)
+        .iter()
+        .map(|ctx| {
+            let ctx = &**ctx;
+            (
+                std::slice::from_raw_parts(ctx.proof.data, ctx.proof.len),
+                std::slice::from_raw_parts(ctx.public_inputs.data, ctx.public_inputs.len),
+            )
+        })
+        .collect::<Vec<(&[u8], &[u8])>>();
+
+    let _prover = &*prover_ptr;
+    match _prover.aggregate(&*srs, &proofs) {
+        Ok(aggregate) => Box::into_raw(Box::new(Buffer::from_vec(aggregate))),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+unsafe fn aggregate_inputs<'a>(public_inputs: *const Buffer, len: usize) -> Vec<&'a [u8]> {
+    std::slice::from_raw_parts(public_inputs, len)
+        .iter()
+        .map(|i| std::slice::from_raw_parts(i.data, i.len))
+        .collect()
+}
+
+/// # Safety
+///
+/// `public_inputs` must point to `len` buffers, the public inputs of the
+/// aggregated proofs in the order they were passed to `aggregate_proofs`
+#[no_mangle]
+pub unsafe extern "C" fn verify_aggregate(
+    prover_ptr: *const StorageProofs,
+    srs: *const AggregationSrs,
+    aggregate: *const Buffer,
+    public_inputs: *const Buffer,
+    len: usize,
+) -> bool {
+    let aggregate = std::slice::from_raw_parts((*aggregate).data, (*aggregate).len);
+    let public_inputs = aggregate_inputs(public_inputs, len);
+    let _prover = &*prover_ptr;
+    _prover
+        .verify_aggregate(&(*srs).verifier_key(), aggregate, &public_inputs)
+        .is_ok()
+}
+
+/// # Safety
+///
+/// Same as `verify_aggregate`, for a valid pointer to a Verifier
+#[no_mangle]
+pub unsafe extern "C" fn verifier_verify_aggregate(
+    verifier: *const Verifier,
+    srs: *const AggregationSrs,
+    aggregate: *const Buffer,
+    public_inputs: *const Buffer,
+    len: usize,
+) -> bool {
+    let aggregate = std::slice::from_raw_parts((*aggregate).data, (*aggregate).len);
+    let public_inputs = aggregate_inputs(public_inputs, len);
+    let verifier = &*verifier;
+    verifier
+        .verify_aggregate(&(*srs).verifier_key(), aggregate, &public_inputs)
+        .is_ok()
+}
+
+/// # Safety
+///
+/// Construct a prover of plain, non zero-knowledge, proofs for the
+/// `StorageProver` circuit with these parameters. Free it with
+/// `free_plain_prover`.
+#[no_mangle]
+pub unsafe extern "C" fn init_plain_prover(
+    block_size: usize,
+    query_len: usize,
+    levels: usize,
+    digest_chunk: usize,
+) -> *mut PlainProver {
+    Box::into_raw(Box::new(PlainProver::new(CircuitParams::new(
+        block_size,
+        query_len,
+        levels,
+        digest_chunk,
+    ))))
+}
+
+unsafe fn read_u256s(buffer: *const Buffer) -> Option<Vec<U256>> {
+    std::slice::from_raw_parts((*buffer).data, (*buffer).len)
+        .chunks(U256::BYTES)
+        .map(U256::try_from_le_slice)
+        .collect()
+}
+
+unsafe fn read_u256(buffer: *const Buffer) -> Option<U256> {
+    U256::try_from_le_slice(std::slice::from_raw_parts((*buffer).data, (*buffer).len))
+}
+
+/// # Safety
+///
+/// Same arguments as `prove`, for a valid pointer to a PlainProver. Returns
+/// null if the inputs don't satisfy the circuit.
+#[no_mangle]
+pub unsafe extern "C" fn plain_prove(
+    prover: *const PlainProver,
+    chunks: *const Buffer,
+    siblings: *const Buffer,
+    hashes: *const Buffer,
+    path: *const i32,
+    path_len: usize,
+    _pubkey: *const Buffer,
+    root: *const Buffer,
+    salt: *const Buffer,
+) -> *mut ProofCtx {
+    let input = match (
+        read_u256s(chunks),
+        read_u256s(siblings),
+        read_u256s(hashes),
+        read_u256(root),
+        read_u256(salt),
+    ) {
+        (Some(chunks), Some(siblings), Some(hashes), Some(root), Some(salt)) => {
+            StorageProofInput {
+                chunks,
+                siblings,
+                hashes,
+                path: std::slice::from_raw_parts(path, path_len).to_vec(),
+                root,
+                salt,
+            }
+        }
+        _ => return std::ptr::null_mut(),
+    };
+
+    let proof_bytes = &mut Vec::new();
+    let public_inputs_bytes = &mut Vec::new();
+
+    let prover = &*prover;
+    let envelope = prover
+        .prove_input(&input, proof_bytes, public_inputs_bytes)
+        .and_then(|_| prover.envelope(proof_bytes))
+        .and_then(|e| e.to_bytes());
+
+    match envelope {
+        Ok(envelope) => Box::into_raw(Box::new(ProofCtx::new(
+            std::mem::take(proof_bytes),
+            std::mem::take(public_inputs_bytes),
+            envelope,
+        ))),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// # Safety
+///
+/// Same as `verify_storage_proof`, for a valid pointer to a PlainProver
+#[no_mangle]
+pub unsafe extern "C" fn plain_verify_storage_proof(
+    prover: *const PlainProver,
+    proof: *const Buffer,
+    root: *const Buffer,
+    salt: *const Buffer,
+) -> bool {
+    let proof = std::slice::from_raw_parts((*proof).data, (*proof).len);
+    let (root, salt) = match (read_u256(root), read_u256(salt)) {
+        (Some(root), Some(salt)) => (root, salt),
+        _ => return false,
+    };
+
+    let prover = &*prover;
+    prover.verify_storage_proof(proof, root, salt).is_ok()
+}
+
+/// # Safety
+///
+/// Same as `verify_envelope`, for a valid pointer to a PlainProver
+#[no_mangle]
+pub unsafe extern "C" fn plain_verify_envelope(
+    prover: *const PlainProver,
+    envelope: *const Buffer,
+) -> i32 {
+    let envelope = std::slice::from_raw_parts((*envelope).data, (*envelope).len);
+    let prover = &*prover;
+    match prover.verify_envelope(envelope) {
+        Ok(()) => ENVELOPE_OK,
+        Err(e) => e.code(),
+    }
+}
+
+unsafe fn read_public_input(ctx: *const ProofCtx, name: &str, dest: *mut u8) -> bool {
+    if ctx.is_null() || dest.is_null() {
+        return false;
+    }
+
+    let envelope = std::slice::from_raw_parts((*ctx).envelope.data, (*ctx).envelope.len);
+    let val = match ProofEnvelope::from_bytes(envelope) {
+        Ok(envelope) => envelope.public_inputs.get(name),
+        Err(_) => None,
+    };
+
+    match val {
+        Some(val) => {
+            let bytes: [u8; U256::BYTES] = val.to_le_bytes();
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), dest, U256::BYTES);
+            true
+        }
+        None => false,
+    }
+}
+
+/// # Safety
+///
+/// Use on a valid pointer to ProofCtx, writes the named public input as a
+/// little-endian U256 into `dest`, which must hold 32 bytes
+#[no_mangle]
+pub unsafe extern "C" fn proof_ctx_public_input(
+    ctx: *const ProofCtx,
+    name: *const Buffer,
+    dest: *mut u8,
+) -> bool {
+    let name = std::slice::from_raw_parts((*name).data, (*name).len);
+    match str::from_utf8(name) {
+        Ok(name) => read_public_input(ctx, name, dest),
+        Err(_) => false,
+    }
+}
+
+/// # Safety
+///
+/// Use on a valid pointer to ProofCtx, writes `root` as a little-endian U256
+/// into `dest`, which must hold 32 bytes
+#[no_mangle]
+pub unsafe extern "C" fn proof_ctx_root(ctx: *const ProofCtx, dest: *mut u8) -> bool {
+    read_public_input(ctx, "root", dest)
+}
+
+/// # Safety
+///
+/// Use on a valid pointer to ProofCtx, writes `salt` as a little-endian U256
+/// into `dest`, which must hold 32 bytes
+#[no_mangle]
+pub unsafe extern "C" fn proof_ctx_salt(ctx: *const ProofCtx, dest: *mut u8) -> bool {
+    read_public_input(ctx, "salt", dest)
+}
+
+/// Completion callback of a proof job, called from the job's thread
+pub type ProofJobCallback = extern "C" fn(status: i32, user_data: *mut c_void);
+
+struct UserData(*mut c_void);
+
+// the pointer is only handed back to the caller's callback
+unsafe impl Send for UserData {}
+
+/// # Safety
+///
+/// Use after constructing a StorageProofs object with init. Starts proving
+/// `args` in the background and returns a job handle, `callback` (if any) is
+/// called with the final `JOB_*` status from the job's thread. The handle must
+/// be released with `free_proof_job`.
+#[no_mangle]
+pub unsafe extern "C" fn submit_proof_job(
+    prover_ptr: *const StorageProofs,
+    args: *const Buffer,
+    callback: Option<ProofJobCallback>,
+    user_data: *mut c_void,
+) -> *mut ProofJob {
+    let inputs = std::slice::from_raw_parts((*args).data, (*args).len).to_vec();
+
+    Arc::increment_strong_count(prover_ptr);
+    let prover = Arc::from_raw(prover_ptr);
+
+    let user_data = UserData(user_data);
+    let on_done = callback.map(|callback| {
+        Box::new(move |status: i32| {
+            let user_data = user_data;
+            callback(status, user_data.0)
+        }) as Box<dyn FnOnce(i32) + Send>
+    });
+
+    match ProofJob::submit(prover, inputs, on_done) {
+        Ok(job) => Box::into_raw(Box::new(job)),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// # Safety
+///
+/// Use on a valid pointer to ProofJob, returns one of the `JOB_*` status codes
+#[no_mangle]
+pub unsafe extern "C" fn proof_job_status(job: *const ProofJob) -> i32 {
+    if job.is_null() {
+        return JOB_FAILED;
+    }
+
+    (*job).status()
+}
+
+/// # Safety
+///
+/// Use on a valid pointer to ProofJob, the job stops at the next stage
+#[no_mangle]
+pub unsafe extern "C" fn cancel_proof_job(job: *const ProofJob) {
+    if job.is_null() {
+        return;
+    }
+
+    (*job).cancel()
 }
 
-impl ProofCtx {
-    pub fn new(proof: &[u8], public_inputs: &[u8]) -> Self {
-        Self {
-            proof: Buffer {
-                data: proof.as_ptr(),
-                len: proof.len(),
-            },
-            public_inputs: Buffer {
-                data: public_inputs.as_ptr(),
-                len: public_inputs.len(),
-            },
+/// # Safety
+///
+/// Use on a valid pointer to ProofJob. Returns the proof of a job that's
+/// `JOB_DONE`, or null otherwise. The proof can only be taken once and must
+/// be freed with `free_proof_ctx`.
+#[no_mangle]
+pub unsafe extern "C" fn proof_job_result(job: *const ProofJob) -> *mut ProofCtx {
+    if job.is_null() {
+        return std::ptr::null_mut();
+    }
+
+    match (*job).take_result() {
+        Some(Ok((proof, public_inputs, envelope))) => {
+            Box::into_raw(Box::new(ProofCtx::new(proof, public_inputs, envelope)))
         }
+        _ => std::ptr::null_mut(),
     }
 }
 
 /// # Safety
 ///
-/// Construct a StorageProofs object
+/// Use on a valid pointer to ProofJob, a job that's still running is cancelled
 #[no_mangle]
-pub unsafe extern "C" fn init_storage_proofs(
-    r1cs: Buffer,
-    wasm: Buffer,
-    zkey: *const Buffer,
-) -> *mut StorageProofs {
-    let r1cs = {
+pub unsafe extern "C" fn free_proof_job(job: *mut ProofJob) {
+    if job.is_null() {
+        return;
+    }
 
-        let slice = std::slice::from_raw_parts((r1cs).data, (r1cs).len);
-        str::from_utf8(slice).unwrap().to_string().to_owned()
-    };
+    let job = Box::from_raw(job);
+    if !job.is_finished() {
+        job.cancel();
+    }
+}
 
-    let wasm = {
-        let slice = std::slice::from_raw_parts((wasm).data, (wasm).len);
-        str::from_utf8(slice).unwrap().to_string().to_owned()
-    };
+/// # Safety
+///
+/// Use on a valid pointer to StorageProofs, resizes its proving pool
+#[no_mangle]
+pub unsafe extern "C" fn set_num_threads(
+    prover_ptr: *const StorageProofs,
+    num_threads: usize,
+) -> bool {
+    let _prover = &*prover_ptr;
+    _prover.set_num_threads(num_threads).is_ok()
+}
 
-    let zkey = {
-        if !zkey.is_null() {
-            let slice = std::slice::from_raw_parts((*zkey).data, (*zkey).len);
-            Some(str::from_utf8(slice).unwrap().to_string().to_owned())
-        } else {
-            None
-        }
-    };
+/// # Safety
+///
+/// Use on a valid pointer to StorageProofs, sets how many witnesses can be
+/// computed concurrently
+#[no_mangle]
+pub unsafe extern "C" fn set_witness_instances(
+    prover_ptr: *const StorageProofs,
+    instances: usize,
+) {
+    let _prover = &*prover_ptr;
+    _prover.set_witness_instances(instances)
+}
 
-    Box::into_raw(Box::new(StorageProofs::new(wasm, r1cs, zkey)))
+/// # Safety
+///
+/// Use on a valid pointer to StorageProofs, sets whether proving from inputs,
+/// with `prove` or any of the mpack functions, checks their Merkle paths
+/// before computing the witness
+#[no_mangle]
+pub unsafe extern "C" fn set_check_paths(prover_ptr: *const StorageProofs, enabled: bool) {
+    let _prover = &*prover_ptr;
+    _prover.set_check_paths(enabled)
 }
 
 /// # Safety
 ///
-/// Use after constructing a StorageProofs object with init
+/// Construct an empty registry of `StorageProver` circuits, every prover it
+/// loads computes proofs on `num_threads` threads or one per core if it's 0
 #[no_mangle]
-pub unsafe extern "C" fn prove(
-    prover_ptr: *mut StorageProofs,
-    chunks: *const Buffer,
-    siblings: *const Buffer,
-    hashes: *const Buffer,
-    path: *const i32,
-    path_len: usize,
-    pubkey: *const Buffer,
-    root: *const Buffer,
-    salt: *const Buffer,
-) -> *mut ProofCtx {
-    let chunks = {
-        let slice = std::slice::from_raw_parts((*chunks).data, (*chunks).len);
-        slice
-            .chunks(U256::BYTES)
-            .map(|c| U256::try_from_le_slice(c).unwrap())
-            .collect::<Vec<U256>>()
-    };
-    // println!("prove:args: {}", "chunks");
-    // for n in chunks {
-    //     println!("\t{}", n);
-    // }
-
-    let siblings = {
-        let slice = std::slice::from_raw_parts((*siblings).data, (*siblings).len);
-        slice
-            .chunks(U256::BYTES)
-            .map(|c| U256::try_from_le_slice(c).unwrap())
-            .collect::<Vec<U256>>()
-    };
+pub unsafe extern "C" fn init_prover_registry(num_threads: usize) -> *mut ProverRegistry {
+    Box::into_raw(Box::new(ProverRegistry::new(num_threads)))
+}
 
-    let hashes = {
-        let slice = std::slice::from_raw_parts((*hashes).data, (*hashes).len);
-        slice
-            .chunks(U256::BYTES)
-            .map(|c| U256::try_from_le_slice(c).unwrap())
-            .collect::<Vec<U256>>()
+/// # Safety
+///
+/// Use on a valid pointer to a ProverRegistry, before it's shared with other
+/// threads. The files are only loaded when the circuit is first used.
+#[no_mangle]
+pub unsafe extern "C" fn registry_register(
+    registry: *mut ProverRegistry,
+    block_size: usize,
+    query_len: usize,
+    levels: usize,
+    digest_chunk: usize,
+    r1cs: Buffer,
+    wasm: Buffer,
+    zkey: *const Buffer,
+) -> bool {
+    let path = |buf: &Buffer| {
+        let slice = std::slice::from_raw_parts(buf.data, buf.len);
+        str::from_utf8(slice).map(|s| s.to_string())
     };
 
-    let path = {
-        let slice = std::slice::from_raw_parts(path, path_len);
-        slice.to_vec()
+    let (r1cs, wasm) = match (path(&r1cs), path(&wasm)) {
+        (Ok(r1cs), Ok(wasm)) => (r1cs, wasm),
+        _ => return false,
+    };
+    let zkey = match zkey.as_ref().map(path) {
+        Some(Ok(zkey)) => Some(zkey),
+        Some(Err(_)) => return false,
+        None => None,
     };
 
-    let _pubkey =
-        U256::try_from_le_slice(std::slice::from_raw_parts((*pubkey).data, (*pubkey).len)).unwrap();
-
-    let root =
-        U256::try_from_le_slice(std::slice::from_raw_parts((*root).data, (*root).len)).unwrap();
-
-    let salt =
-        U256::try_from_le_slice(std::slice::from_raw_parts((*salt).data, (*salt).len)).unwrap();
-
-    let proof_bytes = &mut Vec::new();
-    let public_inputs_bytes = &mut Vec::new();
-
-    let mut _prover = &mut *prover_ptr;
-    _prover
-        .prove(
-            chunks.as_slice(),
-            siblings.as_slice(),
-            hashes.as_slice(),
-            path.as_slice(),
-            root,
-            salt,
-            proof_bytes,
-            public_inputs_bytes,
-        )
-        .unwrap();
+    let params = CircuitParams::new(block_size, query_len, levels, digest_chunk);
+    (*registry).register(params, wasm, r1cs, zkey).is_ok()
+}
 
-    Box::into_raw(Box::new(ProofCtx::new(proof_bytes, public_inputs_bytes)))
+/// # Safety
+///
+/// Use on a valid pointer to a ProverRegistry, returns the prover for the
+/// circuit or null if it isn't registered. Free it with `free_prover`.
+#[no_mangle]
+pub unsafe extern "C" fn registry_prover(
+    registry: *const ProverRegistry,
+    block_size: usize,
+    query_len: usize,
+    levels: usize,
+    digest_chunk: usize,
+) -> *mut StorageProofs {
+    let params = CircuitParams::new(block_size, query_len, levels, digest_chunk);
+    match (*registry).prover(&params) {
+        Ok(prover) => Arc::into_raw(prover) as *mut _,
+        Err(_) => std::ptr::null_mut(),
+    }
 }
 
 /// # Safety
 ///
-/// Use after constructing a StorageProofs object with init
+/// Use on a valid pointer to a ProverRegistry, proves the mpack encoded
+/// `args` with the circuit matching their shape. Returns null if there is no
+/// such circuit or proving fails.
 #[no_mangle]
-pub unsafe extern "C" fn prove_mpack_ext(
-    prover_ptr: *mut StorageProofs,
+pub unsafe extern "C" fn registry_prove_mpack(
+    registry: *const ProverRegistry,
     args: *const Buffer,
 ) -> *mut ProofCtx {
     let inputs = std::slice::from_raw_parts((*args).data, (*args).len);
@@ -154,31 +1175,45 @@ pub unsafe extern "C" fn prove_mpack_ext(
     let proof_bytes = &mut Vec::new();
     let public_inputs_bytes = &mut Vec::new();
 
-    let mut _prover = &mut *prover_ptr;
-    _prover
-        .prove_mpack(
-            inputs,
-            proof_bytes,
-            public_inputs_bytes,
-        )
-        .unwrap();
-
-    Box::into_raw(Box::new(ProofCtx::new(proof_bytes, public_inputs_bytes)))
+    let envelope = (*registry)
+        .prove_mpack(inputs, proof_bytes, public_inputs_bytes)
+        .and_then(|(prover, _)| prover.envelope(proof_bytes, public_inputs_bytes))
+        .and_then(|e| e.to_bytes());
+
+    match envelope {
+        Ok(envelope) => Box::into_raw(Box::new(ProofCtx::new(
+            std::mem::take(proof_bytes),
+            std::mem::take(public_inputs_bytes),
+            envelope,
+        ))),
+        Err(_) => std::ptr::null_mut(),
+    }
 }
 
+/// # Safety
+///
+/// Use on a valid pointer to a ProverRegistry, provers handed out by
+/// `registry_prover` stay valid until they're freed themselves
 #[no_mangle]
+pub unsafe extern "C" fn free_prover_registry(registry: *mut ProverRegistry) {
+    if registry.is_null() {
+        return;
+    }
+
+    drop(Box::from_raw(registry))
+}
+
 /// # Safety
 ///
-/// Should be called on a valid proof and public inputs previously generated by prove
-pub unsafe extern "C" fn verify(
-    prover_ptr: *mut StorageProofs,
-    proof: *const Buffer,
-    public_inputs: *const Buffer,
-) -> bool {
-    let proof = std::slice::from_raw_parts((*proof).data, (*proof).len);
-    let public_inputs = std::slice::from_raw_parts((*public_inputs).data, (*public_inputs).len);
-    let mut _prover = &mut *prover_ptr;
-    _prover.verify(proof, public_inputs).is_ok()
+/// Use on a buffer returned by this library, e.g. from `export_wtns_mpack`
+#[no_mangle]
+pub unsafe extern "C" fn free_buffer(buffer: *mut Buffer) {
+    if buffer.is_null() {
+        return;
+    }
+
+    let buffer = Box::from_raw(buffer);
+    buffer.free_owned();
 }
 
 /// # Safety
@@ -190,7 +1225,43 @@ pub unsafe extern "C" fn free_prover(prover: *mut StorageProofs) {
         return;
     }
 
-    unsafe { drop(Box::from_raw(prover)) }
+    unsafe { drop(Arc::from_raw(prover)) }
+}
+
+/// # Safety
+///
+/// Use on a valid pointer to a Verifier or panics
+#[no_mangle]
+pub unsafe extern "C" fn free_verifier(verifier: *mut Verifier) {
+    if verifier.is_null() {
+        return;
+    }
+
+    drop(Box::from_raw(verifier))
+}
+
+/// # Safety
+///
+/// Use on a valid pointer to an AggregationSrs or panics
+#[no_mangle]
+pub unsafe extern "C" fn free_aggregation_srs(srs: *mut AggregationSrs) {
+    if srs.is_null() {
+        return;
+    }
+
+    drop(Box::from_raw(srs))
+}
+
+/// # Safety
+///
+/// Use on a valid pointer to a PlainProver or panics
+#[no_mangle]
+pub unsafe extern "C" fn free_plain_prover(prover: *mut PlainProver) {
+    if prover.is_null() {
+        return;
+    }
+
+    drop(Box::from_raw(prover))
 }
 
 /// # Safety
@@ -219,7 +1290,19 @@ mod tests {
         circuit_tests::utils::{digest, treehash}, storage_proofs::EXT_ID_U256_LE, ffi::prove_mpack_ext
     };
 
-    use super::{init_storage_proofs, prove, Buffer};
+    use crate::verifier::{
+        VK_FORMAT_ARK_COMPRESSED, VK_FORMAT_ARK_UNCOMPRESSED, VK_FORMAT_EVM_WORDS,
+        VK_FORMAT_SNARKJS,
+    };
+
+    use super::{
+        aggregate_proofs, aggregation_srs_export, aggregation_srs_import, aggregation_srs_setup,
+        circuit_stats, export_vk, free_aggregation_srs, free_buffer, free_proof_ctx, free_prover,
+        free_verifier, import_verifier, init_storage_proofs, init_storage_proofs_marlin,
+        init_storage_proofs_native, proof_ctx_root, proof_ctx_salt, prove, prover_verifier,
+        rerandomize, verifier_verify, verifier_verify_aggregate, verify_aggregate, Buffer,
+        ProofCtx, StorageProofs,
+    };
 
     use rmpv::Value;
     use rmpv::encode::write_value;
@@ -396,7 +1479,7 @@ mod tests {
             len: wasm_path.len(),
         };
 
-        let prover_ptr = unsafe { init_storage_proofs(r1cs, wasm, std::ptr::null()) };
+        let prover_ptr = unsafe { init_storage_proofs(r1cs, wasm, std::ptr::null(), 0) };
         let prove_ctx: *mut crate::ffi::ProofCtx = unsafe {
             prove_mpack_ext(
                 prover_ptr,
@@ -409,11 +1492,44 @@ mod tests {
 
     #[test]
     fn test_storer_ffi() {
+        let prover_ptr = storer_prover();
+        let (prove_ctx, _) = storer_proof(prover_ptr, 42);
+
+        unsafe {
+            free_proof_ctx(prove_ctx);
+            free_prover(prover_ptr);
+        }
+    }
+
+    /// Prover for the test circuit
+    fn storer_prover() -> *mut StorageProofs {
+        let r1cs_path = "src/circuit_tests/artifacts/storer-test.r1cs";
+        let wasm_path = "src/circuit_tests/artifacts/storer-test_js/storer-test.wasm";
+
+        let r1cs = Buffer {
+            data: r1cs_path.as_ptr(),
+            len: r1cs_path.len(),
+        };
+
+        let wasm = Buffer {
+            data: wasm_path.as_ptr(),
+            len: wasm_path.len(),
+        };
+
+        let prover_ptr = unsafe { init_storage_proofs(r1cs, wasm, std::ptr::null(), 0) };
+        assert!(!prover_ptr.is_null());
+
+        prover_ptr
+    }
+
+    /// A proof through `prove` for chunks drawn from `seed`, the root is also
+    /// used as the salt
+    fn storer_proof(prover_ptr: *mut StorageProofs, seed: u64) -> (*mut ProofCtx, U256) {
         // generate a tuple of (preimages, hash), where preimages is a vector of 256 U256s
         // and hash is the hash of each vector generated using the digest function
         let data = (0..4)
             .map(|_| {
-                let rng = StdRng::seed_from_u64(42);
+                let rng = StdRng::seed_from_u64(seed);
                 let preimages: Vec<U256> = rng
                     .sample_iter(Alphanumeric)
                     .take(256)
@@ -481,20 +1597,6 @@ mod tests {
             len: root_bytes.len(),
         };
 
-        let r1cs_path = "src/circuit_tests/artifacts/storer-test.r1cs";
-        let wasm_path = "src/circuit_tests/artifacts/storer-test_js/storer-test.wasm";
-
-        let r1cs = Buffer {
-            data: r1cs_path.as_ptr(),
-            len: r1cs_path.len(),
-        };
-
-        let wasm = Buffer {
-            data: wasm_path.as_ptr(),
-            len: wasm_path.len(),
-        };
-
-        let prover_ptr = unsafe { init_storage_proofs(r1cs, wasm, std::ptr::null()) };
         let prove_ctx: *mut crate::ffi::ProofCtx = unsafe {
             prove(
                 prover_ptr,
@@ -509,6 +1611,210 @@ mod tests {
             )
         };
 
-        assert!(prove_ctx.is_null() == false);
+        assert!(!prove_ctx.is_null());
+
+        (prove_ctx, root)
+    }
+
+    #[test]
+    fn test_proof_ctx_accessors() {
+        let prover_ptr = storer_prover();
+        let (prove_ctx, root) = storer_proof(prover_ptr, 42);
+
+        let mut root_out = [0u8; U256::BYTES];
+        let mut salt_out = [0u8; U256::BYTES];
+        unsafe {
+            assert!(proof_ctx_root(prove_ctx, root_out.as_mut_ptr()));
+            assert!(proof_ctx_salt(prove_ctx, salt_out.as_mut_ptr()));
+        }
+        assert_eq!(U256::from_le_bytes(root_out), root);
+        assert_eq!(U256::from_le_bytes(salt_out), root);
+
+        unsafe {
+            free_proof_ctx(prove_ctx);
+            free_prover(prover_ptr);
+        }
+    }
+
+    #[test]
+    fn test_circuit_stats() {
+        let prover_ptr = storer_prover();
+
+        let stats = unsafe { circuit_stats(prover_ptr) };
+        assert_eq!(stats.num_public_inputs, 2);
+        assert_eq!(stats.num_public_outputs, 0);
+
+        unsafe { free_prover(prover_ptr) };
+    }
+
+    #[test]
+    fn test_export_import_vk() {
+        let prover_ptr = storer_prover();
+        let (prove_ctx, _) = storer_proof(prover_ptr, 42);
+
+        for format in [
+            VK_FORMAT_ARK_COMPRESSED,
+            VK_FORMAT_ARK_UNCOMPRESSED,
+            VK_FORMAT_SNARKJS,
+            VK_FORMAT_EVM_WORDS,
+        ] {
+            unsafe {
+                let vk = export_vk(prover_ptr, format);
+                assert!(!vk.is_null());
+
+                let verifier = import_verifier(vk, format);
+                assert!(!verifier.is_null());
+                assert!(verifier_verify(
+                    verifier,
+                    &(*prove_ctx).proof,
+                    &(*prove_ctx).public_inputs,
+                ));
+
+                free_verifier(verifier);
+                free_buffer(vk);
+            }
+        }
+
+        unsafe {
+            free_proof_ctx(prove_ctx);
+            free_prover(prover_ptr);
+        }
+    }
+
+    #[test]
+    fn test_rerandomize() {
+        let prover_ptr = storer_prover();
+        let (prove_ctx, _) = storer_proof(prover_ptr, 42);
+
+        unsafe {
+            let fresh = rerandomize(prover_ptr, &(*prove_ctx).proof);
+            assert!(!fresh.is_null());
+            let proof = &(*prove_ctx).proof;
+            assert_ne!(
+                std::slice::from_raw_parts((*fresh).data, (*fresh).len),
+                std::slice::from_raw_parts(proof.data, proof.len)
+            );
+
+            let vk = export_vk(prover_ptr, VK_FORMAT_ARK_COMPRESSED);
+            let verifier = import_verifier(vk, VK_FORMAT_ARK_COMPRESSED);
+            assert!(verifier_verify(verifier, fresh, &(*prove_ctx).public_inputs));
+
+            free_verifier(verifier);
+            free_buffer(vk);
+            free_buffer(fresh);
+            free_proof_ctx(prove_ctx);
+            free_prover(prover_ptr);
+        }
+    }
+
+    #[test]
+    fn test_aggregate_proofs() {
+        let prover_ptr = storer_prover();
+        // distinct roots and salts, and a count that has to be padded
+        let ctxs: Vec<*mut ProofCtx> = (42..45)
+            .map(|seed| storer_proof(prover_ptr, seed).0)
+            .collect();
+
+        unsafe {
+            let srs = aggregation_srs_setup(ctxs.len());
+            let aggregate = aggregate_proofs(
+                prover_ptr,
+                srs,
+                ctxs.as_ptr() as *const *const ProofCtx,
+                ctxs.len(),
+            );
+            assert!(!aggregate.is_null());
+
+            let mut inputs: Vec<Buffer> =
+                ctxs.iter().map(|ctx| (**ctx).public_inputs.clone()).collect();
+            assert!(verify_aggregate(prover_ptr, srs, aggregate, inputs.as_ptr(), inputs.len()));
+            assert!(!verify_aggregate(prover_ptr, srs, aggregate, inputs.as_ptr(), 2));
+
+            let exported = aggregation_srs_export(srs);
+            let imported = aggregation_srs_import(exported);
+            assert!(!imported.is_null());
+            let verifier = prover_verifier(prover_ptr);
+            assert!(verifier_verify_aggregate(
+                verifier,
+                imported,
+                aggregate,
+                inputs.as_ptr(),
+                inputs.len(),
+            ));
+
+            // the inputs are bound to the proofs they came with
+            inputs.swap(0, 1);
+            assert!(!verify_aggregate(prover_ptr, srs, aggregate, inputs.as_ptr(), inputs.len()));
+
+            free_verifier(verifier);
+            free_aggregation_srs(imported);
+            free_buffer(exported);
+            free_buffer(aggregate);
+            free_aggregation_srs(srs);
+            ctxs.into_iter().for_each(|ctx| free_proof_ctx(ctx));
+            free_prover(prover_ptr);
+        }
+    }
+
+    #[test]
+    fn test_init_marlin_missing_r1cs() {
+        let r1cs_path = "missing.r1cs";
+        let wasm_path = "./src/circuit_tests/artifacts/storer-test_js/storer-test.wasm";
+
+        let r1cs = Buffer {
+            data: r1cs_path.as_ptr(),
+            len: r1cs_path.len(),
+        };
+        let wasm = Buffer {
+            data: wasm_path.as_ptr(),
+            len: wasm_path.len(),
+        };
+
+        let prover_ptr = unsafe { init_storage_proofs_marlin(r1cs, wasm, std::ptr::null(), 0) };
+        assert!(prover_ptr.is_null());
+    }
+
+    #[test]
+    fn test_init_native_missing_sym() {
+        // an r1cs without the .sym file next to it
+        let r1cs_path = std::env::temp_dir().join("storer-test-no-sym.r1cs");
+        std::fs::copy("src/circuit_tests/artifacts/storer-test.r1cs", &r1cs_path).unwrap();
+        let r1cs_path = r1cs_path.to_str().unwrap();
+
+        let r1cs = Buffer {
+            data: r1cs_path.as_ptr(),
+            len: r1cs_path.len(),
+        };
+
+        let prover_ptr = unsafe { init_storage_proofs_native(r1cs, std::ptr::null(), 0) };
+        assert!(prover_ptr.is_null());
+    }
+
+    #[test]
+    fn test_prove_mpack_ext_bad_args() {
+        let r1cs_path = "src/circuit_tests/artifacts/storer-test.r1cs";
+        let wasm_path = "src/circuit_tests/artifacts/storer-test_js/storer-test.wasm";
+
+        let r1cs = Buffer {
+            data: r1cs_path.as_ptr(),
+            len: r1cs_path.len(),
+        };
+        let wasm = Buffer {
+            data: wasm_path.as_ptr(),
+            len: wasm_path.len(),
+        };
+
+        // an mpack nil instead of a map of arguments
+        let args = [0xc0u8];
+        let args_buff = Buffer {
+            data: args.as_ptr(),
+            len: args.len(),
+        };
+
+        unsafe {
+            let prover_ptr = init_storage_proofs(r1cs, wasm, std::ptr::null(), 0);
+            assert!(prove_mpack_ext(prover_ptr, &args_buff).is_null());
+            free_prover(prover_ptr);
+        }
     }
 }
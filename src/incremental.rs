@@ -0,0 +1,173 @@
+//! Incremental proofs over the periods of a contract
+//!
+//! Instead of one Groth16 proof per period, each period's storage proof is a
+//! step of a Nova recursive SNARK over the BN254/Grumpkin cycle, folded into
+//! a running accumulator, and one compressed SNARK at the end attests to all
+//! of them. The step circuit, `StorageStep` in `circuits/storer_step.circom`,
+//! keeps the number of periods and a running Poseidon hash of their roots
+//! and salts as its state, so the verifier only needs the list of challenges.
+//!
+//! Nova needs no trusted setup and runs on the CPU; the public parameters are
+//! derived from the step circuit alone. Witnesses are computed by nova-scotia
+//! with the `generate_witness.js` circom writes next to the wasm, so proving
+//! needs `node`. Build with the `nova` feature.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use ff::PrimeField;
+use nova_scotia::circom::reader::load_r1cs;
+use nova_scotia::{create_public_params, create_recursive_circuit, FileLocation, C1, C2, F, S};
+use nova_snark::provider::bn256_grumpkin::{bn256, grumpkin};
+use nova_snark::{CompressedSNARK, PublicParams};
+use rs_poseidon::poseidon::hash;
+use ruint::aliases::U256;
+use serde_json::Value;
+
+use crate::storage_proofs::StorageProofInput;
+
+type G1 = bn256::Point;
+type G2 = grumpkin::Point;
+type Snark = CompressedSNARK<G1, G2, C1<G1>, C2<G2>, S<G1>, S<G2>>;
+type ProverKey = nova_snark::ProverKey<G1, G2, C1<G1>, C2<G2>, S<G1>, S<G2>>;
+type VerifierKey = nova_snark::VerifierKey<G1, G2, C1<G1>, C2<G2>, S<G1>, S<G2>>;
+
+/// Proof that every period of `challenges` was answered, in order
+pub struct IncrementalProof {
+    num_periods: usize,
+    snark: Snark,
+}
+
+impl IncrementalProof {
+    pub fn num_periods(&self) -> usize {
+        self.num_periods
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        serde_json::to_vec(&(self.num_periods, &self.snark)).map_err(|e| e.to_string())
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let (num_periods, snark) = serde_json::from_slice(bytes).map_err(|e| e.to_string())?;
+        Ok(Self { num_periods, snark })
+    }
+}
+
+/// Running hash of the `(root, salt)` challenges of the periods, the state
+/// the step circuit ends with
+pub fn challenges_hash(challenges: &[(U256, U256)]) -> U256 {
+    challenges
+        .iter()
+        .fold(U256::ZERO, |acc, (root, salt)| hash(&[acc, *root, *salt]))
+}
+
+pub struct IncrementalProver {
+    wasm: PathBuf,
+    r1cs: nova_scotia::circom::circuit::R1CS<F<G1>>,
+    pp: PublicParams<G1, G2, C1<G1>, C2<G2>>,
+    pk: ProverKey,
+    vk: VerifierKey,
+}
+
+impl IncrementalProver {
+    /// Prover for a compiled `StorageStep` circuit, deriving the public
+    /// parameters from the r1cs is expensive, so provers should be reused
+    pub fn new(wasm: impl Into<PathBuf>, r1cs: impl Into<PathBuf>) -> Result<Self, String> {
+        let r1cs = load_r1cs::<G1, G2>(&FileLocation::PathBuf(r1cs.into()));
+        let pp = create_public_params::<G1, G2>(r1cs.clone());
+        let (pk, vk) = Snark::setup(&pp).map_err(|e| format!("{:?}", e))?;
+
+        Ok(Self {
+            wasm: wasm.into(),
+            r1cs,
+            pp,
+            pk,
+            vk,
+        })
+    }
+
+    /// Fold the proof of every period into one
+    pub fn prove(&self, periods: &[StorageProofInput]) -> Result<IncrementalProof, String> {
+        if periods.is_empty() {
+            return Err("no periods to prove".to_string());
+        }
+
+        let private_inputs = periods.iter().map(step_inputs).collect();
+        let recursive = create_recursive_circuit(
+            FileLocation::PathBuf(self.wasm.clone()),
+            self.r1cs.clone(),
+            private_inputs,
+            initial_state(),
+            &self.pp,
+        )
+        .map_err(|e| e.to_string())?;
+        let snark = Snark::prove(&self.pp, &self.pk, &recursive).map_err(|e| format!("{:?}", e))?;
+
+        Ok(IncrementalProof {
+            num_periods: periods.len(),
+            snark,
+        })
+    }
+
+    /// Verify that `proof` answers exactly `challenges`, the `(root, salt)`
+    /// of every period in order
+    pub fn verify(
+        &self,
+        proof: &IncrementalProof,
+        challenges: &[(U256, U256)],
+    ) -> Result<(), String> {
+        if proof.num_periods != challenges.len() {
+            return Err(format!(
+                "proof covers {} periods, expected {}",
+                proof.num_periods,
+                challenges.len()
+            ));
+        }
+
+        let (state, _) = proof
+            .snark
+            .verify(
+                &self.vk,
+                proof.num_periods,
+                initial_state(),
+                vec![F::<G2>::from(0)],
+            )
+            .map_err(|e| format!("{:?}", e))?;
+
+        let expected = vec![
+            F::<G1>::from(challenges.len() as u64),
+            to_field(challenges_hash(challenges))?,
+        ];
+        if state != expected {
+            return Err("proof is for other challenges".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+/// No periods proven, empty running hash
+fn initial_state() -> Vec<F<G1>> {
+    vec![F::<G1>::from(0), F::<G1>::from(0)]
+}
+
+fn to_field(n: U256) -> Result<F<G1>, String> {
+    F::<G1>::from_str_vartime(&n.to_string()).ok_or_else(|| format!("{} isn't a field element", n))
+}
+
+/// Inputs of one step, multi-dimensional signals are flattened like in
+/// `WitnessInputs`
+fn step_inputs(input: &StorageProofInput) -> HashMap<String, Value> {
+    HashMap::from([
+        ("chunks".to_string(), numbers(&input.chunks)),
+        ("siblings".to_string(), numbers(&input.siblings)),
+        ("hashes".to_string(), numbers(&input.hashes)),
+        ("path".to_string(), numbers(&input.path)),
+        ("root".to_string(), Value::String(input.root.to_string())),
+        ("salt".to_string(), Value::String(input.salt.to_string())),
+    ])
+}
+
+fn numbers<T: ToString>(vals: &[T]) -> Value {
+    Value::Array(vals.iter().map(|v| Value::String(v.to_string())).collect())
+}
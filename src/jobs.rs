@@ -0,0 +1,126 @@
+//! Asynchronous proving jobs
+//!
+//! A job proves one set of mpack inputs on the prover's thread pool, so the
+//! caller's event loop isn't blocked while the proof is computed. Jobs stay
+//! `JOB_QUEUED` until a thread of the pool is free. Their status can be
+//! polled, a callback runs once they're finished, and cancelling stops them
+//! at the next stage boundary (witness, MSMs, serialization).
+
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::storage_proofs::{ProofStage, StorageProofs, PROOF_CANCELLED};
+
+pub const JOB_QUEUED: i32 = 0;
+pub const JOB_WITNESS: i32 = 1;
+pub const JOB_PROVING: i32 = 2;
+pub const JOB_SERIALIZING: i32 = 3;
+pub const JOB_DONE: i32 = 4;
+pub const JOB_FAILED: i32 = 5;
+pub const JOB_CANCELLED: i32 = 6;
+
+/// Proof bytes, public inputs bytes and envelope of a finished job
+pub type JobOutput = (Vec<u8>, Vec<u8>, Vec<u8>);
+
+/// Called with the final status once the job is finished
+pub type OnDone = Box<dyn FnOnce(i32) + Send>;
+
+#[derive(Debug, Default)]
+struct JobState {
+    status: AtomicI32,
+    cancelled: AtomicBool,
+    result: Mutex<Option<Result<JobOutput, String>>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProofJob {
+    state: Arc<JobState>,
+}
+
+impl ProofJob {
+    pub fn submit(
+        prover: Arc<StorageProofs>,
+        inputs: Vec<u8>,
+        on_done: Option<OnDone>,
+    ) -> Result<Self, String> {
+        let state = Arc::new(JobState::default());
+        let job = Self {
+            state: state.clone(),
+        };
+
+        // proving installs itself in the same pool, so a job only takes one
+        // of its threads and the MSMs run on the others
+        prover.pool().spawn(move || {
+            let result = run(&prover, &inputs, &state);
+            let status = match &result {
+                Ok(_) => JOB_DONE,
+                Err(e) if e == PROOF_CANCELLED => JOB_CANCELLED,
+                Err(_) => JOB_FAILED,
+            };
+
+            if let Ok(mut slot) = state.result.lock() {
+                *slot = Some(result);
+            }
+            state.status.store(status, Ordering::SeqCst);
+
+            if let Some(on_done) = on_done {
+                on_done(status);
+            }
+        });
+
+        Ok(job)
+    }
+
+    /// One of the `JOB_*` status codes
+    pub fn status(&self) -> i32 {
+        self.state.status.load(Ordering::SeqCst)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.status() >= JOB_DONE
+    }
+
+    /// Ask the job to stop, it's only checked between stages
+    pub fn cancel(&self) {
+        self.state.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Take the result out of a finished job, it can only be taken once
+    pub fn take_result(&self) -> Option<Result<JobOutput, String>> {
+        if !self.is_finished() {
+            return None;
+        }
+
+        self.state.result.lock().ok()?.take()
+    }
+}
+
+fn run(prover: &StorageProofs, inputs: &[u8], state: &JobState) -> Result<JobOutput, String> {
+    let mut proof_bytes = Vec::new();
+    let mut public_inputs_bytes = Vec::new();
+
+    prover.prove_mpack_with_progress(
+        inputs,
+        &mut proof_bytes,
+        &mut public_inputs_bytes,
+        &|stage| {
+            if state.cancelled.load(Ordering::SeqCst) {
+                return false;
+            }
+
+            let status = match stage {
+                ProofStage::Witness => JOB_WITNESS,
+                ProofStage::Proving => JOB_PROVING,
+                ProofStage::Serialization => JOB_SERIALIZING,
+            };
+            state.status.store(status, Ordering::SeqCst);
+            true
+        },
+    )?;
+
+    let envelope = prover
+        .envelope(&proof_bytes, &public_inputs_bytes)?
+        .to_bytes()?;
+
+    Ok((proof_bytes, public_inputs_bytes, envelope))
+}
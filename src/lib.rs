@@ -1,3 +1,26 @@
+pub mod aggregate;
+pub mod batch;
+mod binfile;
+pub mod ceremony;
+pub mod check;
+pub mod circuit;
+pub mod circuit_info;
+pub mod envelope;
 pub mod ffi;
+#[cfg(feature = "nova")]
+pub mod incremental;
+pub mod jobs;
+pub mod merkle;
+pub mod native_witness;
+pub mod plain;
+pub mod proof_system;
+pub mod ptau;
+pub mod public_inputs;
+pub mod registry;
 pub mod storage_proofs;
+pub mod symbols;
+pub mod verifier;
+pub mod witness;
+pub mod wtns;
+pub mod zkey;
 mod circuit_tests;
@@ -0,0 +1,179 @@
+//! Poseidon digests and Merkle trees as the `StorageProver` circuit computes them
+
+use rs_poseidon::poseidon::hash;
+use ruint::aliases::U256;
+
+/// `PoseidonDigest(BLOCK_SIZE, DIGEST_CHUNK)`: hash `block` in chunks of
+/// `chunk_size` symbols, the last one zero padded, then hash the chunk hashes
+pub fn digest(block: &[U256], chunk_size: usize) -> U256 {
+    let hashes: Vec<U256> = block
+        .chunks(chunk_size)
+        .map(|chunk| {
+            let mut chunk = chunk.to_vec();
+            chunk.resize(chunk_size, U256::ZERO);
+            hash(&chunk)
+        })
+        .collect();
+
+    hash(&hashes)
+}
+
+/// Full binary tree of `Poseidon(2)` hashes over `2^depth` leaves
+#[derive(Debug, Clone)]
+pub struct MerkleTree {
+    // levels[0] are the leaves, the last level is the root
+    levels: Vec<Vec<U256>>,
+}
+
+impl MerkleTree {
+    /// Missing leaves are zero
+    pub fn new(leaves: &[U256], depth: usize) -> Result<Self, String> {
+        let width = 1usize
+            .checked_shl(depth as u32)
+            .ok_or_else(|| format!("tree depth {} is too large", depth))?;
+        if leaves.len() > width {
+            return Err(format!(
+                "{} leaves don't fit a tree of depth {}",
+                leaves.len(),
+                depth
+            ));
+        }
+
+        let mut level = leaves.to_vec();
+        level.resize(width, U256::ZERO);
+
+        let mut levels = vec![level];
+        for _ in 0..depth {
+            let next = levels
+                .last()
+                .unwrap()
+                .chunks(2)
+                .map(hash)
+                .collect();
+            levels.push(next);
+        }
+
+        Ok(Self { levels })
+    }
+
+    pub fn depth(&self) -> usize {
+        self.levels.len() - 1
+    }
+
+    pub fn root(&self) -> U256 {
+        self.levels[self.depth()][0]
+    }
+
+    /// Siblings of leaf `index` from the bottom up, bit `i` of the index says
+    /// whether the node at level `i` is a right child
+    pub fn siblings(&self, index: usize) -> Vec<U256> {
+        (0..self.depth())
+            .map(|level| self.levels[level][(index >> level) ^ 1])
+            .collect()
+    }
+}
+
+/// Whether `leaf` at `index` with `siblings` from the bottom up leads to
+/// `root`, hashing like `MerkleProof(LEVELS)`: bit `i` of the index puts the
+/// node at level `i` on the right. Like `Num2Bits(LEVELS)`, indices that
+/// don't fit in `LEVELS` bits never verify.
+pub fn verify_merkle_path(leaf: U256, siblings: &[U256], index: u64, root: U256) -> bool {
+    if siblings.len() < 64 && index >> siblings.len() != 0 {
+        return false;
+    }
+
+    let node = siblings
+        .iter()
+        .enumerate()
+        .fold(leaf, |node, (level, sibling)| {
+            if level < 64 && (index >> level) & 1 == 1 {
+                hash(&[*sibling, node])
+            } else {
+                hash(&[node, *sibling])
+            }
+        });
+
+    node == root
+}
+
+/// Check the Merkle paths of flattened `StorageProver` inputs, the error
+/// names the first query whose hash, siblings and path don't lead to `root`
+pub fn check_merkle_paths(
+    hashes: &[U256],
+    siblings: &[U256],
+    path: &[i32],
+    root: U256,
+) -> Result<(), String> {
+    if hashes.is_empty()
+        || siblings.is_empty()
+        || hashes.len() != path.len()
+        || siblings.len() % hashes.len() != 0
+    {
+        return Err(format!(
+            "{} hashes, {} siblings and {} path indices don't make up queries",
+            hashes.len(),
+            siblings.len(),
+            path.len()
+        ));
+    }
+
+    let levels = siblings.len() / hashes.len();
+    for (i, ((leaf, siblings), index)) in hashes
+        .iter()
+        .zip(siblings.chunks(levels))
+        .zip(path)
+        .enumerate()
+    {
+        let index = u64::try_from(*index).map_err(|_| format!("query {}: negative path", i))?;
+        if !verify_merkle_path(*leaf, siblings, index, root) {
+            return Err(format!("query {}: path doesn't lead to the root", i));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rs_poseidon::poseidon::hash;
+    use ruint::aliases::U256;
+
+    use super::{check_merkle_paths, verify_merkle_path, MerkleTree};
+
+    #[test]
+    fn test_merkle_tree() {
+        let leaves: Vec<U256> = (1..4u64).map(U256::from).collect();
+        let tree = MerkleTree::new(&leaves, 2).unwrap();
+
+        let left = hash(&[leaves[0], leaves[1]]);
+        let right = hash(&[leaves[2], U256::ZERO]);
+        assert_eq!(tree.root(), hash(&[left, right]));
+        assert_eq!(tree.siblings(2), vec![U256::ZERO, left]);
+
+        assert!(MerkleTree::new(&leaves, 1).is_err());
+    }
+
+    #[test]
+    fn test_verify_merkle_path() {
+        let leaves: Vec<U256> = (1..5u64).map(U256::from).collect();
+        let tree = MerkleTree::new(&leaves, 2).unwrap();
+        let root = tree.root();
+
+        for (index, leaf) in leaves.iter().enumerate() {
+            assert!(verify_merkle_path(*leaf, &tree.siblings(index), index as u64, root));
+        }
+        assert!(!verify_merkle_path(leaves[0], &tree.siblings(0), 1, root));
+        assert!(!verify_merkle_path(leaves[0], &tree.siblings(0), 4, root));
+        assert!(!verify_merkle_path(leaves[0], &tree.siblings(0), 0, leaves[0]));
+
+        let hashes = vec![leaves[1], leaves[3]];
+        let siblings = [tree.siblings(1), tree.siblings(3)].concat();
+        assert!(check_merkle_paths(&hashes, &siblings, &[1, 3], root).is_ok());
+        assert_eq!(
+            check_merkle_paths(&hashes, &siblings, &[1, 2], root),
+            Err("query 1: path doesn't lead to the root".to_string())
+        );
+        assert!(check_merkle_paths(&hashes, &siblings, &[-1, 3], root).is_err());
+        assert!(check_merkle_paths(&hashes, &siblings[1..], &[1, 3], root).is_err());
+    }
+}
@@ -0,0 +1,389 @@
+//! Native witness generator
+//!
+//! Instead of running the circom wasm, the witness is solved directly from the
+//! r1cs. Once the inputs are assigned, every `StorageProver` constraint (the
+//! Poseidon rounds of `PoseidonDigest` and `MerkleProof`, the switchers, ...)
+//! has at most one unknown wire that appears linearly, so it can be solved for
+//! that wire. The order constraints are solved in doesn't depend on the input
+//! values, so it's worked out once when the generator is created and every
+//! witness just replays it.
+//!
+//! The only signals that aren't determined by constraints are the path bits of
+//! `MerkleProof`, which circom assigns with `<--`. They're set from the path
+//! input using the names in the `.sym` file.
+//!
+//! Since the witness of the circuit is unique for given inputs and path bits,
+//! the result is identical to what the wasm computes.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use ark_bn254::{Bn254, Fr};
+use ark_circom::circom::{R1CSFile, R1CS};
+use ark_ff::{BigInteger, Field, One, PrimeField, Zero};
+use num_bigint::{BigInt, Sign};
+
+use crate::symbols::SymbolTable;
+use crate::witness::WitnessInputs;
+
+/// Wires holding the circuit inputs, public and private, of an r1cs file
+pub fn input_wires(file: &R1CSFile<Bn254>) -> Range<usize> {
+    let start = 1 + file.header.n_pub_out as usize;
+    start..start + (file.header.n_pub_in + file.header.n_prv_in) as usize
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    A,
+    B,
+    C,
+}
+
+/// Solve `constraint` for `wire`, which only appears on `side`
+#[derive(Debug, Clone)]
+struct Step {
+    constraint: usize,
+    wire: usize,
+    side: Side,
+    coeff_inv: Fr,
+}
+
+/// Wire set to bit `bit` of the `source` input wire
+#[derive(Debug, Clone)]
+struct BitHint {
+    wire: usize,
+    source: usize,
+    bit: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct NativeWitness {
+    num_wires: usize,
+    inputs: Vec<(String, Vec<usize>)>,
+    hints: Vec<BitHint>,
+    steps: Vec<Step>,
+    checks: Vec<usize>,
+}
+
+impl NativeWitness {
+    pub fn new(
+        r1cs: &R1CS<Bn254>,
+        input_wires: Range<usize>,
+        symbols: &SymbolTable,
+    ) -> Result<Self, String> {
+        let num_wires = r1cs.num_variables;
+        let wires: HashMap<&str, usize> = symbols
+            .symbols()
+            .iter()
+            .filter_map(|s| s.wire.map(|w| (s.name.as_str(), w as usize)))
+            .collect();
+
+        // inputs in the order circom flattens them, which is the symbol order
+        let mut inputs: Vec<(String, Vec<usize>)> = Vec::new();
+        for symbol in symbols.symbols() {
+            let wire = match symbol.wire {
+                Some(wire) if input_wires.contains(&(wire as usize)) => wire as usize,
+                _ => continue,
+            };
+            let name = input_name(&symbol.name);
+            match inputs.iter_mut().find(|(n, _)| n == name) {
+                Some((_, wires)) => wires.push(wire),
+                None => inputs.push((name.to_string(), vec![wire])),
+            }
+        }
+
+        let num_inputs: usize = inputs.iter().map(|(_, wires)| wires.len()).sum();
+        if num_inputs != input_wires.len() {
+            return Err(format!(
+                "symbol file names {} inputs, the r1cs has {}",
+                num_inputs,
+                input_wires.len()
+            ));
+        }
+
+        let hints: Vec<BitHint> = symbols
+            .symbols()
+            .iter()
+            .filter_map(|s| {
+                let wire = s.wire? as usize;
+                let (component, bit) = path_bit(&s.name)?;
+                let source = path_source(&wires, component)?;
+                input_wires
+                    .contains(&source)
+                    .then_some(BitHint { wire, source, bit })
+            })
+            .collect();
+
+        let mut known = vec![false; num_wires];
+        known[0] = true;
+        input_wires.for_each(|w| known[w] = true);
+        hints.iter().for_each(|h| known[h.wire] = true);
+
+        let (steps, checks) = plan(r1cs, &mut known)?;
+
+        if let Some(wire) = known.iter().position(|k| !k) {
+            let name = symbols.wire_name(wire as u64).unwrap_or("<unnamed>");
+            return Err(format!(
+                "wire {} ({}) can't be solved from the constraints",
+                wire, name
+            ));
+        }
+
+        Ok(Self {
+            num_wires,
+            inputs,
+            hints,
+            steps,
+            checks,
+        })
+    }
+
+    /// Compute the witness for `inputs`, indexed by wire, failing like the
+    /// wasm does if the inputs don't satisfy the circuit
+    pub fn calculate(&self, r1cs: &R1CS<Bn254>, inputs: &WitnessInputs) -> Result<Vec<Fr>, String> {
+        let (w, failed) = self.solve(r1cs, inputs)?;
+        if let Some(i) = failed {
+            return Err(format!("constraint {} is not satisfied", i));
+        }
+
+        for i in &self.checks {
+            let (a, b, c) = &r1cs.constraints[*i];
+            if eval(a, &w) * eval(b, &w) != eval(c, &w) {
+                return Err(format!("constraint {} is not satisfied", i));
+            }
+        }
+
+        Ok(w)
+    }
+
+    /// Solve the witness without checking the constraints that aren't used to
+    /// solve a wire, so it can be inspected when the inputs are wrong. A
+    /// constraint that would divide by zero leaves its wire at zero, the first
+    /// one that doesn't hold because of it is returned with the witness.
+    pub fn solve(
+        &self,
+        r1cs: &R1CS<Bn254>,
+        inputs: &WitnessInputs,
+    ) -> Result<(Vec<Fr>, Option<usize>), String> {
+        if let Some(name) = inputs
+            .keys()
+            .find(|name| !self.inputs.iter().any(|(n, _)| n == *name))
+        {
+            return Err(format!("unknown input {}", name));
+        }
+
+        let mut w = vec![Fr::zero(); self.num_wires];
+        w[0] = Fr::one();
+
+        for (name, wires) in &self.inputs {
+            let values = inputs
+                .get(name)
+                .ok_or_else(|| format!("missing input {}", name))?;
+            if values.len() != wires.len() {
+                return Err(format!(
+                    "expected {} values for input {}, got {}",
+                    wires.len(),
+                    name,
+                    values.len()
+                ));
+            }
+
+            for (wire, val) in wires.iter().zip(values) {
+                w[*wire] = to_field(val);
+            }
+        }
+
+        for hint in &self.hints {
+            let bit = w[hint.source].into_repr().get_bit(hint.bit);
+            w[hint.wire] = Fr::from(bit as u64);
+        }
+
+        let mut failed = None;
+        for step in &self.steps {
+            let (a, b, c) = &r1cs.constraints[step.constraint];
+
+            // the wire being solved is still zero, so it drops out of `eval`
+            let rest = match step.side {
+                Side::C => Some(eval(a, &w) * eval(b, &w) - eval(c, &w)),
+                Side::A => eval(b, &w)
+                    .inverse()
+                    .map(|inv| eval(c, &w) * inv - eval(a, &w)),
+                Side::B => eval(a, &w)
+                    .inverse()
+                    .map(|inv| eval(c, &w) * inv - eval(b, &w)),
+            };
+
+            match rest {
+                Some(rest) => w[step.wire] = rest * step.coeff_inv,
+                // with a zero divisor the constraint only holds if C is zero
+                None if failed.is_none() && !eval(c, &w).is_zero() => {
+                    failed = Some(step.constraint)
+                }
+                None => {}
+            }
+        }
+
+        Ok((w, failed))
+    }
+}
+
+/// Order the constraints so each solves one unknown wire, the ones that
+/// aren't used to solve a wire are checked at the end
+fn plan(r1cs: &R1CS<Bn254>, known: &mut [bool]) -> Result<(Vec<Step>, Vec<usize>), String> {
+    let num_constraints = r1cs.constraints.len();
+    let mut unknowns = vec![0usize; num_constraints];
+    let mut users: Vec<Vec<usize>> = vec![Vec::new(); known.len()];
+
+    for (i, (a, b, c)) in r1cs.constraints.iter().enumerate() {
+        let mut wires: Vec<usize> = a.iter().chain(b).chain(c).map(|(w, _)| *w).collect();
+        wires.sort_unstable();
+        wires.dedup();
+
+        for wire in wires {
+            if wire >= known.len() {
+                return Err(format!("constraint {} uses unknown wire {}", i, wire));
+            }
+            if !known[wire] {
+                unknowns[i] += 1;
+                users[wire].push(i);
+            }
+        }
+    }
+
+    let mut queue: Vec<usize> = (0..num_constraints).filter(|i| unknowns[*i] == 1).collect();
+    let mut used = vec![false; num_constraints];
+    let mut steps = Vec::new();
+
+    while let Some(i) = queue.pop() {
+        if used[i] || unknowns[i] != 1 {
+            continue;
+        }
+
+        let (a, b, c) = &r1cs.constraints[i];
+        let wire = match a.iter().chain(b).chain(c).find(|(w, _)| !known[*w]) {
+            Some((wire, _)) => *wire,
+            None => continue,
+        };
+
+        let coeff = |lc: &[(usize, Fr)]| -> Fr {
+            lc.iter()
+                .filter(|(w, _)| *w == wire)
+                .map(|(_, coeff)| *coeff)
+                .sum()
+        };
+        let (in_a, in_b, in_c) = (coeff(a), coeff(b), coeff(c));
+        let (side, coeff) = match (in_a.is_zero(), in_b.is_zero(), in_c.is_zero()) {
+            (true, true, false) => (Side::C, in_c),
+            (false, true, true) => (Side::A, in_a),
+            (true, false, true) => (Side::B, in_b),
+            // quadratic in the unknown wire
+            _ => continue,
+        };
+
+        steps.push(Step {
+            constraint: i,
+            wire,
+            side,
+            coeff_inv: coeff.inverse().ok_or("zero coefficient")?,
+        });
+        used[i] = true;
+        known[wire] = true;
+
+        for j in &users[wire] {
+            unknowns[*j] -= 1;
+            if unknowns[*j] == 1 {
+                queue.push(*j);
+            }
+        }
+    }
+
+    let checks = (0..num_constraints).filter(|i| !used[*i]).collect();
+    Ok((steps, checks))
+}
+
+pub(crate) fn eval(lc: &[(usize, Fr)], w: &[Fr]) -> Fr {
+    lc.iter().map(|(wire, coeff)| w[*wire] * coeff).sum()
+}
+
+/// Inputs are reduced modulo the field, negative values wrap around
+fn to_field(val: &BigInt) -> Fr {
+    let (sign, bytes) = val.to_bytes_le();
+    let val = Fr::from_le_bytes_mod_order(&bytes);
+    if sign == Sign::Minus {
+        -val
+    } else {
+        val
+    }
+}
+
+/// `main.chunks[1][2]` is an element of the `chunks` input
+fn input_name(name: &str) -> &str {
+    let name = name.strip_prefix("main.").unwrap_or(name);
+    name.split('[').next().unwrap_or(name)
+}
+
+/// Component and bit index of a `MerkleProof` path bit, e.g.
+/// `main.merkelizer[3].indexBits.out[7]`
+fn path_bit(name: &str) -> Option<(&str, usize)> {
+    let (component, bit) = name.rsplit_once(".indexBits.out[")?;
+    Some((component, bit.strip_suffix(']')?.parse().ok()?))
+}
+
+/// Wire the path bits of `component` are taken from. `pathIndices` is usually
+/// optimized away, in `StorageProver` it's the same wire as `path[i]`.
+fn path_source(wires: &HashMap<&str, usize>, component: &str) -> Option<usize> {
+    if let Some(wire) = wires.get(format!("{}.pathIndices", component).as_str()) {
+        return Some(*wire);
+    }
+
+    let (parent, index) = component.rsplit_once(".merkelizer[")?;
+    wires
+        .get(format!("{}.path[{}", parent, index).as_str())
+        .copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_bn254::{Bn254, Fr};
+    use ark_circom::circom::R1CS;
+
+    use super::NativeWitness;
+    use crate::symbols::SymbolTable;
+    use crate::witness::{push_input, WitnessInputs};
+
+    #[test]
+    fn test_zero_divisor() {
+        // z * x = y, solved as z = y / x
+        let one = Fr::from(1u32);
+        let r1cs = R1CS::<Bn254> {
+            num_inputs: 3,
+            num_aux: 1,
+            num_variables: 4,
+            constraints: vec![(vec![(3, one)], vec![(1, one)], vec![(2, one)])],
+            wire_mapping: None,
+        };
+        let symbols = SymbolTable::parse("1,1,0,main.x\n2,2,0,main.y\n3,3,0,main.z\n").unwrap();
+        let native = NativeWitness::new(&r1cs, 1..3, &symbols).unwrap();
+
+        let inputs = |x: u64, y: u64| {
+            let mut inputs = WitnessInputs::new();
+            push_input(&mut inputs, "x", x);
+            push_input(&mut inputs, "y", y);
+            inputs
+        };
+
+        let (w, failed) = native.solve(&r1cs, &inputs(2, 6)).unwrap();
+        assert_eq!((w[3], failed), (Fr::from(3u32), None));
+
+        // 0 * z = 0 holds for any z
+        let (w, failed) = native.solve(&r1cs, &inputs(0, 0)).unwrap();
+        assert_eq!((w[3], failed), (Fr::from(0u32), None));
+
+        let (_, failed) = native.solve(&r1cs, &inputs(0, 6)).unwrap();
+        assert_eq!(failed, Some(0));
+        assert_eq!(
+            native.calculate(&r1cs, &inputs(0, 6)),
+            Err("constraint 0 is not satisfied".to_string())
+        );
+    }
+}
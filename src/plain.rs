@@ -0,0 +1,402 @@
+//! Plain storage proofs
+//!
+//! Where zero-knowledge isn't needed, in private clusters or while debugging,
+//! a plain proof simply carries the challenged chunks with their digests and
+//! Merkle paths. The verifier recomputes them natively with the same rules as
+//! `StorageProver`, which is far cheaper than proving, but reveals the chunks.
+//!
+//! Plain proofs travel in the same envelopes as SNARKs. There's no r1cs, so
+//! the circuit id of a `PlainProver` is derived from its `CircuitParams`, and
+//! its key hash from the proof format version.
+
+use ark_serialize::CanonicalSerialize;
+use rmpv::decode::read_value;
+use rmpv::encode::write_value;
+use rmpv::Value;
+use ruint::aliases::U256;
+
+use crate::envelope::{sha256, CircuitId, EnvelopeError, ProofEnvelope, ENVELOPE_VERSION};
+use crate::merkle::{digest, verify_merkle_path};
+use crate::public_inputs::{PublicInputs, StoragePublicInputs, PUBLIC_INPUT_NAMES};
+use crate::registry::CircuitParams;
+use crate::storage_proofs::{StorageProofInput, EXT_ID_U256_LE};
+
+pub const PLAIN_PROOF_VERSION: u64 = 1;
+
+/// One challenged chunk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlainQuery {
+    pub chunk: Vec<U256>,
+    pub hash: U256,
+    pub siblings: Vec<U256>,
+    pub index: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlainStorageProof {
+    pub queries: Vec<PlainQuery>,
+    pub root: U256,
+    pub salt: U256,
+}
+
+impl PlainStorageProof {
+    /// Split the flattened inputs of `StorageProver` into queries, failing
+    /// like proving would if they don't satisfy the circuit
+    pub fn new(params: &CircuitParams, input: &StorageProofInput) -> Result<Self, String> {
+        if !params.fits(
+            input.chunks.len(),
+            input.siblings.len(),
+            input.hashes.len(),
+            input.path.len(),
+        ) {
+            return Err(format!("inputs don't fit circuit {}", params));
+        }
+
+        let queries = input
+            .chunks
+            .chunks(params.block_size)
+            .zip(input.siblings.chunks(params.levels))
+            .zip(&input.hashes)
+            .zip(&input.path)
+            .map(|(((chunk, siblings), hash), index)| PlainQuery {
+                chunk: chunk.to_vec(),
+                hash: *hash,
+                siblings: siblings.to_vec(),
+                // negative indices don't fit the tree either
+                index: u64::try_from(*index).unwrap_or(u64::MAX),
+            })
+            .collect();
+
+        let proof = Self {
+            queries,
+            root: input.root,
+            salt: input.salt,
+        };
+        proof.check(params)?;
+        Ok(proof)
+    }
+
+    pub fn public_inputs(&self) -> StoragePublicInputs {
+        StoragePublicInputs {
+            root: self.root,
+            salt: self.salt,
+        }
+    }
+
+    /// Check every query against the `StorageProver` constraints: the chunk
+    /// digest is its hash, and the hash with the siblings leads to the root
+    pub fn check(&self, params: &CircuitParams) -> Result<(), String> {
+        if self.queries.len() != params.query_len {
+            return Err(format!(
+                "expected {} queries, got {}",
+                params.query_len,
+                self.queries.len()
+            ));
+        }
+
+        for (i, query) in self.queries.iter().enumerate() {
+            if query.chunk.len() != params.block_size || query.siblings.len() != params.levels {
+                return Err(format!("query {} doesn't fit circuit {}", i, params));
+            }
+            if digest(&query.chunk, params.digest_chunk) != query.hash {
+                return Err(format!("query {}: chunk doesn't match its hash", i));
+            }
+            if !verify_merkle_path(query.hash, &query.siblings, query.index, self.root) {
+                return Err(format!("query {}: path doesn't lead to the root", i));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        let ext = |n: &U256| Value::Ext(EXT_ID_U256_LE, n.to_le_bytes_vec());
+        let queries = self
+            .queries
+            .iter()
+            .map(|query| {
+                Value::Map(vec![
+                    ("chunk".into(), Value::Array(query.chunk.iter().map(ext).collect())),
+                    ("hash".into(), ext(&query.hash)),
+                    (
+                        "siblings".into(),
+                        Value::Array(query.siblings.iter().map(ext).collect()),
+                    ),
+                    ("index".into(), query.index.into()),
+                ])
+            })
+            .collect();
+
+        let proof = Value::Map(vec![
+            ("version".into(), PLAIN_PROOF_VERSION.into()),
+            ("queries".into(), Value::Array(queries)),
+            ("root".into(), ext(&self.root)),
+            ("salt".into(), ext(&self.salt)),
+        ]);
+
+        let mut bytes = Vec::new();
+        write_value(&mut bytes, &proof).map_err(|e| e.to_string())?;
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, String> {
+        let val = read_value(&mut bytes).map_err(|e| e.to_string())?;
+
+        let version = get(&val, "version")?
+            .as_u64()
+            .ok_or("version must be an integer")?;
+        if version != PLAIN_PROOF_VERSION {
+            return Err(format!("unsupported plain proof version {}", version));
+        }
+
+        let queries = get(&val, "queries")?
+            .as_array()
+            .ok_or("queries must be an array")?
+            .iter()
+            .map(|query| {
+                Ok(PlainQuery {
+                    chunk: get_u256s(query, "chunk")?,
+                    hash: get_u256(query, "hash")?,
+                    siblings: get_u256s(query, "siblings")?,
+                    index: get(query, "index")?
+                        .as_u64()
+                        .ok_or("index must be an integer")?,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(Self {
+            queries,
+            root: get_u256(&val, "root")?,
+            salt: get_u256(&val, "salt")?,
+        })
+    }
+}
+
+fn get<'a>(val: &'a Value, key: &str) -> Result<&'a Value, String> {
+    val.as_map()
+        .and_then(|m| m.iter().find(|(k, _)| k.as_str() == Some(key)))
+        .map(|(_, v)| v)
+        .ok_or_else(|| format!("missing field {}", key))
+}
+
+fn to_u256(val: &Value) -> Option<U256> {
+    match val {
+        Value::Ext(EXT_ID_U256_LE, bytes) => U256::try_from_le_slice(bytes),
+        _ => None,
+    }
+}
+
+fn get_u256(val: &Value, key: &str) -> Result<U256, String> {
+    to_u256(get(val, key)?).ok_or_else(|| format!("bad value for {}", key))
+}
+
+fn get_u256s(val: &Value, key: &str) -> Result<Vec<U256>, String> {
+    get(val, key)?
+        .as_array()
+        .and_then(|vals| vals.iter().map(to_u256).collect())
+        .ok_or_else(|| format!("bad values for {}", key))
+}
+
+/// Proves and verifies plain proofs for the circuit of `params`, with the
+/// same calls as `StorageProofs`
+#[derive(Debug, Clone)]
+pub struct PlainProver {
+    params: CircuitParams,
+    circuit_id: CircuitId,
+    vk_hash: [u8; 32],
+}
+
+impl PlainProver {
+    pub fn new(params: CircuitParams) -> Self {
+        Self {
+            params,
+            circuit_id: CircuitId {
+                num_constraints: 0,
+                num_public_inputs: PUBLIC_INPUT_NAMES.len() as u64,
+                num_wires: 0,
+                r1cs_hash: sha256(format!("plain {}", params).as_bytes()),
+            },
+            vk_hash: sha256(format!("plain v{}", PLAIN_PROOF_VERSION).as_bytes()),
+        }
+    }
+
+    pub fn params(&self) -> &CircuitParams {
+        &self.params
+    }
+
+    pub fn circuit_id(&self) -> &CircuitId {
+        &self.circuit_id
+    }
+
+    /// Same outputs as `StorageProofs::prove_input`, the public inputs are
+    /// ark serialized field elements like a SNARK's
+    pub fn prove_input(
+        &self,
+        input: &StorageProofInput,
+        proof_bytes: &mut Vec<u8>,
+        public_inputs_bytes: &mut Vec<u8>,
+    ) -> Result<StoragePublicInputs, String> {
+        let proof = PlainStorageProof::new(&self.params, input)?;
+
+        proof_bytes.extend(proof.to_bytes()?);
+        public_inputs(&proof)
+            .values(&names())?
+            .serialize(public_inputs_bytes)
+            .map_err(|e| e.to_string())?;
+
+        Ok(proof.public_inputs())
+    }
+
+    pub fn verify_storage_proof(
+        &self,
+        proof_bytes: &[u8],
+        root: U256,
+        salt: U256,
+    ) -> Result<(), String> {
+        let proof = PlainStorageProof::from_bytes(proof_bytes)?;
+        if proof.root != root || proof.salt != salt {
+            return Err("proof is for another root or salt".to_string());
+        }
+
+        proof.check(&self.params)
+    }
+
+    pub fn envelope(&self, proof_bytes: &[u8]) -> Result<ProofEnvelope, String> {
+        let proof = PlainStorageProof::from_bytes(proof_bytes)?;
+
+        Ok(ProofEnvelope {
+            version: ENVELOPE_VERSION,
+            circuit_id: self.circuit_id.clone(),
+            vk_hash: self.vk_hash,
+            public_inputs: public_inputs(&proof),
+            proof: proof_bytes.to_vec(),
+        })
+    }
+
+    /// Same as `StorageProofs::verify_envelope`
+    pub fn verify_envelope(&self, envelope: &[u8]) -> Result<(), EnvelopeError> {
+        let envelope = ProofEnvelope::from_bytes(envelope)?;
+
+        if envelope.circuit_id != self.circuit_id {
+            return Err(EnvelopeError::CircuitMismatch);
+        }
+        if envelope.vk_hash != self.vk_hash {
+            return Err(EnvelopeError::VerifyingKeyMismatch);
+        }
+
+        let inputs = StoragePublicInputs::try_from(&envelope.public_inputs)
+            .map_err(EnvelopeError::Malformed)?;
+        let proof =
+            PlainStorageProof::from_bytes(&envelope.proof).map_err(EnvelopeError::Malformed)?;
+        if proof.public_inputs() != inputs || proof.check(&self.params).is_err() {
+            return Err(EnvelopeError::InvalidProof);
+        }
+
+        Ok(())
+    }
+}
+
+fn names() -> Vec<String> {
+    PUBLIC_INPUT_NAMES.iter().map(|n| n.to_string()).collect()
+}
+
+fn public_inputs(proof: &PlainStorageProof) -> PublicInputs {
+    PublicInputs::from_pairs(vec![
+        ("root".to_string(), proof.root),
+        ("salt".to_string(), proof.salt),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use ruint::aliases::U256;
+
+    use super::{PlainProver, PlainStorageProof};
+    use crate::envelope::EnvelopeError;
+    use crate::merkle::{digest, MerkleTree};
+    use crate::registry::CircuitParams;
+    use crate::storage_proofs::StorageProofInput;
+
+    fn input(params: &CircuitParams, indices: &[usize]) -> StorageProofInput {
+        let blocks: Vec<Vec<U256>> = (0..4u64)
+            .map(|b| {
+                (0..params.block_size as u64)
+                    .map(|s| U256::from(b * 100 + s))
+                    .collect()
+            })
+            .collect();
+        let leaves: Vec<U256> = blocks
+            .iter()
+            .map(|block| digest(block, params.digest_chunk))
+            .collect();
+        let tree = MerkleTree::new(&leaves, params.levels).unwrap();
+
+        StorageProofInput {
+            chunks: indices.iter().flat_map(|i| blocks[*i].clone()).collect(),
+            siblings: indices.iter().flat_map(|i| tree.siblings(*i)).collect(),
+            hashes: indices.iter().map(|i| leaves[*i]).collect(),
+            path: indices.iter().map(|i| *i as i32).collect(),
+            root: tree.root(),
+            salt: U256::from(42),
+        }
+    }
+
+    #[test]
+    fn test_plain_proof() {
+        let params = CircuitParams::new(8, 2, 2, 4);
+        let prover = PlainProver::new(params);
+        let input = input(&params, &[1, 2]);
+
+        let proof_bytes = &mut Vec::new();
+        let public_inputs_bytes = &mut Vec::new();
+        let inputs = prover
+            .prove_input(&input, proof_bytes, public_inputs_bytes)
+            .unwrap();
+        assert_eq!(inputs.root, input.root);
+        assert!(!public_inputs_bytes.is_empty());
+
+        let proof = PlainStorageProof::from_bytes(proof_bytes).unwrap();
+        assert_eq!(proof, PlainStorageProof::new(&params, &input).unwrap());
+        assert!(prover
+            .verify_storage_proof(proof_bytes, input.root, input.salt)
+            .is_ok());
+        assert!(prover
+            .verify_storage_proof(proof_bytes, input.root, U256::from(43))
+            .is_err());
+
+        let envelope = prover.envelope(proof_bytes).unwrap().to_bytes().unwrap();
+        assert!(prover.verify_envelope(&envelope).is_ok());
+        let other = PlainProver::new(CircuitParams::new(8, 2, 3, 4));
+        assert_eq!(
+            other.verify_envelope(&envelope),
+            Err(EnvelopeError::CircuitMismatch)
+        );
+
+        // a forged chunk is caught like the digest constraint would
+        let mut forged = proof.clone();
+        forged.queries[1].chunk[0] += U256::from(1);
+        assert_eq!(
+            forged.check(&params),
+            Err("query 1: chunk doesn't match its hash".to_string())
+        );
+        let mut envelope = prover.envelope(proof_bytes).unwrap();
+        envelope.proof = forged.to_bytes().unwrap();
+        assert_eq!(
+            prover.verify_envelope(&envelope.to_bytes().unwrap()),
+            Err(EnvelopeError::InvalidProof)
+        );
+
+        // so is a leaf at the wrong index, or one that doesn't fit the tree
+        let mut input = input;
+        input.path.swap(0, 1);
+        assert_eq!(
+            PlainStorageProof::new(&params, &input),
+            Err("query 0: path doesn't lead to the root".to_string())
+        );
+        input.path = vec![1, 6];
+        assert!(PlainStorageProof::new(&params, &input).is_err());
+        input.path = vec![1, -2];
+        assert!(PlainStorageProof::new(&params, &input).is_err());
+    }
+}
@@ -0,0 +1,249 @@
+//! Proof systems
+//!
+//! Groth16 proofs are the smallest and cheapest to verify, but every circuit
+//! needs its own trusted setup. Marlin proves the same r1cs with a universal
+//! SRS instead: one setup, sized for the largest circuit, serves every
+//! circuit, which only has to be indexed. Its proofs are larger and slower to
+//! compute and verify.
+
+use std::fmt;
+use std::sync::Arc;
+
+use ark_bn254::{Bn254, Fr};
+use ark_circom::circom::R1CS;
+use ark_circom::CircomReduction;
+use ark_groth16::{
+    create_random_proof_with_reduction, prepare_verifying_key, verify_proof, PreparedVerifyingKey,
+    Proof, ProvingKey,
+};
+use ark_marlin::{IndexProverKey, IndexVerifierKey, Marlin, SimpleHashFiatShamirRng};
+use ark_poly::univariate::DensePolynomial;
+use ark_poly_commit::marlin_pc::MarlinKZG10;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, Read};
+use ark_std::rand::rngs::ThreadRng;
+use ark_std::rand::RngCore;
+use blake2::Blake2s;
+use rand_chacha::ChaChaRng;
+
+use crate::ceremony::MpcParams;
+use crate::circuit::StorageCircuit;
+use crate::envelope::{sha256, vk_hash};
+
+type MarlinPc = MarlinKZG10<Bn254, DensePolynomial<Fr>>;
+type MarlinBn254 = Marlin<Fr, MarlinPc, SimpleHashFiatShamirRng<Blake2s, ChaChaRng>>;
+
+/// Universal SRS of Marlin, fit for every circuit up to the size it was
+/// generated for
+pub type MarlinSrs = ark_marlin::UniversalSRS<Fr, MarlinPc>;
+
+/// Which proof system a prover uses, chosen when it's created
+#[derive(Debug, Clone)]
+pub enum ProofSystem {
+    /// Groth16 with the keys of this `.zkey`, or keys from local randomness
+    Groth16(Option<String>),
+    /// Marlin, indexing the circuit with this universal SRS, or one from
+    /// local randomness
+    Marlin(Option<Arc<MarlinSrs>>),
+}
+
+/// Sizes `universal_setup` needs to index `r1cs`: constraints, variables
+/// with the public inputs padded to a power of two, and non-zero entries.
+/// Marlin may swap the `A` and `B` terms of a constraint to balance them, so
+/// either can end up with all of their entries.
+pub fn marlin_bounds(r1cs: &R1CS<Bn254>) -> (usize, usize, usize) {
+    let (mut a, mut b, mut c) = (0, 0, 0);
+    for (a_terms, b_terms, c_terms) in &r1cs.constraints {
+        a += a_terms.len();
+        b += b_terms.len();
+        c += c_terms.len();
+    }
+    let num_variables = r1cs.num_inputs.next_power_of_two() + r1cs.num_aux;
+
+    (r1cs.constraints.len(), num_variables, (a + b).max(c))
+}
+
+/// Marlin SRS large enough for `r1cs` from local randomness, like Groth16 keys
+/// without a zkey it's only fit for tests
+pub fn marlin_setup<R: RngCore>(r1cs: &R1CS<Bn254>, rng: &mut R) -> Result<MarlinSrs, String> {
+    let (num_constraints, num_variables, num_non_zero) = marlin_bounds(r1cs);
+    MarlinBn254::universal_setup(num_constraints, num_variables, num_non_zero, rng)
+        .map_err(|e| format!("{:?}", e))
+}
+
+pub fn read_marlin_srs(bytes: &[u8]) -> Result<MarlinSrs, String> {
+    MarlinSrs::deserialize(bytes).map_err(|e| e.to_string())
+}
+
+pub fn write_marlin_srs(srs: &MarlinSrs) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    srs.serialize(&mut bytes).map_err(|e| e.to_string())?;
+    Ok(bytes)
+}
+
+/// Keys of the proof system a prover was created with
+pub(crate) enum ProvingKeys {
+    /// Keys in the layout of snarkjs, with the phase 2 transcript they came
+    /// out of if it's known
+    Groth16 {
+        params: ProvingKey<Bn254>,
+        pvk: PreparedVerifyingKey<Bn254>,
+        mpc: Option<MpcParams>,
+    },
+    Marlin {
+        pk: IndexProverKey<Fr, MarlinPc>,
+        vk: IndexVerifierKey<Fr, MarlinPc>,
+    },
+}
+
+impl fmt::Debug for ProvingKeys {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("ProvingKeys").field(&self.name()).finish()
+    }
+}
+
+impl ProvingKeys {
+    pub(crate) fn groth16(params: ProvingKey<Bn254>, mpc: Option<MpcParams>) -> Self {
+        let pvk = prepare_verifying_key(&params.vk);
+        Self::Groth16 { params, pvk, mpc }
+    }
+
+    pub(crate) fn marlin(srs: &MarlinSrs, r1cs: &R1CS<Bn254>) -> Result<Self, String> {
+        let (pk, vk) = MarlinBn254::index(srs, StorageCircuit::setup(r1cs))
+            .map_err(|e| format!("unable to index the circuit: {:?}", e))?;
+        Ok(Self::Marlin { pk, vk })
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Self::Groth16 { .. } => "Groth16",
+            Self::Marlin { .. } => "Marlin",
+        }
+    }
+
+    /// Groth16 parameters, for the features that only exist for Groth16
+    pub(crate) fn params(&self) -> Result<&ProvingKey<Bn254>, String> {
+        match self {
+            Self::Groth16 { params, .. } => Ok(params),
+            _ => Err(format!("{} provers have no Groth16 keys", self.name())),
+        }
+    }
+
+    pub(crate) fn mpc_params(&self) -> Option<&MpcParams> {
+        match self {
+            Self::Groth16 { mpc, .. } => mpc.as_ref(),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn prepared_vk(&self) -> Option<&PreparedVerifyingKey<Bn254>> {
+        match self {
+            Self::Groth16 { pvk, .. } => Some(pvk),
+            _ => None,
+        }
+    }
+
+    /// Hash of the verifying key, for proof envelopes
+    pub(crate) fn vk_hash(&self) -> Result<[u8; 32], String> {
+        match self {
+            Self::Groth16 { params, .. } => vk_hash(&params.vk),
+            Self::Marlin { vk, .. } => {
+                let mut bytes = Vec::new();
+                vk.serialize(&mut bytes).map_err(|e| e.to_string())?;
+                Ok(sha256(&bytes))
+            }
+        }
+    }
+
+    /// Serialized proof of `circuit`
+    pub(crate) fn prove<R: RngCore>(
+        &self,
+        circuit: StorageCircuit<'_>,
+        rng: &mut R,
+    ) -> Result<Vec<u8>, String> {
+        let mut proof_bytes = Vec::new();
+        match self {
+            Self::Groth16 { params, .. } => {
+                create_random_proof_with_reduction::<_, _, _, CircomReduction>(circuit, params, rng)
+                    .map_err(|e| e.to_string())?
+                    .serialize(&mut proof_bytes)
+            }
+            Self::Marlin { pk, .. } => MarlinBn254::prove(pk, circuit, rng)
+                .map_err(|e| format!("{:?}", e))?
+                .serialize(&mut proof_bytes),
+        }
+        .map_err(|e| e.to_string())?;
+
+        Ok(proof_bytes)
+    }
+
+    /// Whether a serialized proof is valid for `inputs`, an error if it can't
+    /// be read
+    pub(crate) fn verify<R: Read>(&self, proof_bytes: R, inputs: &[Fr]) -> Result<bool, String> {
+        match self {
+            Self::Groth16 { pvk, .. } => {
+                let proof = Proof::<Bn254>::deserialize(proof_bytes).map_err(|e| e.to_string())?;
+                verify_proof(pvk, &proof, inputs).map_err(|e| e.to_string())
+            }
+            Self::Marlin { vk, .. } => {
+                let proof = ark_marlin::Proof::<Fr, MarlinPc>::deserialize(proof_bytes)
+                    .map_err(|e| e.to_string())?;
+                MarlinBn254::verify(vk, inputs, &proof, &mut ThreadRng::default())
+                    .map_err(|e| format!("{:?}", e))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_bn254::{Bn254, Fr};
+    use ark_circom::circom::R1CS;
+    use ark_std::rand::{rngs::StdRng, SeedableRng};
+
+    use super::{marlin_setup, read_marlin_srs, write_marlin_srs, ProvingKeys};
+    use crate::circuit::StorageCircuit;
+
+    #[test]
+    fn test_marlin() {
+        let mut rng = StdRng::seed_from_u64(42);
+
+        // x * y = z with public z
+        let one = Fr::from(1u32);
+        let r1cs = R1CS::<Bn254> {
+            num_inputs: 2,
+            num_aux: 2,
+            num_variables: 4,
+            constraints: vec![(vec![(2, one)], vec![(3, one)], vec![(1, one)])],
+            wire_mapping: None,
+        };
+        let srs = marlin_setup(&r1cs, &mut rng).unwrap();
+        let srs = read_marlin_srs(&write_marlin_srs(&srs).unwrap()).unwrap();
+        let keys = ProvingKeys::marlin(&srs, &r1cs).unwrap();
+        assert!(keys.params().is_err());
+        assert!(keys.prepared_vk().is_none());
+
+        let witness: Vec<Fr> = [1u32, 12, 3, 4].into_iter().map(Fr::from).collect();
+        let proof = keys
+            .prove(StorageCircuit::new(&r1cs, witness), &mut rng)
+            .unwrap();
+
+        assert!(keys.verify(proof.as_slice(), &[Fr::from(12u32)]).unwrap());
+        assert!(!keys
+            .verify(proof.as_slice(), &[Fr::from(13u32)])
+            .unwrap_or(false));
+        assert!(keys
+            .verify(&proof[..proof.len() - 1], &[Fr::from(12u32)])
+            .is_err());
+
+        // the same SRS indexes other circuits of the same size
+        let square = R1CS::<Bn254> {
+            constraints: vec![(vec![(2, one)], vec![(2, one)], vec![(1, one)])],
+            ..r1cs.clone()
+        };
+        let other = ProvingKeys::marlin(&srs, &square).unwrap();
+        assert_ne!(other.vk_hash().unwrap(), keys.vk_hash().unwrap());
+        assert!(!other
+            .verify(proof.as_slice(), &[Fr::from(12u32)])
+            .unwrap_or(false));
+    }
+}
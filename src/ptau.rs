@@ -0,0 +1,448 @@
+//! Reader for snarkjs `.ptau` files, phase 1 of the BN254 powers of tau
+//!
+//! Section 1 is the header with the field element size, the prime, the power
+//! and the ceremony power. Sections 2 to 6 hold `tau^i G1` for
+//! `i < 2 * 2^power - 1`, `tau^i G2`, `alpha tau^i G1` and `beta tau^i G1` for
+//! `i < 2^power`, and `beta G2`. Section 7 lists the phase 1 contributions.
+//!
+//! Files prepared for phase 2 also have sections 12 to 15, the same points in
+//! the Lagrange basis of every domain `2^p` for `p <= power`, one domain after
+//! the other.
+
+use std::io::{Read, Seek, Write};
+
+use ark_bn254::{Bn254, Fq, Fr, G1Affine, G1Projective, G2Affine, G2Projective};
+use ark_circom::circom::R1CS;
+use ark_ec::msm::VariableBaseMSM;
+use ark_ec::{AffineCurve, PairingEngine, ProjectiveCurve};
+use ark_ff::{FpParameters, PrimeField, UniformRand, Zero};
+use ark_poly::{EvaluationDomain, GeneralEvaluationDomain};
+use ark_std::rand::rngs::ThreadRng;
+
+use crate::binfile::{
+    read_bigint, write_bigint, write_bin, write_g1, write_g2, BinReader, FIELD_SIZE,
+};
+
+const MAGIC: &[u8; 4] = b"ptau";
+const VERSION: u32 = 1;
+
+const SECTION_HEADER: u32 = 1;
+const SECTION_TAU_G1: u32 = 2;
+const SECTION_TAU_G2: u32 = 3;
+const SECTION_ALPHA_TAU_G1: u32 = 4;
+const SECTION_BETA_TAU_G1: u32 = 5;
+const SECTION_BETA_G2: u32 = 6;
+const SECTION_CONTRIBUTIONS: u32 = 7;
+const SECTION_LAGRANGE_TAU_G1: u32 = 12;
+const SECTION_LAGRANGE_TAU_G2: u32 = 13;
+const SECTION_LAGRANGE_ALPHA_TAU_G1: u32 = 14;
+const SECTION_LAGRANGE_BETA_TAU_G1: u32 = 15;
+
+/// Largest power of the BN254 scalar field's two-adic subgroup
+const MAX_POWER: u32 = 28;
+
+/// Size of the domain of the constraints and public inputs of `r1cs`. The H
+/// points of its keys are taken from twice this domain, so the powers of tau
+/// must be at least twice as large.
+pub fn required_domain_size(r1cs: &R1CS<Bn254>) -> Result<usize, String> {
+    GeneralEvaluationDomain::<Fr>::new(r1cs.constraints.len() + r1cs.num_inputs)
+        .map(|domain| domain.size())
+        .ok_or_else(|| "the circuit is too large".to_string())
+}
+
+/// The powers of tau needed for circuits with a domain of up to `domain_size`
+/// constraints
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PowersOfTau {
+    /// log2 of the largest domain the file supports
+    pub power: u32,
+    pub ceremony_power: u32,
+    /// `tau^i G1` for `i < 2 * domain_size - 1`
+    pub tau_g1: Vec<G1Affine>,
+    /// `tau^i G2` for `i < domain_size`
+    pub tau_g2: Vec<G2Affine>,
+    /// `alpha tau^i G1` for `i < domain_size`
+    pub alpha_tau_g1: Vec<G1Affine>,
+    /// `beta tau^i G1` for `i < domain_size`
+    pub beta_tau_g1: Vec<G1Affine>,
+    pub beta_g2: G2Affine,
+    /// The points in the Lagrange basis of the domain, if the file has them
+    pub lagrange: Option<Lagrange>,
+}
+
+/// `L_i(tau)` times the generators, `alpha` and `beta`, where `L_i` is the
+/// `i`th Lagrange polynomial of the domain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lagrange {
+    pub tau_g1: Vec<G1Affine>,
+    pub tau_g2: Vec<G2Affine>,
+    pub alpha_tau_g1: Vec<G1Affine>,
+    pub beta_tau_g1: Vec<G1Affine>,
+}
+
+impl PowersOfTau {
+    /// Read the points for a domain of `domain_size`, a power of two
+    pub fn read<R: Read + Seek>(reader: R, domain_size: usize) -> Result<Self, String> {
+        if !domain_size.is_power_of_two() {
+            return Err(format!("domain size {} is not a power of two", domain_size));
+        }
+
+        let mut file = BinReader::new(reader, MAGIC)?;
+        if file.version != VERSION {
+            return Err(format!("unsupported ptau version {}", file.version));
+        }
+
+        let header = file.read_section(SECTION_HEADER)?;
+        let (power, ceremony_power) = read_header(&header)?;
+        if domain_size > 1 << power {
+            return Err(format!(
+                "powers of tau of power {} are too small for a domain of {}",
+                power, domain_size
+            ));
+        }
+
+        // the domains of every smaller power come first
+        let lagrange = if file.has_section(SECTION_LAGRANGE_TAU_G1) {
+            let start = domain_size - 1;
+            Some(Lagrange {
+                tau_g1: file.read_g1s(SECTION_LAGRANGE_TAU_G1, start, domain_size)?,
+                tau_g2: file.read_g2s(SECTION_LAGRANGE_TAU_G2, start, domain_size)?,
+                alpha_tau_g1: file.read_g1s(SECTION_LAGRANGE_ALPHA_TAU_G1, start, domain_size)?,
+                beta_tau_g1: file.read_g1s(SECTION_LAGRANGE_BETA_TAU_G1, start, domain_size)?,
+            })
+        } else {
+            None
+        };
+
+        Ok(Self {
+            power,
+            ceremony_power,
+            tau_g1: file.read_g1s(SECTION_TAU_G1, 0, 2 * domain_size - 1)?,
+            tau_g2: file.read_g2s(SECTION_TAU_G2, 0, domain_size)?,
+            alpha_tau_g1: file.read_g1s(SECTION_ALPHA_TAU_G1, 0, domain_size)?,
+            beta_tau_g1: file.read_g1s(SECTION_BETA_TAU_G1, 0, domain_size)?,
+            beta_g2: file.read_g2s(SECTION_BETA_G2, 0, 1)?[0],
+            lagrange,
+        })
+    }
+
+    /// Read the points the phase 2 of `r1cs` needs, twice its domain, failing
+    /// if the file is too small for it
+    pub fn read_for_circuit<R: Read + Seek>(reader: R, r1cs: &R1CS<Bn254>) -> Result<Self, String> {
+        let domain_size = 2 * required_domain_size(r1cs)?;
+        Self::read(reader, domain_size).map_err(|e| {
+            format!(
+                "{} constraints and {} public inputs: {}",
+                r1cs.constraints.len(),
+                r1cs.num_inputs - 1,
+                e
+            )
+        })
+    }
+
+    /// The power of the file, without reading any points
+    pub fn read_power<R: Read + Seek>(reader: R) -> Result<u32, String> {
+        let mut file = BinReader::new(reader, MAGIC)?;
+        let header = file.read_section(SECTION_HEADER)?;
+        Ok(read_header(&header)?.0)
+    }
+
+    pub fn domain_size(&self) -> usize {
+        self.tau_g2.len()
+    }
+
+    /// Check that every point uses the same `tau`, `alpha` and `beta` and
+    /// that the Lagrange points, if any, match the powers.
+    ///
+    /// The powers are checked with random linear combinations, so this costs
+    /// a few multi-scalar multiplications and pairings whatever the size.
+    pub fn verify(&self) -> Result<(), String> {
+        let n = self.domain_size();
+        let g1 = G1Affine::prime_subgroup_generator();
+        let g2 = G2Affine::prime_subgroup_generator();
+
+        if self.tau_g1.len() != 2 * n - 1
+            || self.alpha_tau_g1.len() != n
+            || self.beta_tau_g1.len() != n
+        {
+            return Err("powers of tau have inconsistent lengths".to_string());
+        }
+        if self.tau_g1[0] != g1 || self.tau_g2[0] != g2 {
+            return Err("powers of tau don't start at the generators".to_string());
+        }
+        if n > 1 && (self.tau_g1[1].is_zero() || self.tau_g2[1].is_zero()) {
+            return Err("tau is zero".to_string());
+        }
+
+        let tau_g2 = self.tau_g2.get(1).copied().unwrap_or(g2);
+        let tau_g1 = self.tau_g1.get(1).copied().unwrap_or(g1);
+        let mut rng = ThreadRng::default();
+
+        for (name, points) in [
+            ("tau G1", &self.tau_g1),
+            ("alpha tau G1", &self.alpha_tau_g1),
+            ("beta tau G1", &self.beta_tau_g1),
+        ] {
+            let (before, after) = consecutive(points, &mut rng);
+            if !same_ratio((before, after), (g2, tau_g2)) {
+                return Err(format!("{} points are not consecutive powers", name));
+            }
+        }
+
+        let (before, after) = consecutive(&self.tau_g2, &mut rng);
+        if !same_ratio((g1, tau_g1), (before, after)) {
+            return Err("tau G2 points are not consecutive powers".to_string());
+        }
+
+        if !same_ratio((g1, self.beta_tau_g1[0]), (g2, self.beta_g2)) {
+            return Err("beta G1 and beta G2 don't match".to_string());
+        }
+
+        if let Some(lagrange) = &self.lagrange {
+            if *lagrange != self.compute_lagrange(n)? {
+                return Err("Lagrange points don't match the powers of tau".to_string());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The points in the Lagrange basis of a domain of `domain_size`, taken
+    /// from the file if it has them
+    pub fn lagrange(&self, domain_size: usize) -> Result<Lagrange, String> {
+        match &self.lagrange {
+            Some(lagrange) if domain_size == self.domain_size() => Ok(lagrange.clone()),
+            _ => self.compute_lagrange(domain_size),
+        }
+    }
+
+    /// The Lagrange basis at tau is the inverse FFT of the powers of tau
+    fn compute_lagrange(&self, domain_size: usize) -> Result<Lagrange, String> {
+        if !domain_size.is_power_of_two() || domain_size > self.domain_size() {
+            return Err(format!(
+                "no Lagrange points for a domain of {} from powers of tau for {}",
+                domain_size,
+                self.domain_size()
+            ));
+        }
+
+        let domain = GeneralEvaluationDomain::<Fr>::new(domain_size).unwrap();
+        let g1s = |powers: &[G1Affine]| {
+            let mut points: Vec<G1Projective> = powers[..domain_size]
+                .iter()
+                .map(|p| p.into_projective())
+                .collect();
+            domain.ifft_in_place(&mut points);
+            G1Projective::batch_normalization_into_affine(&points)
+        };
+
+        let mut tau_g2: Vec<G2Projective> = self.tau_g2[..domain_size]
+            .iter()
+            .map(|p| p.into_projective())
+            .collect();
+        domain.ifft_in_place(&mut tau_g2);
+
+        Ok(Lagrange {
+            tau_g1: g1s(&self.tau_g1),
+            tau_g2: G2Projective::batch_normalization_into_affine(&tau_g2),
+            alpha_tau_g1: g1s(&self.alpha_tau_g1),
+            beta_tau_g1: g1s(&self.beta_tau_g1),
+        })
+    }
+
+    /// Write a `.ptau` file, with the Lagrange sections if `prepared`. The
+    /// points must cover the whole `2^power` domain.
+    pub fn write<W: Write>(&self, writer: W, prepared: bool) -> Result<(), String> {
+        if self.domain_size() != 1 << self.power {
+            return Err(format!(
+                "powers of tau for a domain of {} can't be written as power {}",
+                self.domain_size(),
+                self.power
+            ));
+        }
+
+        let mut header = Vec::new();
+        header.extend_from_slice(&(FIELD_SIZE as u32).to_le_bytes());
+        write_bigint(&mut header, &<Fq as PrimeField>::Params::MODULUS);
+        header.extend_from_slice(&self.power.to_le_bytes());
+        header.extend_from_slice(&self.ceremony_power.to_le_bytes());
+
+        let g1s = |points: &[G1Affine]| {
+            let mut bytes = Vec::new();
+            points.iter().for_each(|p| write_g1(&mut bytes, p));
+            bytes
+        };
+        let g2s = |points: &[G2Affine]| {
+            let mut bytes = Vec::new();
+            points.iter().for_each(|p| write_g2(&mut bytes, p));
+            bytes
+        };
+
+        let mut sections = vec![
+            (SECTION_HEADER, header),
+            (SECTION_TAU_G1, g1s(&self.tau_g1)),
+            (SECTION_TAU_G2, g2s(&self.tau_g2)),
+            (SECTION_ALPHA_TAU_G1, g1s(&self.alpha_tau_g1)),
+            (SECTION_BETA_TAU_G1, g1s(&self.beta_tau_g1)),
+            (SECTION_BETA_G2, g2s(&[self.beta_g2])),
+            // the phase 1 transcript isn't kept
+            (SECTION_CONTRIBUTIONS, 0u32.to_le_bytes().to_vec()),
+        ];
+
+        if prepared {
+            let mut lagrange = [Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+            for p in 0..=self.power {
+                let points = self.lagrange(1 << p)?;
+                lagrange[0].extend(g1s(&points.tau_g1));
+                lagrange[1].extend(g2s(&points.tau_g2));
+                lagrange[2].extend(g1s(&points.alpha_tau_g1));
+                lagrange[3].extend(g1s(&points.beta_tau_g1));
+            }
+
+            let [tau_g1, tau_g2, alpha_tau_g1, beta_tau_g1] = lagrange;
+            sections.extend([
+                (SECTION_LAGRANGE_TAU_G1, tau_g1),
+                (SECTION_LAGRANGE_TAU_G2, tau_g2),
+                (SECTION_LAGRANGE_ALPHA_TAU_G1, alpha_tau_g1),
+                (SECTION_LAGRANGE_BETA_TAU_G1, beta_tau_g1),
+            ]);
+        }
+
+        write_bin(writer, MAGIC, VERSION, &sections)
+    }
+}
+
+fn read_header(header: &[u8]) -> Result<(u32, u32), String> {
+    let u32_at = |pos: usize| -> Result<u32, String> {
+        header
+            .get(pos..pos + 4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+            .ok_or_else(|| "ptau header is truncated".to_string())
+    };
+
+    let n8 = u32_at(0)? as usize;
+    let prime = header.get(4..4 + n8).ok_or("ptau header is truncated")?;
+    if n8 != FIELD_SIZE || read_bigint(prime) != <Fq as PrimeField>::Params::MODULUS {
+        return Err("powers of tau are not over bn254".to_string());
+    }
+
+    let power = u32_at(4 + n8)?;
+    if power > MAX_POWER {
+        return Err(format!("power {} is larger than bn254 supports", power));
+    }
+
+    Ok((power, u32_at(8 + n8)?))
+}
+
+/// Random linear combinations `sum r_i p_i` and `sum r_i p_{i+1}`, which are
+/// in the ratio tau exactly when every consecutive pair is, except with
+/// negligible probability
+fn consecutive<G: AffineCurve<ScalarField = Fr>>(points: &[G], rng: &mut ThreadRng) -> (G, G) {
+    if points.len() < 2 {
+        return (G::zero(), G::zero());
+    }
+
+    let scalars: Vec<_> = (0..points.len() - 1)
+        .map(|_| Fr::rand(rng).into_repr())
+        .collect();
+    let before = VariableBaseMSM::multi_scalar_mul(&points[..points.len() - 1], &scalars);
+    let after = VariableBaseMSM::multi_scalar_mul(&points[1..], &scalars);
+
+    (before.into_affine(), after.into_affine())
+}
+
+/// `e(a.0, b.1) == e(a.1, b.0)`, i.e. `a.1 / a.0 == b.1 / b.0` in the exponent
+pub(crate) fn same_ratio(a: (G1Affine, G1Affine), b: (G2Affine, G2Affine)) -> bool {
+    Bn254::pairing(a.0, b.1) == Bn254::pairing(a.1, b.0)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::io::Cursor;
+
+    use ark_bn254::{Fr, G1Affine, G1Projective, G2Affine};
+    use ark_ec::{AffineCurve, ProjectiveCurve};
+    use ark_ff::{Field, One, PrimeField, UniformRand};
+    use ark_std::rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::PowersOfTau;
+
+    /// Powers of tau of `power` for random `tau`, `alpha` and `beta`
+    pub(crate) fn generate<R: Rng>(power: u32, rng: &mut R) -> PowersOfTau {
+        let (tau, alpha, beta) = (Fr::rand(rng), Fr::rand(rng), Fr::rand(rng));
+        let n = 1usize << power;
+        let g1 = G1Affine::prime_subgroup_generator();
+        let g2 = G2Affine::prime_subgroup_generator();
+        let powers: Vec<Fr> = (0..2 * n - 1).map(|i| tau.pow([i as u64])).collect();
+        let g1s = |x: Fr, count: usize| {
+            powers[..count]
+                .iter()
+                .map(|p| g1.mul((x * p).into_repr()).into_affine())
+                .collect()
+        };
+
+        PowersOfTau {
+            power,
+            ceremony_power: power,
+            tau_g1: g1s(Fr::one(), 2 * n - 1),
+            tau_g2: powers[..n]
+                .iter()
+                .map(|p| g2.mul(p.into_repr()).into_affine())
+                .collect(),
+            alpha_tau_g1: g1s(alpha, n),
+            beta_tau_g1: g1s(beta, n),
+            beta_g2: g2.mul(beta.into_repr()).into_affine(),
+            lagrange: None,
+        }
+    }
+
+    #[test]
+    fn test_read_ptau() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let ptau = generate(3, &mut rng);
+        ptau.verify().unwrap();
+
+        let mut bytes = Vec::new();
+        ptau.write(&mut bytes, false).unwrap();
+        assert_eq!(PowersOfTau::read_power(Cursor::new(&bytes)).unwrap(), 3);
+        assert_eq!(PowersOfTau::read(Cursor::new(&bytes), 8).unwrap(), ptau);
+        assert!(PowersOfTau::read(Cursor::new(&bytes), 16).is_err());
+
+        // a smaller domain only needs the first powers
+        let small = PowersOfTau::read(Cursor::new(&bytes), 4).unwrap();
+        assert_eq!(small.tau_g1, ptau.tau_g1[..7]);
+        small.verify().unwrap();
+
+        let mut tampered = ptau.clone();
+        tampered.tau_g1.swap(2, 3);
+        assert!(tampered.verify().is_err());
+
+        let mut tampered = ptau.clone();
+        tampered.beta_g2 = tampered.tau_g2[1];
+        assert!(tampered.verify().is_err());
+    }
+
+    #[test]
+    fn test_ptau_lagrange() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let ptau = generate(3, &mut rng);
+
+        let mut bytes = Vec::new();
+        ptau.write(&mut bytes, true).unwrap();
+
+        for domain_size in [1, 2, 4, 8] {
+            let prepared = PowersOfTau::read(Cursor::new(&bytes), domain_size).unwrap();
+            let lagrange = prepared.lagrange.clone().unwrap();
+            assert_eq!(lagrange, ptau.lagrange(domain_size).unwrap());
+            prepared.verify().unwrap();
+
+            // the Lagrange polynomials sum to one
+            let sum: G1Projective = lagrange.tau_g1.iter().map(|p| p.into_projective()).sum();
+            assert_eq!(sum.into_affine(), G1Affine::prime_subgroup_generator());
+        }
+
+        let mut prepared = PowersOfTau::read(Cursor::new(&bytes), 8).unwrap();
+        prepared.lagrange.as_mut().unwrap().tau_g1.swap(0, 1);
+        assert!(prepared.verify().is_err());
+    }
+}
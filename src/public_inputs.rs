@@ -0,0 +1,127 @@
+//! Named public inputs
+//!
+//! `CircomCircuit::get_public_inputs` returns an anonymous vector of field
+//! elements. These types attach the signal names from the circuit metadata so
+//! callers don't have to know the order of the public signals.
+
+use ark_bn254::Fr;
+use ruint::aliases::U256;
+
+use crate::symbols::{signal_name, SymbolTable};
+
+// public signals of `StorageProver`, in the order they appear in the r1cs,
+// used when there is no `.sym` file next to the r1cs
+pub const PUBLIC_INPUT_NAMES: [&str; 2] = ["root", "salt"];
+
+/// Names of the public inputs of a circuit with `num_inputs` r1cs inputs
+/// (including the implicit `one` wire)
+pub fn public_input_names(symbols: Option<&SymbolTable>, num_inputs: usize) -> Vec<String> {
+    let names = symbols.and_then(|symbols| {
+        (1..num_inputs as u64)
+            .map(|wire| symbols.wire_name(wire).map(|n| signal_name(n).to_string()))
+            .collect::<Option<Vec<String>>>()
+    });
+
+    names.unwrap_or_else(|| PUBLIC_INPUT_NAMES.iter().map(|n| n.to_string()).collect())
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PublicInputs {
+    inputs: Vec<(String, U256)>,
+}
+
+impl PublicInputs {
+    pub fn new(names: &[String], values: &[Fr]) -> Result<Self, String> {
+        if names.len() != values.len() {
+            return Err(format!(
+                "expected {} public inputs, got {}",
+                names.len(),
+                values.len()
+            ));
+        }
+
+        Ok(Self {
+            inputs: names
+                .iter()
+                .zip(values)
+                .map(|(name, val)| (name.clone(), U256::from(*val)))
+                .collect(),
+        })
+    }
+
+    pub fn from_pairs(inputs: Vec<(String, U256)>) -> Self {
+        Self { inputs }
+    }
+
+    pub fn pairs(&self) -> &[(String, U256)] {
+        &self.inputs
+    }
+
+    pub fn get(&self, name: &str) -> Option<U256> {
+        self.inputs
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| *v)
+    }
+
+    /// Field elements in the order given by `names`, as the verifier expects them
+    pub fn values(&self, names: &[String]) -> Result<Vec<Fr>, String> {
+        names
+            .iter()
+            .map(|name| {
+                let val = self
+                    .get(name)
+                    .ok_or_else(|| format!("missing public input {}", name))?;
+                Fr::try_from(val).map_err(|e| e.to_string())
+            })
+            .collect()
+    }
+}
+
+/// Public inputs of the `StorageProver` circuit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StoragePublicInputs {
+    pub root: U256,
+    pub salt: U256,
+}
+
+impl TryFrom<&PublicInputs> for StoragePublicInputs {
+    type Error = String;
+
+    fn try_from(inputs: &PublicInputs) -> Result<Self, Self::Error> {
+        Ok(Self {
+            root: inputs.get("root").ok_or("missing public input root")?,
+            salt: inputs.get("salt").ok_or("missing public input salt")?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_bn254::Fr;
+    use ruint::aliases::U256;
+
+    use super::{public_input_names, PublicInputs, StoragePublicInputs};
+    use crate::symbols::SymbolTable;
+
+    #[test]
+    fn test_named_public_inputs() {
+        let symbols = SymbolTable::parse("1,1,0,main.salt\n2,2,0,main.root\n").unwrap();
+        let names = public_input_names(Some(&symbols), 3);
+        assert_eq!(names, vec!["salt".to_string(), "root".to_string()]);
+
+        let inputs = PublicInputs::new(&names, &[Fr::from(7u64), Fr::from(42u64)]).unwrap();
+        let storage = StoragePublicInputs::try_from(&inputs).unwrap();
+        assert_eq!(storage.root, U256::from(42));
+        assert_eq!(storage.salt, U256::from(7));
+
+        // the verifier order follows the names, not the order they were stored in
+        let values = inputs.values(&["root".to_string(), "salt".to_string()]);
+        assert_eq!(values.unwrap(), vec![Fr::from(42u64), Fr::from(7u64)]);
+    }
+
+    #[test]
+    fn test_default_public_input_names() {
+        assert_eq!(public_input_names(None, 3), vec!["root", "salt"]);
+    }
+}
@@ -0,0 +1,284 @@
+//! Registry of `StorageProver` circuits
+//!
+//! Holds one prover per `(BLOCK_SIZE, QUERY_LEN, LEVELS, DIGEST_CHUNK)`
+//! combination. Provers, and with them the zkeys, are only loaded the first
+//! time they're used, and the circuit for a proof is picked from the shape of
+//! its inputs.
+
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use once_cell::sync::OnceCell;
+
+use crate::circuit_info::CircuitInfo;
+use crate::public_inputs::StoragePublicInputs;
+use crate::storage_proofs::{parse_mpack_args, StorageProofInput, StorageProofs};
+use crate::witness::{WitnessBackend, WitnessInputs};
+use crate::zkey::ZkeyCheck;
+
+/// Template parameters of a `StorageProver` circuit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CircuitParams {
+    pub block_size: usize,
+    pub query_len: usize,
+    pub levels: usize,
+    pub digest_chunk: usize,
+}
+
+impl CircuitParams {
+    pub fn new(block_size: usize, query_len: usize, levels: usize, digest_chunk: usize) -> Self {
+        Self {
+            block_size,
+            query_len,
+            levels,
+            digest_chunk,
+        }
+    }
+
+    /// Whether inputs with these many chunk, sibling and hash values fit the
+    /// circuit. The digest chunk size doesn't change the shape of the inputs.
+    pub fn fits(&self, chunks: usize, siblings: usize, hashes: usize, path: usize) -> bool {
+        hashes == self.query_len
+            && path == self.query_len
+            && chunks == self.query_len * self.block_size
+            && siblings == self.query_len * self.levels
+    }
+
+    /// Whether the input dimensions of a circuit match these parameters. A
+    /// circuit without a `.sym` file has no dimensions to compare.
+    pub fn check(&self, info: &CircuitInfo) -> Result<(), String> {
+        if info.inputs.is_empty() {
+            return Ok(());
+        }
+
+        let expected = [
+            ("chunks", vec![self.query_len, self.block_size]),
+            ("siblings", vec![self.query_len, self.levels]),
+            ("path", vec![self.query_len]),
+            ("hashes", vec![self.query_len]),
+        ];
+        for (name, dims) in expected {
+            let input = info
+                .input(name)
+                .ok_or_else(|| format!("circuit {} has no {} input", self, name))?;
+            if input.dims != dims {
+                return Err(format!(
+                    "circuit {}: {} has dimensions {:?}, expected {:?}",
+                    self, name, input.dims, dims
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Formatted as `BLOCK_SIZE_QUERY_LEN_LEVELS_DIGEST_CHUNK`, like the suffix of
+/// `storer_main_256_80_32_16.circom`
+impl fmt::Display for CircuitParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}_{}_{}_{}",
+            self.block_size, self.query_len, self.levels, self.digest_chunk
+        )
+    }
+}
+
+impl FromStr for CircuitParams {
+    type Err = String;
+
+    fn from_str(id: &str) -> Result<Self, Self::Err> {
+        let params = id
+            .split('_')
+            .map(|p| {
+                p.parse::<usize>()
+                    .map_err(|e| format!("bad circuit id {}: {}", id, e))
+            })
+            .collect::<Result<Vec<usize>, String>>()?;
+
+        match params[..] {
+            [block_size, query_len, levels, digest_chunk] => {
+                Ok(Self::new(block_size, query_len, levels, digest_chunk))
+            }
+            _ => Err(format!("bad circuit id {}", id)),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Circuit {
+    params: CircuitParams,
+    wasm: String,
+    r1cs: String,
+    zkey: Option<String>,
+    prover: OnceCell<Arc<StorageProofs>>,
+}
+
+#[derive(Debug, Default)]
+pub struct ProverRegistry {
+    circuits: Vec<Circuit>,
+    num_threads: usize,
+}
+
+impl ProverRegistry {
+    /// Every prover gets a pool of `num_threads` threads, 0 uses one per core
+    pub fn new(num_threads: usize) -> Self {
+        Self {
+            circuits: Vec::new(),
+            num_threads,
+        }
+    }
+
+    /// Add a circuit, its files are only loaded when it's first used
+    pub fn register(
+        &mut self,
+        params: CircuitParams,
+        wasm: String,
+        r1cs: String,
+        zkey: Option<String>,
+    ) -> Result<(), String> {
+        if self.circuits.iter().any(|c| c.params == params) {
+            return Err(format!("circuit {} is already registered", params));
+        }
+
+        for path in [Some(&wasm), Some(&r1cs), zkey.as_ref()]
+            .into_iter()
+            .flatten()
+        {
+            if !Path::new(path).exists() {
+                return Err(format!("{} doesn't exist", path));
+            }
+        }
+
+        self.circuits.push(Circuit {
+            params,
+            wasm,
+            r1cs,
+            zkey,
+            prover: OnceCell::new(),
+        });
+        Ok(())
+    }
+
+    pub fn params(&self) -> Vec<CircuitParams> {
+        self.circuits.iter().map(|c| c.params).collect()
+    }
+
+    pub fn is_loaded(&self, params: &CircuitParams) -> bool {
+        self.circuit(params)
+            .map(|c| c.prover.get().is_some())
+            .unwrap_or(false)
+    }
+
+    /// Prover for `params`, loading it on first use. Loading errors aren't
+    /// cached, the next call tries again.
+    pub fn prover(&self, params: &CircuitParams) -> Result<Arc<StorageProofs>, String> {
+        let circuit = self
+            .circuit(params)
+            .ok_or_else(|| format!("circuit {} isn't registered", params))?;
+
+        let prover = circuit.prover.get_or_try_init(|| {
+            let prover = StorageProofs::load(
+                WitnessBackend::Wasm(circuit.wasm.clone()),
+                circuit.r1cs.clone(),
+                circuit.zkey.clone(),
+                self.num_threads,
+                ZkeyCheck::Shape,
+            )?;
+            params.check(prover.circuit_info())?;
+            Ok::<_, String>(Arc::new(prover))
+        })?;
+        Ok(prover.clone())
+    }
+
+    /// Prover for a circuit id such as `256_80_32_16`
+    pub fn prover_by_id(&self, id: &str) -> Result<Arc<StorageProofs>, String> {
+        self.prover(&id.parse()?)
+    }
+
+    /// Prover for the circuit matching the shape of `input`
+    pub fn select(&self, input: &StorageProofInput) -> Result<Arc<StorageProofs>, String> {
+        self.select_shape(
+            input.chunks.len(),
+            input.siblings.len(),
+            input.hashes.len(),
+            input.path.len(),
+        )
+    }
+
+    pub fn prove_input(
+        &self,
+        input: &StorageProofInput,
+        proof_bytes: &mut Vec<u8>,
+        public_inputs_bytes: &mut Vec<u8>,
+    ) -> Result<StoragePublicInputs, String> {
+        self.select(input)?
+            .prove_input(input, proof_bytes, public_inputs_bytes)
+    }
+
+    /// Same as `prove_input`, for mpack encoded inputs
+    pub fn prove_mpack(
+        &self,
+        inputs: &[u8],
+        proof_bytes: &mut Vec<u8>,
+        public_inputs_bytes: &mut Vec<u8>,
+    ) -> Result<(Arc<StorageProofs>, StoragePublicInputs), String> {
+        let mut witness_inputs = WitnessInputs::new();
+        parse_mpack_args(&mut witness_inputs, inputs)?;
+
+        let len = |name: &str| witness_inputs.get(name).map(|v| v.len()).unwrap_or(0);
+        let prover =
+            self.select_shape(len("chunks"), len("siblings"), len("hashes"), len("path"))?;
+        let inputs =
+            prover.prove_witness_inputs(witness_inputs, proof_bytes, public_inputs_bytes)?;
+
+        Ok((prover, inputs))
+    }
+
+    fn select_shape(
+        &self,
+        chunks: usize,
+        siblings: usize,
+        hashes: usize,
+        path: usize,
+    ) -> Result<Arc<StorageProofs>, String> {
+        let mut matches = self
+            .circuits
+            .iter()
+            .filter(|c| c.params.fits(chunks, siblings, hashes, path));
+
+        match (matches.next(), matches.next()) {
+            (Some(circuit), None) => self.prover(&circuit.params),
+            (Some(a), Some(b)) => Err(format!(
+                "inputs fit both circuit {} and {}",
+                a.params, b.params
+            )),
+            (None, _) => Err(format!(
+                "no circuit for {} chunks, {} siblings and {} hashes",
+                chunks, siblings, hashes
+            )),
+        }
+    }
+
+    fn circuit(&self, params: &CircuitParams) -> Option<&Circuit> {
+        self.circuits.iter().find(|c| c.params == *params)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CircuitParams;
+
+    #[test]
+    fn test_circuit_params_id() {
+        let params = CircuitParams::new(256, 80, 32, 16);
+        assert_eq!(params.to_string(), "256_80_32_16");
+        assert_eq!("256_80_32_16".parse::<CircuitParams>(), Ok(params));
+        assert!("256_80_32".parse::<CircuitParams>().is_err());
+
+        assert!(params.fits(80 * 256, 80 * 32, 80, 80));
+        assert!(!params.fits(80 * 256, 79 * 32, 80, 80));
+    }
+}
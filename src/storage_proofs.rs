@@ -1,87 +1,366 @@
 use std::fs::File;
+use std::io::{Cursor, Seek, Write};
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
 
 use ark_bn254::{Bn254, Fr};
-use ark_circom::{read_zkey, CircomBuilder, CircomConfig, CircomCircuit};
-use ark_groth16::{
-    create_random_proof as prove, generate_random_parameters, prepare_verifying_key, verify_proof,
-    Proof, ProvingKey,
-};
+use ark_circom::circom::{R1CSFile, R1CS};
+use ark_circom::read_zkey;
+use ark_ff::One;
+use ark_groth16::{Proof, ProvingKey, VerifyingKey};
 use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, Read};
 use ark_std::rand::rngs::ThreadRng;
+use once_cell::sync::OnceCell;
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
 use ruint::aliases::U256;
 
+use crate::aggregate::{
+    aggregate_bytes, verify_aggregate_bytes, AggregationSrs, AggregationVerifierKey,
+};
+use crate::batch;
+use crate::ceremony::Phase2;
+use crate::check::{describe_constraints, failing_constraints, unsatisfied, ConstraintFailure};
+use crate::circuit::StorageCircuit;
+use crate::circuit_info::CircuitInfo;
+use crate::envelope::{CircuitId, EnvelopeError, ProofEnvelope, ENVELOPE_VERSION};
+use crate::merkle::check_merkle_paths;
+use crate::native_witness::{input_wires, NativeWitness};
+use crate::proof_system::{marlin_setup, ProofSystem, ProvingKeys};
+use crate::public_inputs::{public_input_names, PublicInputs, StoragePublicInputs};
+use crate::symbols::SymbolTable;
+use crate::verifier::{export_vk, rerandomize_bytes, Verifier, VkFormat};
+use crate::witness::{push_input, WitnessBackend, WitnessGenerator, WitnessInputs, WitnessPool};
+use crate::wtns::{read_wtns, write_wtns};
+use crate::zkey::{check_zkey, read_mpc_params, write_zkey, ZkeyCheck};
+
 use rmpv;
 use rmpv::decode::read_value;
 
-type Params256Ty = ark_ec::bn::Bn<ark_bn254::Parameters>;
-
 pub const EXT_ID_U256_LE: i8 = 50;
 pub const EXT_ID_U256_BE: i8 = 51;
 
+pub const PROOF_CANCELLED: &str = "proof cancelled";
+
+/// Coarse stages of computing a proof
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProofStage {
+    Witness,
+    Proving,
+    Serialization,
+}
+
 
-#[derive(Debug, Clone)]
+/// Inputs of a single `StorageProver` proof
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StorageProofInput {
+    pub chunks: Vec<U256>,
+    pub siblings: Vec<U256>,
+    pub hashes: Vec<U256>,
+    pub path: Vec<i32>,
+    pub root: U256,
+    pub salt: U256,
+}
+
+impl StorageProofInput {
+    pub fn witness_inputs(&self) -> WitnessInputs {
+        witness_inputs(
+            &self.chunks,
+            &self.siblings,
+            &self.hashes,
+            &self.path,
+            self.root,
+            self.salt,
+        )
+    }
+}
+
+#[derive(Debug)]
 pub struct StorageProofs {
-    builder: CircomBuilder<Bn254>,
-    params: ProvingKey<Bn254>,
-    rng: ThreadRng,
+    r1cs: R1CS<Bn254>,
+    symbols: Option<SymbolTable>,
+    input_wires: Range<usize>,
+    witness: WitnessGenerator,
+    // native solver used by `check_inputs`, built on first use
+    solver: OnceCell<Result<NativeWitness, String>>,
+    keys: ProvingKeys,
+    circuit_id: CircuitId,
+    info: CircuitInfo,
+    vk_hash: [u8; 32],
+    public_input_names: Vec<String>,
+    pool: RwLock<Arc<ThreadPool>>,
+    // check the Merkle paths natively before proving
+    check_paths: AtomicBool,
 }
 
 impl StorageProofs {
-    // TODO: add rng
-    pub fn new(
-        wtns: String,
+    /// `num_threads` is the size of the pool proofs are computed on,
+    /// 0 uses one thread per core
+    pub fn new(wtns: String, r1cs: String, zkey: Option<String>, num_threads: usize) -> Self {
+        Self::with_witness(WitnessBackend::Wasm(wtns), r1cs, zkey, num_threads)
+    }
+
+    /// Same as `new`, computing witnesses with `backend`
+    pub fn with_witness(
+        backend: WitnessBackend,
         r1cs: String,
-        zkey: Option<String>, /* , rng: Option<ThreadRng> */
+        zkey: Option<String>,
+        num_threads: usize,
     ) -> Self {
-        let mut rng = ThreadRng::default();
-        let builder = CircomBuilder::new(CircomConfig::<Bn254>::new(wtns, r1cs).unwrap());
-        let params: ProvingKey<Bn254> = match zkey {
+        Self::load(backend, r1cs, zkey, num_threads, ZkeyCheck::Shape)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Same as `with_witness`, returning an error instead of panicking if a
+    /// file can't be loaded or the zkey wasn't generated for the r1cs
+    pub fn load(
+        backend: WitnessBackend,
+        r1cs: String,
+        zkey: Option<String>,
+        num_threads: usize,
+        check: ZkeyCheck,
+    ) -> Result<Self, String> {
+        Self::build(backend, r1cs, num_threads, |r1cs, pool| match zkey {
             Some(zkey) => {
-                let mut file = File::open(zkey).unwrap();
-                read_zkey(&mut file).unwrap().0
+                let mut file = File::open(&zkey).map_err(|e| format!("{}: {}", zkey, e))?;
+                let mpc = read_mpc_params(&mut file).map_err(|e| format!("{}: {}", zkey, e))?;
+                file.rewind().map_err(|e| format!("{}: {}", zkey, e))?;
+                let (params, matrices) =
+                    read_zkey(&mut file).map_err(|e| format!("{}: {}", zkey, e))?;
+                check_zkey(&params, Some(&matrices), r1cs, check)
+                    .map_err(|e| format!("{}: {}", zkey, e))?;
+                Ok(ProvingKeys::groth16(params, Some(mpc)))
             }
-            None => generate_random_parameters::<Bn254, _, _>(builder.setup(), &mut rng).unwrap(),
-        };
+            None => {
+                let phase2 = pool.install(|| Phase2::dev(r1cs, &mut ThreadRng::default()))?;
+                let (params, mpc) = phase2.into_parts();
+                Ok(ProvingKeys::groth16(params, Some(mpc)))
+            }
+        })
+    }
 
-        Self {
-            builder,
-            params,
-            rng,
+    /// Same as `load`, proving with the proof `system`. Only Groth16 provers
+    /// have a verifying key to export, a zkey, re-randomized proofs and
+    /// aggregation, the rest of the API is the same for every system.
+    pub fn with_proof_system(
+        backend: WitnessBackend,
+        r1cs: String,
+        system: ProofSystem,
+        num_threads: usize,
+    ) -> Result<Self, String> {
+        match system {
+            ProofSystem::Groth16(zkey) => {
+                Self::load(backend, r1cs, zkey, num_threads, ZkeyCheck::Shape)
+            }
+            ProofSystem::Marlin(srs) => Self::build(backend, r1cs, num_threads, |r1cs, pool| {
+                pool.install(|| match srs {
+                    Some(srs) => ProvingKeys::marlin(&srs, r1cs),
+                    None => {
+                        let srs = marlin_setup(r1cs, &mut ThreadRng::default())?;
+                        ProvingKeys::marlin(&srs, r1cs)
+                    }
+                })
+            }),
         }
     }
 
+    /// Same as `load`, proving with already loaded `params`, such as the ark
+    /// serialized keys written by `codex-proofs setup`. They must have the H
+    /// points of snarkjs, like the keys of `Phase2`.
+    pub fn with_proving_key(
+        backend: WitnessBackend,
+        r1cs: String,
+        params: ProvingKey<Bn254>,
+        num_threads: usize,
+    ) -> Result<Self, String> {
+        Self::build(backend, r1cs, num_threads, |r1cs, _| {
+            check_zkey(&params, None, r1cs, ZkeyCheck::Shape)?;
+            Ok(ProvingKeys::groth16(params, None))
+        })
+    }
+
+    fn build(
+        backend: WitnessBackend,
+        r1cs_path: String,
+        num_threads: usize,
+        keys: impl FnOnce(&R1CS<Bn254>, &ThreadPool) -> Result<ProvingKeys, String>,
+    ) -> Result<Self, String> {
+        let pool = build_pool(num_threads)?;
+        let r1cs_bytes =
+            std::fs::read(&r1cs_path).map_err(|e| format!("{}: {}", r1cs_path, e))?;
+        let symbols = SymbolTable::for_r1cs(&r1cs_path);
+        let r1cs_file = R1CSFile::<Bn254>::new(Cursor::new(&r1cs_bytes))
+            .map_err(|e| format!("{}: {}", r1cs_path, e))?;
+        let inputs = input_wires(&r1cs_file);
+        let info = CircuitInfo::new(&r1cs_file, symbols.as_ref());
+        let r1cs: R1CS<Bn254> = r1cs_file.into();
+        // before loading the witness generator, a mismatched zkey fails fast
+        let keys = keys(&r1cs, &pool)?;
+        let witness = match backend {
+            WitnessBackend::Wasm(wtns) => WitnessGenerator::Wasm(
+                WitnessPool::new(&wtns).map_err(|e| format!("{}: {}", wtns, e))?,
+            ),
+            WitnessBackend::Native => {
+                let symbols = symbols.as_ref().ok_or_else(|| {
+                    format!(
+                        "{}: the native witness generator needs the .sym file",
+                        r1cs_path
+                    )
+                })?;
+                let native = NativeWitness::new(&r1cs, inputs.clone(), symbols)
+                    .map_err(|e| format!("{}: {}", r1cs_path, e))?;
+                WitnessGenerator::Native(native)
+            }
+        };
+        let circuit_id = CircuitId::new(&r1cs, &r1cs_bytes);
+        let public_input_names = public_input_names(symbols.as_ref(), r1cs.num_inputs);
+        let vk_hash = keys.vk_hash()?;
+
+        Ok(Self {
+            r1cs,
+            symbols,
+            input_wires: inputs,
+            witness,
+            solver: OnceCell::new(),
+            keys,
+            circuit_id,
+            info,
+            vk_hash,
+            public_input_names,
+            pool: RwLock::new(Arc::new(pool)),
+            check_paths: AtomicBool::new(false),
+        })
+    }
+
+    pub fn num_threads(&self) -> usize {
+        self.pool().current_num_threads()
+    }
+
+    /// Resize the proving pool, proofs already running finish on the old one
+    pub fn set_num_threads(&self, num_threads: usize) -> Result<(), String> {
+        let pool = build_pool(num_threads)?;
+        *self.pool.write().map_err(|e| e.to_string())? = Arc::new(pool);
+        Ok(())
+    }
+
+    pub fn witness_instances(&self) -> usize {
+        self.witness.max_instances()
+    }
+
+    /// Allow up to `instances` witnesses to be computed concurrently. Every
+    /// instance keeps its own wasm memory, so this trades memory for
+    /// throughput in `prove_many`.
+    pub fn set_witness_instances(&self, instances: usize) {
+        self.witness.set_max_instances(instances)
+    }
+
+    pub fn check_paths(&self) -> bool {
+        self.check_paths.load(Ordering::Relaxed)
+    }
+
+    /// Have every proof from inputs, mpack encoded or not, check that each
+    /// query's hash, siblings and path lead to the root before computing the
+    /// witness, so inconsistent inputs fail fast with the index of the query.
+    /// Proofs from a complete `.wtns` witness aren't affected.
+    pub fn set_check_paths(&self, enabled: bool) {
+        self.check_paths.store(enabled, Ordering::Relaxed)
+    }
+
+    pub(crate) fn pool(&self) -> Arc<ThreadPool> {
+        self.pool.read().unwrap().clone()
+    }
+
+    pub fn circuit_id(&self) -> &CircuitId {
+        &self.circuit_id
+    }
+
+    pub fn circuit_info(&self) -> &CircuitInfo {
+        &self.info
+    }
+
+    pub fn vk_hash(&self) -> [u8; 32] {
+        self.vk_hash
+    }
+
+    pub fn public_input_names(&self) -> &[String] {
+        &self.public_input_names
+    }
+
+    /// Name of the proof system, see `ProofSystem`
+    pub fn proof_system(&self) -> &'static str {
+        self.keys.name()
+    }
+
+    /// Groth16 verifying key, an error for other proof systems
+    pub fn verifying_key(&self) -> Result<&VerifyingKey<Bn254>, String> {
+        Ok(&self.keys.params()?.vk)
+    }
+
+    pub fn export_vk(&self, format: VkFormat) -> Result<Vec<u8>, String> {
+        export_vk(self.verifying_key()?, format)
+    }
+
+    /// Write the proving key as a `.zkey`, whether it was read from one or
+    /// generated. Keys passed to `with_proving_key` have no transcript to
+    /// write.
+    pub fn write_zkey<W: Write>(&self, writer: W) -> Result<(), String> {
+        let params = self.keys.params()?;
+        let mpc = self
+            .keys
+            .mpc_params()
+            .ok_or_else(|| "the proving key has no phase 2 transcript to write".to_string())?;
+        write_zkey(writer, params, &self.r1cs, mpc)
+    }
+
+    /// A fresh proof for the same public inputs that can't be linked to
+    /// `proof_bytes`, it verifies exactly when the original does
+    pub fn rerandomize(&self, proof_bytes: &[u8]) -> Result<Vec<u8>, String> {
+        rerandomize_bytes(proof_bytes, self.verifying_key()?)
+    }
+
+    /// Verifier for this circuit that doesn't hold on to the proving key
+    pub fn verifier(&self) -> Result<Verifier, String> {
+        Ok(Verifier::new(self.verifying_key()?.clone()))
+    }
+
     pub fn prove_mpack(
-        &mut self,
+        &self,
         inputs: &[u8],
         proof_bytes: &mut Vec<u8>,
         public_inputs_bytes: &mut Vec<u8>,
-    ) -> Result<(), String> {
-        let mut builder: CircomBuilder<Params256Ty> = self.builder.clone();
-
-        parse_mpack_args(&mut builder, inputs)?;
+    ) -> Result<StoragePublicInputs, String> {
+        self.prove_mpack_with_progress(inputs, proof_bytes, public_inputs_bytes, &|_| true)
+    }
 
-        let circuit: CircomCircuit<Params256Ty> = builder.build()
-            .map_err(|e| e.to_string())?;
+    /// Prove from circuit inputs by signal name
+    pub fn prove_witness_inputs(
+        &self,
+        inputs: WitnessInputs,
+        proof_bytes: &mut Vec<u8>,
+        public_inputs_bytes: &mut Vec<u8>,
+    ) -> Result<StoragePublicInputs, String> {
+        self.prove_inputs(inputs, proof_bytes, public_inputs_bytes, &|_| true)
+    }
 
-        let inputs = circuit
-            .get_public_inputs()
-            .ok_or("Unable to get public inputs!")?;
-        let proof =
-            prove(circuit, &self.params, &mut self.rng)
-            .map_err(|e| e.to_string())?;
+    /// Same as `prove_mpack`, calling `on_stage` before each stage of the
+    /// proof. Returning false from it stops proving with `PROOF_CANCELLED`.
+    pub fn prove_mpack_with_progress(
+        &self,
+        inputs: &[u8],
+        proof_bytes: &mut Vec<u8>,
+        public_inputs_bytes: &mut Vec<u8>,
+        on_stage: &dyn Fn(ProofStage) -> bool,
+    ) -> Result<StoragePublicInputs, String> {
+        let mut witness_inputs = WitnessInputs::new();
 
-        proof
-            .serialize(proof_bytes)
-            .map_err(|e| e.to_string())?;
-        inputs
-            .serialize(public_inputs_bytes)
-            .map_err(|e| e.to_string())?;
+        parse_mpack_args(&mut witness_inputs, inputs)?;
 
-        Ok(())
+        self.prove_inputs(witness_inputs, proof_bytes, public_inputs_bytes, on_stage)
     }
 
     pub fn prove(
-        &mut self,
+        &self,
         chunks: &[U256],
         siblings: &[U256],
         hashes: &[U256],
@@ -90,50 +369,449 @@ impl StorageProofs {
         salt: U256,
         proof_bytes: &mut Vec<u8>,
         public_inputs_bytes: &mut Vec<u8>,
-    ) -> Result<(), String> {
-        let mut builder = self.builder.clone();
+    ) -> Result<StoragePublicInputs, String> {
+        let inputs = witness_inputs(chunks, siblings, hashes, path, root, salt);
 
-        // vec of vecs is flattened, since wasm expects a contiguous array in memory
-        chunks.iter().for_each(|c| builder.push_input("chunks", *c));
+        self.prove_inputs(inputs, proof_bytes, public_inputs_bytes, &|_| true)
+    }
 
-        siblings
-            .iter()
-            .for_each(|c| builder.push_input("siblings", *c));
+    pub fn prove_input(
+        &self,
+        input: &StorageProofInput,
+        proof_bytes: &mut Vec<u8>,
+        public_inputs_bytes: &mut Vec<u8>,
+    ) -> Result<StoragePublicInputs, String> {
+        self.prove(
+            &input.chunks,
+            &input.siblings,
+            &input.hashes,
+            &input.path,
+            input.root,
+            input.salt,
+            proof_bytes,
+            public_inputs_bytes,
+        )
+    }
 
-        hashes.iter().for_each(|c| builder.push_input("hashes", *c));
-        path.iter().for_each(|c| builder.push_input("path", *c));
+    /// Prove several independent inputs concurrently on the proving pool,
+    /// returning the proof and public inputs bytes for each of them in order
+    pub fn prove_many(
+        &self,
+        inputs: &[StorageProofInput],
+    ) -> Vec<Result<(Vec<u8>, Vec<u8>), String>> {
+        self.run_many(inputs, |input, proof_bytes, public_inputs_bytes| {
+            self.prove_input(input, proof_bytes, public_inputs_bytes)
+        })
+    }
+
+    /// Same as `prove_many`, but for mpack encoded inputs
+    pub fn prove_mpack_many(
+        &self,
+        inputs: &[&[u8]],
+    ) -> Vec<Result<(Vec<u8>, Vec<u8>), String>> {
+        self.run_many(inputs, |input, proof_bytes, public_inputs_bytes| {
+            self.prove_mpack(input, proof_bytes, public_inputs_bytes)
+        })
+    }
+
+    fn run_many<T, F>(&self, inputs: &[T], prove_one: F) -> Vec<Result<(Vec<u8>, Vec<u8>), String>>
+    where
+        T: Sync,
+        F: Fn(&T, &mut Vec<u8>, &mut Vec<u8>) -> Result<StoragePublicInputs, String> + Sync,
+    {
+        self.pool().install(|| {
+            inputs
+                .par_iter()
+                .map(|input| {
+                    let mut proof_bytes = Vec::new();
+                    let mut public_inputs_bytes = Vec::new();
+                    prove_one(input, &mut proof_bytes, &mut public_inputs_bytes)?;
+                    Ok((proof_bytes, public_inputs_bytes))
+                })
+                .collect()
+        })
+    }
+
+    /// Prove from a witness in the iden3 `.wtns` format, e.g. computed by
+    /// circom's C++ witness generator, without running the witness calculator.
+    /// The witness is checked against every constraint first, the error names
+    /// the first one it doesn't satisfy.
+    pub fn prove_wtns(
+        &self,
+        wtns: &[u8],
+        proof_bytes: &mut Vec<u8>,
+        public_inputs_bytes: &mut Vec<u8>,
+    ) -> Result<StoragePublicInputs, String> {
+        let witness = read_wtns(wtns)?;
+        self.check_witness_len(&witness)?;
+
+        if witness[0] != Fr::one() {
+            return Err("wire 0 of the witness must be 1".to_string());
+        }
+        if let Some(failure) =
+            failing_constraints(&self.r1cs, &witness, self.symbols.as_ref(), 1).first()
+        {
+            return Err(failure.to_string());
+        }
 
-        builder.push_input("root", root);
-        builder.push_input("salt", salt);
+        self.prove_witness(witness, proof_bytes, public_inputs_bytes, &|_| true)
+    }
+
+    /// Compute the witness for `input` and encode it as `.wtns`
+    pub fn export_wtns(&self, input: &StorageProofInput) -> Result<Vec<u8>, String> {
+        let witness = self.witness.calculate(&self.r1cs, input.witness_inputs())?;
+        Ok(write_wtns(&witness))
+    }
+
+    /// Same as `export_wtns`, for mpack encoded inputs
+    pub fn export_wtns_mpack(&self, inputs: &[u8]) -> Result<Vec<u8>, String> {
+        let mut witness_inputs = WitnessInputs::new();
+        parse_mpack_args(&mut witness_inputs, inputs)?;
+
+        let witness = self.witness.calculate(&self.r1cs, witness_inputs)?;
+        Ok(write_wtns(&witness))
+    }
+
+    /// Check `input` against every constraint of the circuit without proving,
+    /// returning up to `max_failures` constraints it violates. An empty result
+    /// means a proof for it will verify.
+    pub fn check_inputs(
+        &self,
+        input: &StorageProofInput,
+        max_failures: usize,
+    ) -> Result<Vec<ConstraintFailure>, String> {
+        self.check_witness_inputs(input.witness_inputs(), max_failures)
+    }
+
+    /// Same as `check_inputs`, for mpack encoded inputs
+    pub fn check_inputs_mpack(
+        &self,
+        inputs: &[u8],
+        max_failures: usize,
+    ) -> Result<Vec<ConstraintFailure>, String> {
+        let mut witness_inputs = WitnessInputs::new();
+        parse_mpack_args(&mut witness_inputs, inputs)?;
+
+        self.check_witness_inputs(witness_inputs, max_failures)
+    }
+
+    fn check_witness_inputs(
+        &self,
+        inputs: WitnessInputs,
+        max_failures: usize,
+    ) -> Result<Vec<ConstraintFailure>, String> {
+        // the wasm stops at the first failed assertion without returning a
+        // witness, the native solver doesn't check anything. A constraint it
+        // couldn't solve for a zero divisor comes first, the rest of the
+        // witness is off because of it.
+        let (witness, failed) = self.solver()?.solve(&self.r1cs, &inputs)?;
+        let failing = failed
+            .into_iter()
+            .chain(unsatisfied(&self.r1cs, &witness).filter(|i| Some(*i) != failed))
+            .take(max_failures)
+            .collect();
+
+        Ok(describe_constraints(&self.r1cs, failing, self.symbols.as_ref()))
+    }
+
+    fn solver(&self) -> Result<&NativeWitness, String> {
+        if let WitnessGenerator::Native(native) = &self.witness {
+            return Ok(native);
+        }
+
+        self.solver
+            .get_or_init(|| {
+                let symbols = self
+                    .symbols
+                    .as_ref()
+                    .ok_or("checking inputs needs the .sym file")?;
+                NativeWitness::new(&self.r1cs, self.input_wires.clone(), symbols)
+            })
+            .as_ref()
+            .map_err(|e| e.clone())
+    }
+
+    fn prove_inputs(
+        &self,
+        inputs: WitnessInputs,
+        proof_bytes: &mut Vec<u8>,
+        public_inputs_bytes: &mut Vec<u8>,
+        on_stage: &dyn Fn(ProofStage) -> bool,
+    ) -> Result<StoragePublicInputs, String> {
+        if self.check_paths() {
+            check_input_paths(&inputs)?;
+        }
+
+        next_stage(on_stage, ProofStage::Witness)?;
+        let witness = self.witness.calculate(&self.r1cs, inputs)?;
+
+        self.prove_witness(witness, proof_bytes, public_inputs_bytes, on_stage)
+    }
+
+    fn check_witness_len(&self, witness: &[Fr]) -> Result<(), String> {
+        if witness.len() != self.r1cs.num_variables {
+            return Err(format!(
+                "expected a witness of {} wires, got {}",
+                self.r1cs.num_variables,
+                witness.len()
+            ));
+        }
+        Ok(())
+    }
+
+    fn prove_witness(
+        &self,
+        witness: Vec<Fr>,
+        proof_bytes: &mut Vec<u8>,
+        public_inputs_bytes: &mut Vec<u8>,
+        on_stage: &dyn Fn(ProofStage) -> bool,
+    ) -> Result<StoragePublicInputs, String> {
+        self.check_witness_len(&witness)?;
+
+        let circuit = StorageCircuit::new(&self.r1cs, witness);
 
-        let circuit = builder.build().map_err(|e| e.to_string())?;
         let inputs = circuit
             .get_public_inputs()
             .ok_or("Unable to get public inputs!")?;
-        let proof = prove(circuit, &self.params, &mut self.rng).map_err(|e| e.to_string())?;
 
-        proof.serialize(proof_bytes).map_err(|e| e.to_string())?;
+        next_stage(on_stage, ProofStage::Proving)?;
+        let proof = self
+            .pool()
+            .install(|| self.keys.prove(circuit, &mut ThreadRng::default()))?;
+
+        next_stage(on_stage, ProofStage::Serialization)?;
+        proof_bytes.extend_from_slice(&proof);
         inputs
             .serialize(public_inputs_bytes)
             .map_err(|e| e.to_string())?;
 
-        Ok(())
+        let inputs = PublicInputs::new(&self.public_input_names, &inputs)?;
+        StoragePublicInputs::try_from(&inputs)
     }
 
     pub fn verify<RR: Read>(
-        &mut self,
+        &self,
         proof_bytes: RR,
         mut public_inputs: RR,
     ) -> Result<(), String> {
         let inputs: Vec<Fr> =
             CanonicalDeserialize::deserialize(&mut public_inputs).map_err(|e| e.to_string())?;
-        let proof = Proof::<Bn254>::deserialize(proof_bytes).map_err(|e| e.to_string())?;
-        let vk = prepare_verifying_key(&self.params.vk);
 
-        verify_proof(&vk, &proof, inputs.as_slice()).map_err(|e| e.to_string())?;
+        if !self.keys.verify(proof_bytes, inputs.as_slice())? {
+            return Err("proof verification failed".to_string());
+        }
 
         Ok(())
     }
+
+    /// Verify a proof against the expected `root` and `salt`. The public inputs
+    /// are built here, so a proof for another dataset or challenge is rejected
+    /// even if the prover sent matching inputs along with it.
+    pub fn verify_storage_proof<RR: Read>(
+        &self,
+        proof_bytes: RR,
+        root: U256,
+        salt: U256,
+        pubkey: Option<U256>,
+    ) -> Result<(), String> {
+        let mut expected = vec![("root".to_string(), root), ("salt".to_string(), salt)];
+        if let Some(pubkey) = pubkey {
+            expected.push(("pubkey".to_string(), pubkey));
+        }
+
+        if let Some((name, _)) = expected
+            .iter()
+            .find(|(name, _)| !self.public_input_names.contains(name))
+        {
+            return Err(format!("circuit has no public input {}", name));
+        }
+
+        let inputs = PublicInputs::from_pairs(expected).values(&self.public_input_names)?;
+
+        if !self.keys.verify(proof_bytes, inputs.as_slice())? {
+            return Err("proof verification failed".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Verify many `(proof, public inputs)` pairs at once, returning the
+    /// indices of the invalid ones if the batch doesn't verify
+    pub fn verify_batch(&self, proofs: &[(&[u8], &[u8])]) -> Result<(), Vec<usize>> {
+        // only Groth16 proofs combine into a single check
+        let pvk = match self.keys.prepared_vk() {
+            Some(pvk) => pvk,
+            None => {
+                let invalid: Vec<usize> = proofs
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, (proof, inputs))| self.verify(*proof, *inputs).is_err())
+                    .map(|(i, _)| i)
+                    .collect();
+                return if invalid.is_empty() {
+                    Ok(())
+                } else {
+                    Err(invalid)
+                };
+            }
+        };
+
+        let mut invalid = Vec::new();
+        let mut indices = Vec::with_capacity(proofs.len());
+        let mut batch = Vec::with_capacity(proofs.len());
+
+        for (i, (mut proof_bytes, mut public_inputs)) in proofs.iter().copied().enumerate() {
+            let proof = Proof::<Bn254>::deserialize(&mut proof_bytes);
+            let inputs: Result<Vec<Fr>, _> = CanonicalDeserialize::deserialize(&mut public_inputs);
+            match (proof, inputs) {
+                (Ok(proof), Ok(inputs)) => {
+                    indices.push(i);
+                    batch.push((proof, inputs));
+                }
+                _ => invalid.push(i),
+            }
+        }
+
+        if let Err(failed) = batch::verify_batch(pvk, &batch, &mut ThreadRng::default()) {
+            invalid.extend(failed.into_iter().map(|i| indices[i]));
+            invalid.sort_unstable();
+        }
+
+        if invalid.is_empty() {
+            Ok(())
+        } else {
+            Err(invalid)
+        }
+    }
+
+    /// Aggregate `(proof, public inputs)` pairs, as returned by `prove`, into
+    /// a single proof whose size grows with the log of their number
+    pub fn aggregate(
+        &self,
+        srs: &AggregationSrs,
+        proofs: &[(&[u8], &[u8])],
+    ) -> Result<Vec<u8>, String> {
+        aggregate_bytes(srs, self.verifying_key()?, proofs)
+    }
+
+    /// Verify an aggregate of proofs for each of `public_inputs`, in order
+    pub fn verify_aggregate(
+        &self,
+        key: &AggregationVerifierKey,
+        aggregate: &[u8],
+        public_inputs: &[&[u8]],
+    ) -> Result<(), String> {
+        verify_aggregate_bytes(key, self.verifying_key()?, aggregate, public_inputs)
+    }
+
+    /// Wrap the output of `prove` into a versioned envelope
+    pub fn envelope(
+        &self,
+        proof_bytes: &[u8],
+        mut public_inputs_bytes: &[u8],
+    ) -> Result<ProofEnvelope, String> {
+        let inputs: Vec<Fr> = CanonicalDeserialize::deserialize(&mut public_inputs_bytes)
+            .map_err(|e| e.to_string())?;
+
+        Ok(ProofEnvelope {
+            version: ENVELOPE_VERSION,
+            circuit_id: self.circuit_id.clone(),
+            vk_hash: self.vk_hash,
+            public_inputs: PublicInputs::new(&self.public_input_names, &inputs)?,
+            proof: proof_bytes.to_vec(),
+        })
+    }
+
+    /// Verify an envelope produced by `envelope`, rejecting proofs made for
+    /// another circuit or verifying key before running the pairing check
+    pub fn verify_envelope(&self, envelope: &[u8]) -> Result<(), EnvelopeError> {
+        let envelope = ProofEnvelope::from_bytes(envelope)?;
+
+        if envelope.circuit_id != self.circuit_id {
+            return Err(EnvelopeError::CircuitMismatch);
+        }
+        if envelope.vk_hash != self.vk_hash {
+            return Err(EnvelopeError::VerifyingKeyMismatch);
+        }
+
+        let inputs = envelope
+            .public_inputs
+            .values(&self.public_input_names)
+            .map_err(EnvelopeError::Malformed)?;
+
+        match self.keys.verify(envelope.proof.as_slice(), inputs.as_slice()) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(EnvelopeError::InvalidProof),
+            Err(e) => Err(EnvelopeError::Malformed(e)),
+        }
+    }
+}
+
+fn next_stage(on_stage: &dyn Fn(ProofStage) -> bool, stage: ProofStage) -> Result<(), String> {
+    if on_stage(stage) {
+        Ok(())
+    } else {
+        Err(PROOF_CANCELLED.to_string())
+    }
+}
+
+fn build_pool(num_threads: usize) -> Result<ThreadPool, String> {
+    ThreadPoolBuilder::new()
+        .num_threads(num_threads)
+        .thread_name(|i| format!("storage-proofs-{}", i))
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+// the prover is shared between threads, both in `prove_many` and over FFI
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<StorageProofs>();
+};
+
+pub(crate) fn witness_inputs(
+    chunks: &[U256],
+    siblings: &[U256],
+    hashes: &[U256],
+    path: &[i32],
+    root: U256,
+    salt: U256,
+) -> WitnessInputs {
+    let mut inputs = WitnessInputs::new();
+
+    // vec of vecs is flattened, since wasm expects a contiguous array in memory
+    chunks.iter().for_each(|c| push_input(&mut inputs, "chunks", *c));
+
+    siblings
+        .iter()
+        .for_each(|c| push_input(&mut inputs, "siblings", *c));
+
+    hashes.iter().for_each(|c| push_input(&mut inputs, "hashes", *c));
+    path.iter().for_each(|c| push_input(&mut inputs, "path", *c));
+
+    push_input(&mut inputs, "root", root);
+    push_input(&mut inputs, "salt", salt);
+
+    inputs
+}
+
+/// `check_merkle_paths` on the `hashes`, `siblings`, `path` and `root` inputs
+fn check_input_paths(inputs: &WitnessInputs) -> Result<(), String> {
+    let values = |name: &str| inputs.get(name).map(|v| v.as_slice()).unwrap_or_default();
+    let u256s = |name: &str| {
+        values(name)
+            .iter()
+            .map(|v| U256::try_from(v).map_err(|_| format!("{} input isn't a u256", name)))
+            .collect::<Result<Vec<U256>, String>>()
+    };
+
+    let path = values("path")
+        .iter()
+        .map(|v| i32::try_from(v).map_err(|_| "path input isn't an i32".to_string()))
+        .collect::<Result<Vec<i32>, String>>()?;
+    let root = *u256s("root")?.first().ok_or("missing root input")?;
+
+    check_merkle_paths(&u256s("hashes")?, &u256s("siblings")?, &path, root)
 }
 
 fn decode_number(val: &rmpv::Value) -> Result<U256, String> {
@@ -162,40 +840,33 @@ fn decode_number(val: &rmpv::Value) -> Result<U256, String> {
 }
 
 fn parse_mpack_arrays(
-    builder: &mut CircomBuilder<Params256Ty>,
+    inputs: &mut WitnessInputs,
     name: &str,
     array: &Vec<rmpv::Value>
 ) -> Result<(), String> {
-
-    println!("deserde: array: {} size: {}", name, array.len());
     if array.len() > 0 && array[0].is_array() {
-        println!("deserde: arrayOfArrays: {}", name);
         for element in array {
             match element .as_array() {
                 Some(element ) => {
-                    parse_mpack_arrays(builder, name, element)?;
+                    parse_mpack_arrays(inputs, name, element)?;
                 },
                 _ => {
-                    print!("error expected array: {}", name);
                     return Err("expected inner array of u256".to_string())
                 },
             }
         }
     } else {
-        println!("deserde: name: {}", name);
         for val in array {
             let n = decode_number(val)?;
-            println!("\t{}", n);
-            builder.push_input(name, n);
+            push_input(inputs, name, n);
         }
-        println!("done: name: {}", name);
     }
 
     Ok(())
 }
 
-fn parse_mpack_args(
-    builder: &mut CircomBuilder<Params256Ty>,
+pub fn parse_mpack_args(
+    witness_inputs: &mut WitnessInputs,
     mut inputs: &[u8]
 ) -> Result<(), String> {
     let values: rmpv::Value = read_value(&mut inputs).map_err(|e| e.to_string())?;
@@ -212,18 +883,16 @@ fn parse_mpack_args(
         match val {
             // add a (name, Vec<u256>) or (name, Vev<Vec<u256>>) arrays
             rmpv::Value::Array(vals) => {
-                parse_mpack_arrays(builder, name, vals)?;
+                parse_mpack_arrays(witness_inputs, name, vals)?;
             },
             // directly add a (name,u256) arg pair 
             rmpv::Value::Ext(_, _) => {
                 let n = decode_number(val)?;
-                println!("deserde: name: {} u256: {}", name, n);
-                builder.push_input(name, n);
+                push_input(witness_inputs, name, n);
             },
             _ => return Err("unhandled argument kind".to_string()),
         }
     }
 
-    println!("parse_mpack_args DONE!");
     Ok(())
 }
@@ -0,0 +1,99 @@
+//! Reader for the circom `.sym` symbol file
+//!
+//! Each line of the file is `label,wire,component,name`, where `wire` is the
+//! r1cs wire index of the signal or `-1` if the signal was optimized away.
+
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub label: u64,
+    pub wire: Option<u64>,
+    pub component: u64,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+    symbols: Vec<Symbol>,
+}
+
+impl SymbolTable {
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let mut symbols = Vec::new();
+        for (n, line) in contents.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.splitn(4, ',').collect();
+            if fields.len() != 4 {
+                return Err(format!("malformed symbol on line {}", n + 1));
+            }
+
+            let parse = |s: &str| {
+                s.trim()
+                    .parse::<i64>()
+                    .map_err(|e| format!("line {}: {}", n + 1, e))
+            };
+            let wire = parse(fields[1])?;
+            symbols.push(Symbol {
+                label: parse(fields[0])? as u64,
+                wire: if wire < 0 { None } else { Some(wire as u64) },
+                component: parse(fields[2])? as u64,
+                name: fields[3].trim().to_string(),
+            });
+        }
+
+        Ok(Self { symbols })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        Self::parse(&contents)
+    }
+
+    /// Load the `.sym` file circom writes next to the `.r1cs`, if there is one
+    pub fn for_r1cs(r1cs: impl AsRef<Path>) -> Option<Self> {
+        let sym = r1cs.as_ref().with_extension("sym");
+        if !sym.exists() {
+            return None;
+        }
+
+        Self::load(sym).ok()
+    }
+
+    pub fn symbols(&self) -> &[Symbol] {
+        &self.symbols
+    }
+
+    /// Name of the signal assigned to `wire`, e.g. `main.root`
+    pub fn wire_name(&self, wire: u64) -> Option<&str> {
+        self.symbols
+            .iter()
+            .find(|s| s.wire == Some(wire))
+            .map(|s| s.name.as_str())
+    }
+}
+
+/// Strip the `main.` prefix circom puts in front of every top level signal
+pub fn signal_name(name: &str) -> &str {
+    name.strip_prefix("main.").unwrap_or(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{signal_name, SymbolTable};
+
+    #[test]
+    fn test_parse_symbols() {
+        let table = SymbolTable::parse("1,1,0,main.root\n2,2,0,main.salt\n3,-1,0,main.saltSquare\n")
+            .unwrap();
+
+        assert_eq!(table.symbols().len(), 3);
+        assert_eq!(table.wire_name(2), Some("main.salt"));
+        assert_eq!(table.symbols()[2].wire, None);
+        assert_eq!(signal_name("main.root"), "root");
+        assert!(SymbolTable::parse("1,1,main.root").is_err());
+    }
+}
@@ -0,0 +1,535 @@
+//! Verifying key export and import
+//!
+//! The verifying key of a prover can be exported as ark serialized bytes,
+//! compressed or not, as a snarkjs `verification_key.json`, or as the 32-byte
+//! big-endian words an EVM verifier reads. Any of them can be imported back into
+//! a `Verifier` that checks proofs without the r1cs or the proving key.
+//!
+//! Proofs can also be re-randomized with the verifying key alone, giving a
+//! fresh proof of the same statement that can't be linked to the original.
+
+use std::str::FromStr;
+
+use ark_bn254::{Bn254, Fq, Fq2, Fr, G1Affine, G2Affine};
+use ark_ec::{AffineCurve, ProjectiveCurve};
+use ark_ff::{BigInteger, BigInteger256, Field, PrimeField, UniformRand, Zero};
+use ark_groth16::{prepare_verifying_key, verify_proof, PreparedVerifyingKey, Proof, VerifyingKey};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, Read};
+use ark_std::rand::rngs::ThreadRng;
+use ark_std::rand::Rng;
+use num_bigint::BigUint;
+use serde_json::{json, Value};
+
+use crate::aggregate::{verify_aggregate_bytes, AggregationVerifierKey};
+use crate::circuit_info::CURVE_BN128;
+
+/// Size of an EVM word
+const WORD_SIZE: usize = 32;
+
+/// Words before the `IC` points: alpha, beta, gamma and delta
+const EVM_HEADER_WORDS: usize = 2 + 3 * 4;
+
+pub const VK_FORMAT_ARK_COMPRESSED: u8 = 0;
+pub const VK_FORMAT_ARK_UNCOMPRESSED: u8 = 1;
+pub const VK_FORMAT_SNARKJS: u8 = 2;
+pub const VK_FORMAT_EVM_WORDS: u8 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VkFormat {
+    /// ark `serialize`, as used for proofs
+    ArkCompressed,
+    /// ark `serialize_uncompressed`, faster to load
+    ArkUncompressed,
+    /// snarkjs `verification_key.json`
+    Snarkjs,
+    /// alpha, beta, gamma, delta and the `IC` points as 32-byte big-endian
+    /// words, G2 coordinates with the imaginary part first as the pairing
+    /// precompile expects them
+    EvmWords,
+}
+
+impl TryFrom<u8> for VkFormat {
+    type Error = String;
+
+    fn try_from(format: u8) -> Result<Self, Self::Error> {
+        match format {
+            VK_FORMAT_ARK_COMPRESSED => Ok(Self::ArkCompressed),
+            VK_FORMAT_ARK_UNCOMPRESSED => Ok(Self::ArkUncompressed),
+            VK_FORMAT_SNARKJS => Ok(Self::Snarkjs),
+            VK_FORMAT_EVM_WORDS => Ok(Self::EvmWords),
+            _ => Err(format!("unknown verifying key format {}", format)),
+        }
+    }
+}
+
+pub fn export_vk(vk: &VerifyingKey<Bn254>, format: VkFormat) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    match format {
+        VkFormat::ArkCompressed => vk.serialize(&mut bytes).map_err(|e| e.to_string())?,
+        VkFormat::ArkUncompressed => vk
+            .serialize_uncompressed(&mut bytes)
+            .map_err(|e| e.to_string())?,
+        VkFormat::Snarkjs => {
+            bytes = serde_json::to_vec_pretty(&snarkjs_json(vk)).map_err(|e| e.to_string())?
+        }
+        VkFormat::EvmWords => {
+            write_g1(&mut bytes, &vk.alpha_g1);
+            for g2 in [&vk.beta_g2, &vk.gamma_g2, &vk.delta_g2] {
+                write_g2(&mut bytes, g2);
+            }
+            vk.gamma_abc_g1
+                .iter()
+                .for_each(|g1| write_g1(&mut bytes, g1));
+        }
+    }
+
+    Ok(bytes)
+}
+
+pub fn import_vk(bytes: &[u8], format: VkFormat) -> Result<VerifyingKey<Bn254>, String> {
+    let vk = match format {
+        VkFormat::ArkCompressed => VerifyingKey::deserialize(bytes).map_err(|e| e.to_string())?,
+        VkFormat::ArkUncompressed => {
+            VerifyingKey::deserialize_uncompressed(bytes).map_err(|e| e.to_string())?
+        }
+        VkFormat::Snarkjs => {
+            let json: Value = serde_json::from_slice(bytes).map_err(|e| e.to_string())?;
+            from_snarkjs_json(&json)?
+        }
+        VkFormat::EvmWords => {
+            if bytes.len() % WORD_SIZE != 0 || bytes.len() / WORD_SIZE < EVM_HEADER_WORDS + 2 {
+                return Err(format!("bad verifying key size {}", bytes.len()));
+            }
+
+            let mut words = bytes.chunks(WORD_SIZE).peekable();
+            let alpha_g1 = read_g1(&mut words)?;
+            let beta_g2 = read_g2(&mut words)?;
+            let gamma_g2 = read_g2(&mut words)?;
+            let delta_g2 = read_g2(&mut words)?;
+
+            let mut gamma_abc_g1 = Vec::new();
+            while words.peek().is_some() {
+                gamma_abc_g1.push(read_g1(&mut words)?);
+            }
+
+            VerifyingKey {
+                alpha_g1,
+                beta_g2,
+                gamma_g2,
+                delta_g2,
+                gamma_abc_g1,
+            }
+        }
+    };
+
+    if vk.gamma_abc_g1.is_empty() {
+        return Err("verifying key has no IC points".to_string());
+    }
+
+    Ok(vk)
+}
+
+/// A fresh proof for the same public inputs as `proof`, valid exactly when
+/// `proof` is. With random `r1` and `r2` it's
+/// `(A / r1, r1 B + r1 r2 delta, C + r2 A)`.
+pub fn rerandomize<R: Rng>(
+    proof: &Proof<Bn254>,
+    vk: &VerifyingKey<Bn254>,
+    rng: &mut R,
+) -> Proof<Bn254> {
+    let mut r1 = Fr::rand(rng);
+    while r1.is_zero() {
+        r1 = Fr::rand(rng);
+    }
+    let r2 = Fr::rand(rng);
+    let r1_inv = r1.inverse().unwrap();
+
+    let b = proof.b.mul(r1) + vk.delta_g2.mul(r1 * r2);
+    let c = proof.a.mul(r2).add_mixed(&proof.c);
+
+    Proof {
+        a: proof.a.mul(r1_inv).into_affine(),
+        b: b.into_affine(),
+        c: c.into_affine(),
+    }
+}
+
+/// `rerandomize` for ark serialized proof bytes
+pub fn rerandomize_bytes(proof_bytes: &[u8], vk: &VerifyingKey<Bn254>) -> Result<Vec<u8>, String> {
+    let proof = Proof::<Bn254>::deserialize(proof_bytes).map_err(|e| e.to_string())?;
+    let proof = rerandomize(&proof, vk, &mut ThreadRng::default());
+
+    let mut bytes = Vec::new();
+    proof.serialize(&mut bytes).map_err(|e| e.to_string())?;
+    Ok(bytes)
+}
+
+/// Groth16 verifier for a single circuit, built from a verifying key alone
+#[derive(Debug, Clone)]
+pub struct Verifier {
+    vk: VerifyingKey<Bn254>,
+    pvk: PreparedVerifyingKey<Bn254>,
+}
+
+impl Verifier {
+    pub fn new(vk: VerifyingKey<Bn254>) -> Self {
+        let pvk = prepare_verifying_key(&vk);
+        Self { vk, pvk }
+    }
+
+    pub fn import(bytes: &[u8], format: VkFormat) -> Result<Self, String> {
+        Ok(Self::new(import_vk(bytes, format)?))
+    }
+
+    pub fn export(&self, format: VkFormat) -> Result<Vec<u8>, String> {
+        export_vk(&self.vk, format)
+    }
+
+    pub fn verifying_key(&self) -> &VerifyingKey<Bn254> {
+        &self.vk
+    }
+
+    /// Number of public inputs a proof is verified against
+    pub fn num_public_inputs(&self) -> usize {
+        self.vk.gamma_abc_g1.len() - 1
+    }
+
+    /// Same as `StorageProofs::verify`
+    pub fn verify<RR: Read>(&self, proof_bytes: RR, mut public_inputs: RR) -> Result<(), String> {
+        let inputs: Vec<Fr> =
+            CanonicalDeserialize::deserialize(&mut public_inputs).map_err(|e| e.to_string())?;
+        let proof = Proof::<Bn254>::deserialize(proof_bytes).map_err(|e| e.to_string())?;
+
+        self.verify_proof(&proof, &inputs)
+    }
+
+    /// Same as `StorageProofs::rerandomize`
+    pub fn rerandomize(&self, proof_bytes: &[u8]) -> Result<Vec<u8>, String> {
+        rerandomize_bytes(proof_bytes, &self.vk)
+    }
+
+    /// Same as `StorageProofs::verify_aggregate`
+    pub fn verify_aggregate(
+        &self,
+        key: &AggregationVerifierKey,
+        aggregate: &[u8],
+        public_inputs: &[&[u8]],
+    ) -> Result<(), String> {
+        verify_aggregate_bytes(key, &self.vk, aggregate, public_inputs)
+    }
+
+    pub fn verify_proof(&self, proof: &Proof<Bn254>, inputs: &[Fr]) -> Result<(), String> {
+        if inputs.len() != self.num_public_inputs() {
+            return Err(format!(
+                "expected {} public inputs, got {}",
+                self.num_public_inputs(),
+                inputs.len()
+            ));
+        }
+
+        if !verify_proof(&self.pvk, proof, inputs).map_err(|e| e.to_string())? {
+            return Err("proof verification failed".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+fn snarkjs_json(vk: &VerifyingKey<Bn254>) -> Value {
+    json!({
+        "protocol": "groth16",
+        "curve": CURVE_BN128,
+        "nPublic": vk.gamma_abc_g1.len() - 1,
+        "vk_alpha_1": g1_json(&vk.alpha_g1),
+        "vk_beta_2": g2_json(&vk.beta_g2),
+        "vk_gamma_2": g2_json(&vk.gamma_g2),
+        "vk_delta_2": g2_json(&vk.delta_g2),
+        "IC": vk.gamma_abc_g1.iter().map(g1_json).collect::<Vec<_>>(),
+    })
+}
+
+fn from_snarkjs_json(json: &Value) -> Result<VerifyingKey<Bn254>, String> {
+    let field = |name: &str| json.get(name).ok_or_else(|| format!("missing {}", name));
+
+    if field("protocol")?.as_str() != Some("groth16") {
+        return Err("only groth16 verifying keys are supported".to_string());
+    }
+    if field("curve")?.as_str() != Some(CURVE_BN128) {
+        return Err(format!("only {} verifying keys are supported", CURVE_BN128));
+    }
+
+    let gamma_abc_g1 = field("IC")?
+        .as_array()
+        .ok_or("IC must be an array")?
+        .iter()
+        .map(g1_from_json)
+        .collect::<Result<Vec<_>, String>>()?;
+
+    if let Some(n) = json.get("nPublic").and_then(|n| n.as_u64()) {
+        if n as usize + 1 != gamma_abc_g1.len() {
+            return Err(format!(
+                "nPublic is {} but there are {} IC points",
+                n,
+                gamma_abc_g1.len()
+            ));
+        }
+    }
+
+    Ok(VerifyingKey {
+        alpha_g1: g1_from_json(field("vk_alpha_1")?)?,
+        beta_g2: g2_from_json(field("vk_beta_2")?)?,
+        gamma_g2: g2_from_json(field("vk_gamma_2")?)?,
+        delta_g2: g2_from_json(field("vk_delta_2")?)?,
+        gamma_abc_g1,
+    })
+}
+
+// snarkjs writes points in projective coordinates, `[x, y, 1]` for affine
+// points and `[0, 1, 0]` for the point at infinity
+
+fn g1_json(p: &G1Affine) -> Value {
+    if p.infinity {
+        return json!(["0", "1", "0"]);
+    }
+
+    json!([fq_to_string(&p.x), fq_to_string(&p.y), "1"])
+}
+
+fn g2_json(p: &G2Affine) -> Value {
+    if p.infinity {
+        return json!([["0", "0"], ["1", "0"], ["0", "0"]]);
+    }
+
+    json!([fq2_json(&p.x), fq2_json(&p.y), ["1", "0"]])
+}
+
+fn fq2_json(f: &Fq2) -> Value {
+    json!([fq_to_string(&f.c0), fq_to_string(&f.c1)])
+}
+
+fn g1_from_json(val: &Value) -> Result<G1Affine, String> {
+    let coords = json_array(val, 3)?;
+    let (x, y, z) = (
+        fq_from_json(&coords[0])?,
+        fq_from_json(&coords[1])?,
+        fq_from_json(&coords[2])?,
+    );
+
+    if z.is_zero() {
+        return Ok(G1Affine::zero());
+    }
+
+    let z_inv = z.inverse().ok_or("bad point")?;
+    checked_g1(G1Affine::new(x * z_inv, y * z_inv, false))
+}
+
+fn g2_from_json(val: &Value) -> Result<G2Affine, String> {
+    let coords = json_array(val, 3)?;
+    let (x, y, z) = (
+        fq2_from_json(&coords[0])?,
+        fq2_from_json(&coords[1])?,
+        fq2_from_json(&coords[2])?,
+    );
+
+    if z.is_zero() {
+        return Ok(G2Affine::zero());
+    }
+
+    let z_inv = z.inverse().ok_or("bad point")?;
+    checked_g2(G2Affine::new(x * z_inv, y * z_inv, false))
+}
+
+fn fq2_from_json(val: &Value) -> Result<Fq2, String> {
+    let coords = json_array(val, 2)?;
+    Ok(Fq2::new(
+        fq_from_json(&coords[0])?,
+        fq_from_json(&coords[1])?,
+    ))
+}
+
+fn fq_from_json(val: &Value) -> Result<Fq, String> {
+    let s = val.as_str().ok_or("expected a decimal string")?;
+    let n = BigUint::from_str(s).map_err(|e| e.to_string())?;
+    fq_from_biguint(n)
+}
+
+fn json_array(val: &Value, len: usize) -> Result<&[Value], String> {
+    match val.as_array() {
+        Some(vals) if vals.len() == len => Ok(vals),
+        _ => Err(format!("expected an array of {} elements", len)),
+    }
+}
+
+fn fq_to_string(f: &Fq) -> String {
+    let n: BigUint = f.into_repr().into();
+    n.to_string()
+}
+
+fn fq_from_biguint(n: BigUint) -> Result<Fq, String> {
+    let repr = BigInteger256::try_from(n).map_err(|_| "coordinate is too large".to_string())?;
+    Fq::from_repr(repr).ok_or_else(|| "coordinate is not in the field".to_string())
+}
+
+fn write_g1(bytes: &mut Vec<u8>, p: &G1Affine) {
+    // the EVM encodes the point at infinity as (0, 0)
+    let (x, y) = if p.infinity {
+        (Fq::zero(), Fq::zero())
+    } else {
+        (p.x, p.y)
+    };
+
+    write_fq(bytes, &x);
+    write_fq(bytes, &y);
+}
+
+fn write_g2(bytes: &mut Vec<u8>, p: &G2Affine) {
+    let (x, y) = if p.infinity {
+        (Fq2::zero(), Fq2::zero())
+    } else {
+        (p.x, p.y)
+    };
+
+    for f in [x.c1, x.c0, y.c1, y.c0] {
+        write_fq(bytes, &f);
+    }
+}
+
+fn write_fq(bytes: &mut Vec<u8>, f: &Fq) {
+    bytes.extend_from_slice(&f.into_repr().to_bytes_be());
+}
+
+fn read_g1<'a>(words: &mut impl Iterator<Item = &'a [u8]>) -> Result<G1Affine, String> {
+    let x = read_fq(words)?;
+    let y = read_fq(words)?;
+
+    if x.is_zero() && y.is_zero() {
+        return Ok(G1Affine::zero());
+    }
+
+    checked_g1(G1Affine::new(x, y, false))
+}
+
+fn read_g2<'a>(words: &mut impl Iterator<Item = &'a [u8]>) -> Result<G2Affine, String> {
+    let x_c1 = read_fq(words)?;
+    let x_c0 = read_fq(words)?;
+    let y_c1 = read_fq(words)?;
+    let y_c0 = read_fq(words)?;
+    let (x, y) = (Fq2::new(x_c0, x_c1), Fq2::new(y_c0, y_c1));
+
+    if x.is_zero() && y.is_zero() {
+        return Ok(G2Affine::zero());
+    }
+
+    checked_g2(G2Affine::new(x, y, false))
+}
+
+fn read_fq<'a>(words: &mut impl Iterator<Item = &'a [u8]>) -> Result<Fq, String> {
+    let word = words.next().ok_or("verifying key is truncated")?;
+    fq_from_biguint(BigUint::from_bytes_be(word))
+}
+
+fn checked_g1(p: G1Affine) -> Result<G1Affine, String> {
+    if !p.is_on_curve() || !p.is_in_correct_subgroup_assuming_on_curve() {
+        return Err("G1 point is not on the curve".to_string());
+    }
+
+    Ok(p)
+}
+
+fn checked_g2(p: G2Affine) -> Result<G2Affine, String> {
+    if !p.is_on_curve() || !p.is_in_correct_subgroup_assuming_on_curve() {
+        return Err("G2 point is not in the subgroup".to_string());
+    }
+
+    Ok(p)
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_bn254::{Bn254, Fr, G1Affine, G2Affine};
+    use ark_ec::AffineCurve;
+    use ark_ff::PrimeField;
+    use ark_groth16::VerifyingKey;
+    use ark_std::rand::{rngs::StdRng, SeedableRng};
+    use ark_std::UniformRand;
+
+    use ark_circom::circom::R1CS;
+    use ark_groth16::{create_random_proof, generate_random_parameters};
+
+    use super::{export_vk, import_vk, rerandomize, Verifier, VkFormat};
+    use crate::circuit::StorageCircuit;
+
+    #[test]
+    fn test_vk_formats_roundtrip() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let g1 = |rng: &mut StdRng| -> G1Affine {
+            let s = Fr::rand(rng).into_repr();
+            G1Affine::prime_subgroup_generator().mul(s).into()
+        };
+        let g2 = |rng: &mut StdRng| -> G2Affine {
+            let s = Fr::rand(rng).into_repr();
+            G2Affine::prime_subgroup_generator().mul(s).into()
+        };
+
+        let vk = VerifyingKey::<Bn254> {
+            alpha_g1: g1(&mut rng),
+            beta_g2: g2(&mut rng),
+            gamma_g2: g2(&mut rng),
+            delta_g2: g2(&mut rng),
+            gamma_abc_g1: vec![g1(&mut rng), g1(&mut rng), G1Affine::default()],
+        };
+
+        for format in [
+            VkFormat::ArkCompressed,
+            VkFormat::ArkUncompressed,
+            VkFormat::Snarkjs,
+            VkFormat::EvmWords,
+        ] {
+            let bytes = export_vk(&vk, format).unwrap();
+            assert_eq!(import_vk(&bytes, format).unwrap(), vk, "{:?}", format);
+        }
+
+        let words = export_vk(&vk, VkFormat::EvmWords).unwrap();
+        assert_eq!(words.len(), (14 + 2 * 3) * 32);
+        assert!(import_vk(&words[..words.len() - 1], VkFormat::EvmWords).is_err());
+    }
+
+    #[test]
+    fn test_rerandomize() {
+        let mut rng = StdRng::seed_from_u64(42);
+
+        // x * y = z with public z
+        let one = Fr::from(1u32);
+        let r1cs = R1CS::<Bn254> {
+            num_inputs: 2,
+            num_aux: 2,
+            num_variables: 4,
+            constraints: vec![(vec![(2, one)], vec![(3, one)], vec![(1, one)])],
+            wire_mapping: None,
+        };
+        let params =
+            generate_random_parameters::<Bn254, _, _>(StorageCircuit::setup(&r1cs), &mut rng)
+                .unwrap();
+        let witness: Vec<Fr> = [1u32, 12, 3, 4].into_iter().map(Fr::from).collect();
+        let circuit = StorageCircuit::new(&r1cs, witness);
+        let proof = create_random_proof(circuit, &params, &mut rng).unwrap();
+
+        let verifier = Verifier::new(params.vk.clone());
+        let inputs = [Fr::from(12u32)];
+        verifier.verify_proof(&proof, &inputs).unwrap();
+
+        let fresh = rerandomize(&proof, &params.vk, &mut rng);
+        assert_ne!(fresh, proof);
+        verifier.verify_proof(&fresh, &inputs).unwrap();
+        let again = rerandomize(&fresh, &params.vk, &mut rng);
+        verifier.verify_proof(&again, &inputs).unwrap();
+        assert!(verifier.verify_proof(&fresh, &[Fr::from(13u32)]).is_err());
+
+        // changing a single point breaks the proof, re-randomizing doesn't fix it
+        let mut modified = proof.clone();
+        modified.a = fresh.a;
+        assert!(verifier.verify_proof(&modified, &inputs).is_err());
+        let modified = rerandomize(&modified, &params.vk, &mut rng);
+        assert!(verifier.verify_proof(&modified, &inputs).is_err());
+    }
+}
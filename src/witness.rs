@@ -0,0 +1,227 @@
+//! Witness calculation
+//!
+//! Compiling and instantiating the circom wasm module is far more expensive
+//! than running it, so instances are created once and reused for every proof.
+//! An instance can only compute one witness at a time (clones share its
+//! memory), so the pool hands each proof its own instance and creates new ones
+//! on demand, up to `max_instances`.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+
+use ark_bn254::{Bn254, Fr};
+use ark_circom::circom::R1CS;
+use ark_circom::WitnessCalculator;
+use num_bigint::BigInt;
+use serde_json::Value;
+
+use crate::native_witness::NativeWitness;
+
+/// Circuit inputs by signal name, multi-dimensional signals are flattened
+pub type WitnessInputs = HashMap<String, Vec<BigInt>>;
+
+pub fn push_input<T: Into<BigInt>>(inputs: &mut WitnessInputs, name: &str, val: T) {
+    inputs
+        .entry(name.to_string())
+        .or_insert_with(Vec::new)
+        .push(val.into());
+}
+
+/// Read inputs in the JSON format of the circom and snarkjs tools, an object
+/// of signal names to numbers, decimal or `0x` prefixed hex strings, or
+/// nested arrays of them
+pub fn witness_inputs_from_json(json: &str) -> Result<WitnessInputs, String> {
+    let json: Value = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    let args = json.as_object().ok_or("inputs must be an object")?;
+
+    let mut inputs = WitnessInputs::new();
+    for (name, val) in args {
+        let mut values = Vec::new();
+        flatten_json(name, val, &mut values)?;
+        inputs.insert(name.clone(), values);
+    }
+
+    Ok(inputs)
+}
+
+fn flatten_json(name: &str, val: &Value, values: &mut Vec<BigInt>) -> Result<(), String> {
+    let bad_value = || format!("bad value for input {}", name);
+
+    match val {
+        Value::Array(vals) => {
+            for val in vals {
+                flatten_json(name, val, values)?;
+            }
+        }
+        Value::Number(n) => {
+            let n = n
+                .as_u64()
+                .map(BigInt::from)
+                .or_else(|| n.as_i64().map(BigInt::from))
+                .ok_or_else(bad_value)?;
+            values.push(n);
+        }
+        Value::String(s) => {
+            let n = match s.strip_prefix("0x") {
+                Some(hex) => BigInt::parse_bytes(hex.as_bytes(), 16),
+                None => BigInt::parse_bytes(s.as_bytes(), 10),
+            };
+            values.push(n.ok_or_else(bad_value)?);
+        }
+        _ => return Err(bad_value()),
+    }
+
+    Ok(())
+}
+
+/// How witnesses are computed, chosen when the prover is created
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WitnessBackend {
+    /// Run the circom wasm at this path
+    Wasm(String),
+    /// Solve the witness natively from the r1cs, needs the `.sym` file
+    Native,
+}
+
+#[derive(Debug)]
+pub enum WitnessGenerator {
+    Wasm(WitnessPool),
+    Native(NativeWitness),
+}
+
+impl WitnessGenerator {
+    pub fn calculate(&self, r1cs: &R1CS<Bn254>, inputs: WitnessInputs) -> Result<Vec<Fr>, String> {
+        match self {
+            WitnessGenerator::Wasm(pool) => pool.calculate(inputs),
+            WitnessGenerator::Native(native) => native.calculate(r1cs, &inputs),
+        }
+    }
+
+    /// Witnesses that can be computed concurrently, the native generator
+    /// has no limit
+    pub fn max_instances(&self) -> usize {
+        match self {
+            WitnessGenerator::Wasm(pool) => pool.max_instances(),
+            WitnessGenerator::Native(_) => usize::MAX,
+        }
+    }
+
+    pub fn set_max_instances(&self, max_instances: usize) {
+        if let WitnessGenerator::Wasm(pool) = self {
+            pool.set_max_instances(max_instances)
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct PoolState {
+    idle: Vec<WitnessCalculator>,
+    created: usize,
+}
+
+#[derive(Debug)]
+pub struct WitnessPool {
+    wasm: PathBuf,
+    state: Mutex<PoolState>,
+    available: Condvar,
+    max_instances: AtomicUsize,
+}
+
+impl WitnessPool {
+    pub fn new(wasm: impl AsRef<Path>) -> Result<Self, String> {
+        let wasm = wasm.as_ref().to_path_buf();
+        let calculator = WitnessCalculator::new(&wasm).map_err(|e| e.to_string())?;
+
+        Ok(Self {
+            wasm,
+            state: Mutex::new(PoolState {
+                idle: vec![calculator],
+                created: 1,
+            }),
+            available: Condvar::new(),
+            max_instances: AtomicUsize::new(1),
+        })
+    }
+
+    pub fn max_instances(&self) -> usize {
+        self.max_instances.load(Ordering::SeqCst)
+    }
+
+    /// Allow up to `max_instances` witnesses to be computed concurrently, each
+    /// extra instance is only created the first time it's needed
+    pub fn set_max_instances(&self, max_instances: usize) {
+        self.max_instances
+            .store(max_instances.max(1), Ordering::SeqCst);
+        self.available.notify_all();
+    }
+
+    /// Compute the witness for `inputs`, indexed by wire
+    pub fn calculate(&self, inputs: WitnessInputs) -> Result<Vec<Fr>, String> {
+        self.checkout()?
+            .get()
+            .calculate_witness_element::<Bn254, _>(inputs, false)
+            .map_err(|e| e.to_string())
+    }
+
+    fn checkout(&self) -> Result<Checkout<'_>, String> {
+        let mut state = self.state.lock().map_err(|e| e.to_string())?;
+        loop {
+            if let Some(calculator) = state.idle.pop() {
+                return Ok(Checkout {
+                    pool: self,
+                    calculator: Some(calculator),
+                });
+            }
+
+            if state.created < self.max_instances() {
+                state.created += 1;
+                drop(state);
+
+                return match WitnessCalculator::new(&self.wasm) {
+                    Ok(calculator) => Ok(Checkout {
+                        pool: self,
+                        calculator: Some(calculator),
+                    }),
+                    Err(e) => {
+                        if let Ok(mut state) = self.state.lock() {
+                            state.created -= 1;
+                        }
+                        Err(e.to_string())
+                    }
+                };
+            }
+
+            state = self.available.wait(state).map_err(|e| e.to_string())?;
+        }
+    }
+}
+
+/// An instance taken out of the pool, returned to it when dropped
+struct Checkout<'a> {
+    pool: &'a WitnessPool,
+    calculator: Option<WitnessCalculator>,
+}
+
+impl<'a> Checkout<'a> {
+    fn get(&mut self) -> &mut WitnessCalculator {
+        self.calculator.as_mut().unwrap()
+    }
+}
+
+impl<'a> Drop for Checkout<'a> {
+    fn drop(&mut self) {
+        if let (Some(calculator), Ok(mut state)) = (self.calculator.take(), self.pool.state.lock())
+        {
+            // the pool was shrunk while this instance was in use
+            if state.created > self.pool.max_instances() {
+                state.created -= 1;
+                return;
+            }
+
+            state.idle.push(calculator);
+            self.pool.available.notify_one();
+        }
+    }
+}
@@ -0,0 +1,109 @@
+//! Reader and writer for the iden3 `.wtns` witness format
+//!
+//! The file is an iden3 binary container, see `binfile`, with the `wtns`
+//! magic. Section 1 holds the field element size, the prime and the number of
+//! wires, section 2 the witness values in wire order, little endian and not
+//! in Montgomery form. This is what circom's C++ and wasm calculators write.
+
+use std::io::Cursor;
+
+use ark_bn254::Fr;
+use ark_ff::{BigInteger, FpParameters, PrimeField};
+
+use crate::binfile::{read_bigint, write_bigint, write_bin, BinReader, FIELD_SIZE};
+
+const MAGIC: &[u8; 4] = b"wtns";
+const VERSION: u32 = 2;
+
+const SECTION_HEADER: u32 = 1;
+const SECTION_WITNESS: u32 = 2;
+
+pub fn read_wtns(bytes: &[u8]) -> Result<Vec<Fr>, String> {
+    let mut file = BinReader::new(Cursor::new(bytes), MAGIC)?;
+    if file.version > VERSION {
+        return Err(format!("unsupported wtns version {}", file.version));
+    }
+
+    if !file.has_section(SECTION_HEADER) {
+        return Err("missing wtns header".to_string());
+    }
+    if !file.has_section(SECTION_WITNESS) {
+        return Err("missing wtns witness section".to_string());
+    }
+
+    let header = file.read_section(SECTION_HEADER)?;
+    let u32_at = |pos: usize| -> Result<usize, String> {
+        header
+            .get(pos..pos + 4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()) as usize)
+            .ok_or_else(|| "wtns header is truncated".to_string())
+    };
+    let n8 = u32_at(0)?;
+    let prime = header.get(4..4 + n8).ok_or("wtns header is truncated")?;
+    if n8 != FIELD_SIZE || read_bigint(prime) != <Fr as PrimeField>::Params::MODULUS {
+        return Err("witness is not over the bn254 scalar field".to_string());
+    }
+    let num_wires = u32_at(4 + n8)?;
+
+    let values = file.read_section(SECTION_WITNESS)?;
+    if values.len() != num_wires * FIELD_SIZE {
+        return Err(format!(
+            "expected {} witness values, got {} bytes",
+            num_wires,
+            values.len()
+        ));
+    }
+
+    values
+        .chunks(FIELD_SIZE)
+        .map(|bytes| {
+            let val = Fr::from_le_bytes_mod_order(bytes);
+            if val.into_repr().to_bytes_le() != bytes {
+                return Err("witness value is not reduced".to_string());
+            }
+            Ok(val)
+        })
+        .collect()
+}
+
+pub fn write_wtns(witness: &[Fr]) -> Vec<u8> {
+    let mut header = Vec::with_capacity(8 + FIELD_SIZE);
+    header.extend_from_slice(&(FIELD_SIZE as u32).to_le_bytes());
+    write_bigint(&mut header, &<Fr as PrimeField>::Params::MODULUS);
+    header.extend_from_slice(&(witness.len() as u32).to_le_bytes());
+
+    let mut values = Vec::with_capacity(witness.len() * FIELD_SIZE);
+    for val in witness {
+        values.extend_from_slice(&val.into_repr().to_bytes_le());
+    }
+
+    let mut bytes = Vec::with_capacity(64 + values.len());
+    // writing to a vector can't fail
+    write_bin(
+        &mut bytes,
+        MAGIC,
+        VERSION,
+        &[(SECTION_HEADER, header), (SECTION_WITNESS, values)],
+    )
+    .unwrap();
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_bn254::Fr;
+
+    use super::{read_wtns, write_wtns};
+
+    #[test]
+    fn test_wtns_roundtrip() {
+        let witness: Vec<Fr> = (0..10u64)
+            .map(|i| Fr::from(i * 1000) - Fr::from(7u64))
+            .collect();
+        let bytes = write_wtns(&witness);
+
+        assert_eq!(&bytes[..4], b"wtns");
+        assert_eq!(read_wtns(&bytes).unwrap(), witness);
+        assert!(read_wtns(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
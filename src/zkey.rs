@@ -0,0 +1,391 @@
+//! Writer for `.zkey` files in the snarkjs layout, the format `read_zkey`
+//! loads
+//!
+//! Section 1 holds the protocol, section 2 the Groth16 header with both
+//! primes, the number of wires and public inputs, the domain size and the
+//! fixed points of the key. Sections 3 to 9 are the `IC`, the A and B
+//! coefficients of the constraints, and the A, B1, B2, C (`l_query`) and H
+//! points. Section 10 holds the phase 2 transcript, the circuit hash and the
+//! contributions `snarkjs zkey verify` checks.
+//!
+//! The H points are the ones of snarkjs's reduction, `L_{2i+1}(tau) / delta`
+//! for the Lagrange basis of twice the domain, so keys are proven with ark
+//! circom's `CircomReduction`. snarkjs proves with the zkeys this crate
+//! writes, and this crate with the zkeys of snarkjs.
+
+use std::io::{Read, Seek, Write};
+
+use ark_bn254::{Bn254, Fq, Fr, G1Affine};
+use ark_circom::circom::R1CS;
+use ark_ff::{FpParameters, PrimeField, Zero};
+use ark_groth16::ProvingKey;
+use ark_relations::r1cs::ConstraintMatrices;
+
+use crate::binfile::{write_bigint, write_bin, write_g1, write_g2, BinReader, FIELD_SIZE};
+use crate::ceremony::MpcParams;
+use crate::ptau::required_domain_size;
+
+const MAGIC: &[u8; 4] = b"zkey";
+const VERSION: u32 = 1;
+
+const PROTOCOL_GROTH16: u32 = 1;
+
+const SECTION_HEADER: u32 = 1;
+const SECTION_GROTH_HEADER: u32 = 2;
+const SECTION_IC: u32 = 3;
+const SECTION_COEFFS: u32 = 4;
+const SECTION_A: u32 = 5;
+const SECTION_B1: u32 = 6;
+const SECTION_B2: u32 = 7;
+const SECTION_C: u32 = 8;
+const SECTION_H: u32 = 9;
+const SECTION_CONTRIBUTIONS: u32 = 10;
+
+/// How closely a proving key is compared with the r1cs it's loaded for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZkeyCheck {
+    /// Number of constraints, public inputs and wires, and the domain size
+    Shape,
+    /// Also the A and B coefficients of every constraint stored in the zkey
+    Strict,
+}
+
+/// Check that `params` was generated for `r1cs`. `matrices` are the
+/// constraints `read_zkey` returns, keys without them only have their shape
+/// checked.
+pub fn check_zkey(
+    params: &ProvingKey<Bn254>,
+    matrices: Option<&ConstraintMatrices<Fr>>,
+    r1cs: &R1CS<Bn254>,
+    check: ZkeyCheck,
+) -> Result<(), String> {
+    let num_public = params.vk.gamma_abc_g1.len().saturating_sub(1);
+    if num_public != r1cs.num_inputs - 1 {
+        return Err(format!(
+            "zkey has {} public inputs, the r1cs has {}",
+            num_public,
+            r1cs.num_inputs - 1
+        ));
+    }
+
+    if params.a_query.len() != r1cs.num_variables
+        || params.b_g1_query.len() != r1cs.num_variables
+        || params.b_g2_query.len() != r1cs.num_variables
+        || params.l_query.len() + r1cs.num_inputs != r1cs.num_variables
+    {
+        return Err(format!(
+            "zkey has {} wires, the r1cs has {}",
+            params.a_query.len(),
+            r1cs.num_variables
+        ));
+    }
+
+    let domain_size = required_domain_size(r1cs)?;
+    if params.h_query.len() != domain_size {
+        return Err(format!(
+            "zkey has {} H points, the r1cs needs a domain of {}",
+            params.h_query.len(),
+            domain_size
+        ));
+    }
+    // earlier versions of this crate wrote ark's H points, padded with zero
+    if params.h_query[domain_size - 1].is_zero() {
+        return Err("zkey has the H points of ark's reduction, not snarkjs's".to_string());
+    }
+
+    let matrices = match matrices {
+        Some(matrices) => matrices,
+        None => return Ok(()),
+    };
+    if matrices.num_constraints != r1cs.constraints.len() {
+        return Err(format!(
+            "zkey has {} constraints, the r1cs has {}",
+            matrices.num_constraints,
+            r1cs.constraints.len()
+        ));
+    }
+
+    if check == ZkeyCheck::Strict {
+        let sorted = |terms: &mut Vec<(usize, Fr)>| terms.sort_by_key(|(wire, _)| *wire);
+        for (i, (a, b, _)) in r1cs.constraints.iter().enumerate() {
+            for (name, lc, row) in [("A", a, &matrices.a[i]), ("B", b, &matrices.b[i])] {
+                let mut expected = lc.clone();
+                let mut actual: Vec<_> = row.iter().map(|(coeff, wire)| (*wire, *coeff)).collect();
+                sorted(&mut expected);
+                sorted(&mut actual);
+                if expected != actual {
+                    return Err(format!(
+                        "zkey and r1cs differ in the {} terms of constraint {}",
+                        name, i
+                    ));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Write `params` for `r1cs` as a `.zkey`, with the phase 2 transcript `mpc`
+/// the parameters came out of
+pub fn write_zkey<W: Write>(
+    writer: W,
+    params: &ProvingKey<Bn254>,
+    r1cs: &R1CS<Bn254>,
+    mpc: &MpcParams,
+) -> Result<(), String> {
+    let num_public = r1cs.num_inputs - 1;
+    let num_vars = r1cs.num_variables;
+    let domain_size = required_domain_size(r1cs)?;
+
+    if params.vk.gamma_abc_g1.len() != r1cs.num_inputs
+        || params.a_query.len() != num_vars
+        || params.b_g1_query.len() != num_vars
+        || params.b_g2_query.len() != num_vars
+        || params.l_query.len() != num_vars - r1cs.num_inputs
+    {
+        return Err("proving key doesn't match the r1cs".to_string());
+    }
+    if params.h_query.len() != domain_size {
+        return Err(format!(
+            "{} H points don't match a domain of {}",
+            params.h_query.len(),
+            domain_size
+        ));
+    }
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&(FIELD_SIZE as u32).to_le_bytes());
+    write_bigint(&mut header, &<Fq as PrimeField>::Params::MODULUS);
+    header.extend_from_slice(&(FIELD_SIZE as u32).to_le_bytes());
+    write_bigint(&mut header, &<Fr as PrimeField>::Params::MODULUS);
+    header.extend_from_slice(&(num_vars as u32).to_le_bytes());
+    header.extend_from_slice(&(num_public as u32).to_le_bytes());
+    header.extend_from_slice(&(domain_size as u32).to_le_bytes());
+    write_g1(&mut header, &params.vk.alpha_g1);
+    write_g1(&mut header, &params.beta_g1);
+    write_g2(&mut header, &params.vk.beta_g2);
+    write_g2(&mut header, &params.vk.gamma_g2);
+    write_g1(&mut header, &params.delta_g1);
+    write_g2(&mut header, &params.vk.delta_g2);
+
+    let g1s = |points: &[G1Affine]| {
+        let mut bytes = Vec::new();
+        points.iter().for_each(|p| write_g1(&mut bytes, p));
+        bytes
+    };
+
+    let mut b2 = Vec::new();
+    params.b_g2_query.iter().for_each(|p| write_g2(&mut b2, p));
+
+    let sections = [
+        (SECTION_HEADER, PROTOCOL_GROTH16.to_le_bytes().to_vec()),
+        (SECTION_GROTH_HEADER, header),
+        (SECTION_IC, g1s(&params.vk.gamma_abc_g1)),
+        (SECTION_COEFFS, coefficients(r1cs)),
+        (SECTION_A, g1s(&params.a_query)),
+        (SECTION_B1, g1s(&params.b_g1_query)),
+        (SECTION_B2, b2),
+        (SECTION_C, g1s(&params.l_query)),
+        (SECTION_H, g1s(&params.h_query)),
+        (SECTION_CONTRIBUTIONS, mpc.to_bytes()?),
+    ];
+
+    write_bin(writer, MAGIC, VERSION, &sections)
+}
+
+/// The phase 2 transcript of a `.zkey`, `read_zkey` only reads the key
+pub fn read_mpc_params<R: Read + Seek>(reader: R) -> Result<MpcParams, String> {
+    let mut file = BinReader::new(reader, MAGIC)?;
+    MpcParams::from_bytes(&file.read_section(SECTION_CONTRIBUTIONS)?)
+}
+
+/// The A and B terms of every constraint, followed by one A term for each
+/// public input and the `one` wire, the way ark and snarkjs bind the inputs
+fn coefficients(r1cs: &R1CS<Bn254>) -> Vec<u8> {
+    let num_constraints = r1cs.constraints.len();
+    let mut terms = Vec::new();
+    for (i, (a, b, _)) in r1cs.constraints.iter().enumerate() {
+        terms.extend(a.iter().map(|(wire, coeff)| (0u32, i, *wire, *coeff)));
+        terms.extend(b.iter().map(|(wire, coeff)| (1u32, i, *wire, *coeff)));
+    }
+    terms.extend((0..r1cs.num_inputs).map(|i| (0u32, num_constraints + i, i, Fr::from(1u32))));
+
+    let mut bytes = Vec::with_capacity(4 + terms.len() * (12 + FIELD_SIZE));
+    bytes.extend_from_slice(&(terms.len() as u32).to_le_bytes());
+    for (matrix, constraint, wire, coeff) in terms {
+        bytes.extend_from_slice(&matrix.to_le_bytes());
+        bytes.extend_from_slice(&(constraint as u32).to_le_bytes());
+        bytes.extend_from_slice(&(wire as u32).to_le_bytes());
+        // snarkjs stores the coefficients multiplied by R twice
+        write_bigint(&mut bytes, &Fr::from_repr(coeff.0).unwrap().0);
+    }
+
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use ark_bn254::{Bn254, Fr, G1Affine};
+    use ark_circom::circom::{R1CSFile, R1CS};
+    use ark_circom::{read_zkey, CircomReduction};
+    use ark_ff::Zero;
+    use ark_groth16::{
+        create_random_proof, create_random_proof_with_reduction, prepare_verifying_key,
+        verify_proof,
+    };
+    use ark_std::rand::{rngs::StdRng, SeedableRng};
+
+    use super::{
+        check_zkey, read_mpc_params, write_zkey, ZkeyCheck, MAGIC, SECTION_CONTRIBUTIONS,
+        SECTION_HEADER,
+    };
+    use crate::binfile::BinReader;
+    use crate::ceremony::Phase2;
+    use crate::circuit::StorageCircuit;
+
+    #[test]
+    fn test_zkey_roundtrip() {
+        let mut rng = StdRng::seed_from_u64(42);
+
+        // x * y = z with public z
+        let one = Fr::from(1u32);
+        let r1cs = R1CS::<Bn254> {
+            num_inputs: 2,
+            num_aux: 2,
+            num_variables: 4,
+            constraints: vec![(vec![(2, one)], vec![(3, one)], vec![(1, one)])],
+            wire_mapping: None,
+        };
+        let (params, mpc) = Phase2::dev(&r1cs, &mut rng).unwrap().into_parts();
+
+        let mut zkey = Vec::new();
+        write_zkey(&mut zkey, &params, &r1cs, &mpc).unwrap();
+        let (read, matrices) = read_zkey(&mut Cursor::new(&zkey)).unwrap();
+        check_zkey(&read, Some(&matrices), &r1cs, ZkeyCheck::Strict).unwrap();
+        check_zkey(&params, None, &r1cs, ZkeyCheck::Strict).unwrap();
+        assert_eq!(read_mpc_params(Cursor::new(&zkey)).unwrap(), mpc);
+
+        assert_eq!(read.vk, params.vk);
+        assert_eq!(read.beta_g1, params.beta_g1);
+        assert_eq!(read.delta_g1, params.delta_g1);
+        assert_eq!(read.a_query, params.a_query);
+        assert_eq!(read.b_g1_query, params.b_g1_query);
+        assert_eq!(read.b_g2_query, params.b_g2_query);
+        assert_eq!(read.l_query, params.l_query);
+        assert_eq!(read.h_query, params.h_query);
+
+        // keys read back write the same file
+        let mut again = Vec::new();
+        write_zkey(&mut again, &read, &r1cs, &mpc).unwrap();
+        assert_eq!(again, zkey);
+
+        let witness: Vec<Fr> = [1u32, 12, 3, 4].into_iter().map(Fr::from).collect();
+        let prove = |params| {
+            let circuit = StorageCircuit::new(&r1cs, witness.clone());
+            let mut rng = StdRng::seed_from_u64(7);
+            create_random_proof_with_reduction::<_, _, _, CircomReduction>(
+                circuit, params, &mut rng,
+            )
+            .unwrap()
+        };
+        let proof = prove(&read);
+        assert_eq!(proof, prove(&params));
+        let pvk = prepare_verifying_key(&read.vk);
+        assert!(verify_proof(&pvk, &proof, &witness[1..2]).unwrap());
+    }
+
+    #[test]
+    fn test_check_zkey() {
+        let mut rng = StdRng::seed_from_u64(42);
+
+        // x * y = z with public z, and x * x = y with public y and z
+        let one = Fr::from(1u32);
+        let product = R1CS::<Bn254> {
+            num_inputs: 2,
+            num_aux: 2,
+            num_variables: 4,
+            constraints: vec![(vec![(2, one)], vec![(3, one)], vec![(1, one)])],
+            wire_mapping: None,
+        };
+        let square = R1CS::<Bn254> {
+            num_inputs: 3,
+            num_aux: 1,
+            num_variables: 4,
+            constraints: vec![(vec![(3, one)], vec![(3, one)], vec![(1, one)])],
+            wire_mapping: None,
+        };
+        let phase2 = Phase2::dev(&product, &mut rng).unwrap();
+        let mut zkey = Vec::new();
+        phase2.write_zkey(&mut zkey, &product).unwrap();
+        let (params, matrices) = read_zkey(&mut Cursor::new(&zkey)).unwrap();
+
+        let err = check_zkey(&params, Some(&matrices), &square, ZkeyCheck::Shape).unwrap_err();
+        assert!(err.contains("public inputs"), "{}", err);
+
+        // same shape, different constraint
+        let mut other = product.clone();
+        other.constraints[0].1 = vec![(2, one)];
+        check_zkey(&params, Some(&matrices), &other, ZkeyCheck::Shape).unwrap();
+        let err = check_zkey(&params, Some(&matrices), &other, ZkeyCheck::Strict).unwrap_err();
+        assert!(err.contains("B terms of constraint 0"), "{}", err);
+
+        let mut longer = product.clone();
+        longer.constraints.push(product.constraints[0].clone());
+        let err = check_zkey(&params, Some(&matrices), &longer, ZkeyCheck::Shape).unwrap_err();
+        assert!(err.contains("constraints"), "{}", err);
+
+        // the H points of ark's reduction, as earlier versions wrote them
+        let mut ark = params.clone();
+        *ark.h_query.last_mut().unwrap() = G1Affine::zero();
+        let err = check_zkey(&ark, None, &product, ZkeyCheck::Shape).unwrap_err();
+        assert!(err.contains("ark's reduction"), "{}", err);
+        ark.h_query.pop();
+        let err = check_zkey(&ark, None, &product, ZkeyCheck::Shape).unwrap_err();
+        assert!(err.contains("H points"), "{}", err);
+    }
+
+    #[test]
+    fn test_snarkjs_zkey() {
+        // `snarkjs zkey new` of mycircuit.circom, a * b = c with public c
+        let r1cs: R1CS<Bn254> =
+            R1CSFile::<Bn254>::new(&include_bytes!("../tests/snarkjs/mycircuit.r1cs")[..])
+                .unwrap()
+                .into();
+        let zkey = include_bytes!("../tests/snarkjs/mycircuit.zkey");
+        let (params, matrices) = read_zkey(&mut Cursor::new(zkey)).unwrap();
+        check_zkey(&params, Some(&matrices), &r1cs, ZkeyCheck::Strict).unwrap();
+        let mpc = read_mpc_params(Cursor::new(zkey)).unwrap();
+
+        let mut written = Vec::new();
+        write_zkey(&mut written, &params, &r1cs, &mpc).unwrap();
+        let mut expected = BinReader::new(Cursor::new(zkey), MAGIC).unwrap();
+        let mut written = BinReader::new(Cursor::new(written), MAGIC).unwrap();
+        for section in SECTION_HEADER..=SECTION_CONTRIBUTIONS {
+            assert_eq!(
+                written.read_section(section).unwrap(),
+                expected.read_section(section).unwrap(),
+                "section {}",
+                section
+            );
+        }
+
+        let witness: Vec<Fr> = [1u32, 33, 3, 11].into_iter().map(Fr::from).collect();
+        let circuit = || StorageCircuit::new(&r1cs, witness.clone());
+        let mut rng = StdRng::seed_from_u64(42);
+        let pvk = prepare_verifying_key(&params.vk);
+        let proof = create_random_proof_with_reduction::<_, _, _, CircomReduction>(
+            circuit(),
+            &params,
+            &mut rng,
+        )
+        .unwrap();
+        assert!(verify_proof(&pvk, &proof, &witness[1..2]).unwrap());
+
+        // ark's own reduction doesn't fit the H points of snarkjs
+        let proof = create_random_proof(circuit(), &params, &mut rng).unwrap();
+        assert!(!verify_proof(&pvk, &proof, &witness[1..2]).unwrap());
+    }
+}
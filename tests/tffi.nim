@@ -18,7 +18,7 @@ suite "storage proofs ffi":
       r1cs_buff = unsafeBufferPath(r1csPath)
       wasm_buff = unsafeBufferPath(wasmPath)
 
-    let storage_ctx = init_storage_proofs(r1cs_buff, wasm_buff, nil)
+    let storage_ctx = init_storage_proofs(r1cs_buff, wasm_buff, nil, 0)
 
     echo "storage_ctx: ", storage_ctx.repr
     check storage_ctx != nil